@@ -0,0 +1,47 @@
+-- Opens and clicks recorded for newsletter issues, partitioned by month
+create table "engagement_event"
+(
+    id            uuid        not null default gen_random_uuid(),
+    issue_id      uuid        not null,
+    subscriber_id uuid        not null references subscriber (id) on delete cascade,
+    kind          text        not null check (kind in ('open', 'click')),
+    url           text,
+    occurred_at   timestamptz not null default now(),
+    primary key (id, occurred_at)
+) partition by range (occurred_at);
+
+-- Catch-all partition so inserts never fail for a month that has no partition yet
+create table "engagement_event_default" partition of "engagement_event" default;
+
+create index "engagement_event_issue_idx" on "engagement_event" (issue_id, kind);
+
+-- Creates the partition of `engagement_event` holding the month of `month`,
+-- moving over the rows the default partition caught for it. Does nothing when
+-- the partition exists.
+create function create_engagement_event_partition(month timestamptz) returns void
+    language plpgsql as
+$$
+declare
+    starts_at  timestamptz := date_trunc('month', month, 'UTC');
+    ends_at    timestamptz := (starts_at at time zone 'UTC' + interval '1 month') at time zone 'UTC';
+    table_name text        := 'engagement_event_' || to_char(starts_at at time zone 'UTC', 'YYYY_MM');
+begin
+    if to_regclass(table_name) is not null then
+        return;
+    end if;
+    execute format('create table %I (like engagement_event including defaults including constraints)',
+                   table_name);
+    execute format('with moved as (
+                        delete from engagement_event_default
+                        where occurred_at >= $1 and occurred_at < $2
+                        returning *
+                    )
+                    insert into %I select * from moved', table_name) using starts_at, ends_at;
+    execute format('alter table engagement_event attach partition %I for values from (%L) to (%L)',
+                   table_name, starts_at, ends_at);
+end
+$$;
+
+-- The sweeper keeps creating the next month's partition ahead of time
+select create_engagement_event_partition(now());
+select create_engagement_event_partition(now() + interval '1 month');
@@ -0,0 +1,21 @@
+-- One row per send of an issue, written by the sending path
+create table "issue_send"
+(
+    issue_id    uuid primary key,
+    started_at  timestamptz not null default now(),
+    finished_at timestamptz
+);
+
+-- Delivery status of an issue for each recipient
+create table "issue_delivery"
+(
+    issue_id         uuid        not null references issue_send (issue_id) on delete cascade,
+    subscriber_id    uuid        not null references subscriber (id) on delete cascade,
+    subscriber_email text        not null,
+    status           text        not null default 'queued'
+        check (status in ('queued', 'sent', 'failed', 'bounced', 'suppressed')),
+    last_error       text,
+    attempts         integer     not null default 0,
+    updated_at       timestamptz not null default now(),
+    primary key (issue_id, subscriber_id)
+);
@@ -0,0 +1,23 @@
+-- Queue and log of the lifecycle events delivered to webhook endpoints
+create table "webhook_delivery"
+(
+    id               uuid primary key     default gen_random_uuid(),
+    event_id         uuid        not null,
+    event_type       text        not null,
+    endpoint_url     text        not null,
+    payload          jsonb       not null,
+    -- W3C trace context of the request that raised the event
+    traceparent      text,
+    status           text        not null default 'pending'
+        check (status in ('pending', 'delivered', 'failed')),
+    attempts         integer     not null default 0,
+    next_attempt_at  timestamptz not null default now(),
+    last_status_code integer,
+    last_error       text,
+    created_at       timestamptz not null default now(),
+    updated_at       timestamptz not null default now()
+);
+
+create index "webhook_delivery_event_idx" on "webhook_delivery" (event_id);
+create index "webhook_delivery_due_idx" on "webhook_delivery" (next_attempt_at)
+    where status = 'pending';
@@ -0,0 +1,10 @@
+-- Fixed-window counters shared by every replica; losing them on a crash is harmless
+create unlogged table "rate_limit_bucket"
+(
+    key          text primary key,
+    window_start timestamptz not null,
+    hits         integer     not null
+);
+
+-- Expired windows are swept periodically
+create index rate_limit_bucket_window_start on rate_limit_bucket (window_start);
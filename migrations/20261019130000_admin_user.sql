@@ -0,0 +1,10 @@
+-- Operators allowed into the admin area, created with `z2p_axum create-admin`
+create table "admin_user"
+(
+    id            uuid primary key     default gen_random_uuid(),
+    username      text unique not null,
+    password_hash text        not null,
+    -- What the user may do in the dashboard, see `Role` for the matrix
+    role          text        not null check (role in ('admin', 'editor', 'viewer')),
+    created_at    timestamptz not null default now()
+);
@@ -0,0 +1,6 @@
+-- Signups from the hosted pages wait for the subscriber to confirm their
+-- address. Subscribers created through the API are trusted as confirmed.
+alter table "subscriber"
+    add column status text not null default 'confirmed'
+        check (status in ('pending', 'confirmed', 'unsubscribed')),
+    add column status_changed_at timestamptz not null default now();
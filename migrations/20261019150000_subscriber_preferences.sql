@@ -0,0 +1,7 @@
+-- Managed by subscribers from the preference centre
+alter table "subscriber"
+    -- No issue is sent before this time
+    add column paused_until timestamptz,
+    -- `weekly` and `monthly` receive at most one issue per week or month
+    add column frequency    text not null default 'every_issue'
+        check (frequency in ('every_issue', 'weekly', 'monthly'));
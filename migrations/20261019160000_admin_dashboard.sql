@@ -0,0 +1,26 @@
+-- Browser sessions of the admin dashboard, only started by a successful
+-- login. The login form takes its CSRF token from a signed cookie.
+create table "admin_session"
+(
+    -- SHA-256 of the token in the cookie, so a database leak does not leak sessions
+    token_hash    text primary key,
+    admin_user_id uuid        not null references admin_user (id) on delete cascade,
+    csrf_token    text        not null,
+    -- Message shown once on the next page
+    flash         text,
+    created_at    timestamptz not null default now(),
+    expires_at    timestamptz not null
+);
+
+create index admin_session_expires_at on admin_session (expires_at);
+
+-- Issues composed in the dashboard
+create table "newsletter_issue"
+(
+    id           uuid primary key     default gen_random_uuid(),
+    title        text        not null,
+    text_content text        not null,
+    html_content text        not null,
+    created_by   uuid        references admin_user (id) on delete set null,
+    created_at   timestamptz not null default now()
+);
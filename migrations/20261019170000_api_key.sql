@@ -0,0 +1,19 @@
+-- Keys used by integrations, sent as `Authorization: Bearer <key>`
+create table "api_key"
+(
+    id           uuid primary key     default gen_random_uuid(),
+    name         text        not null,
+    -- Visible part of the key, identifies it in logs and listings
+    prefix       text unique not null,
+    -- SHA-256 of the whole key, which is only shown once
+    secret_hash  text        not null,
+    scopes       text[]      not null,
+    created_at   timestamptz not null default now(),
+    last_used_at timestamptz,
+    expires_at   timestamptz,
+    revoked_at   timestamptz
+);
+
+-- Issues published through the API record the key instead of an admin user
+alter table newsletter_issue
+    add column created_by_api_key uuid references api_key (id) on delete set null;
@@ -0,0 +1,133 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHasher, PasswordVerifier, phc::PasswordHash},
+};
+use std::sync::LazyLock;
+
+use miette::{IntoDiagnostic, Result, miette};
+use secrecy::{ExposeSecret, SecretBox};
+use sqlx::{PgPool, types::Uuid};
+
+use crate::roles::Role;
+
+/// Passwords shorter than this are refused
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+
+/// Verified when the username does not exist, so failed logins take as long
+/// whether or not it does
+static UNKNOWN_USER_HASH: LazyLock<String> = LazyLock::new(|| {
+    hash_password(&SecretBox::new(Box::new("unknown-user".to_string())))
+        .expect("Hashing a constant password succeeds")
+});
+
+/// Hashes `password` with Argon2id and a random salt, as a PHC string
+///
+/// # Errors
+///
+/// If hashing fails
+pub fn hash_password(password: &SecretBox<String>) -> Result<String> {
+    Argon2::default()
+        .hash_password(password.expose_secret().as_bytes())
+        .map(|hash| hash.to_string())
+        .map_err(|e| miette!("Failed to hash the password: {e}"))
+}
+
+/// Checks `password` against a PHC string produced by `hash_password`
+#[must_use]
+pub fn verify_password(password: &SecretBox<String>, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.expose_secret().as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Returns the id of the admin user `username` when `password` is theirs
+///
+/// # Errors
+///
+/// - Database error
+/// - The password check panicked
+#[tracing::instrument(name = "Checking admin credentials", skip(pool, password))]
+pub async fn check_credentials(
+    pool: &PgPool,
+    username: &str,
+    password: SecretBox<String>,
+) -> Result<Option<Uuid>> {
+    let user = sqlx::query!(
+        "select id, password_hash from admin_user where username = $1",
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| miette!("Failed to load the admin user: {e}"))?;
+    let (id, password_hash) = match user {
+        Some(user) => (Some(user.id), user.password_hash),
+        None => (None, UNKNOWN_USER_HASH.clone()),
+    };
+    // Argon2 is slow on purpose, keep it off the async workers
+    let verified = tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+        .await
+        .into_diagnostic()?;
+    Ok(id.filter(|_| verified))
+}
+
+/// Creates an admin user holding `role` and returns its id
+///
+/// # Errors
+///
+/// - The password is too short
+/// - The username is taken
+#[tracing::instrument(name = "Creating an admin user", skip(pool, password))]
+pub async fn create_admin(
+    pool: &PgPool,
+    username: &str,
+    password: &SecretBox<String>,
+    role: Role,
+) -> Result<Uuid> {
+    if password.expose_secret().chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(miette!(
+            "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        ));
+    }
+    let password_hash = hash_password(password)?;
+    let id = sqlx::query_scalar!(
+        "insert into admin_user (username, password_hash, role) values ($1, $2, $3) returning id",
+        username,
+        password_hash,
+        role.as_str(),
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db) if db.is_unique_violation() => miette!("`{username}` already exists"),
+        _ => miette!("Failed to create the admin user: {e}"),
+    })?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretBox;
+
+    use super::{hash_password, verify_password};
+
+    fn secret(value: &str) -> SecretBox<String> {
+        SecretBox::new(Box::new(value.to_string()))
+    }
+
+    #[test]
+    fn hashed_password_is_verified() {
+        let hash = hash_password(&secret("correct horse battery staple")).unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password(
+            &secret("correct horse battery staple"),
+            &hash
+        ));
+        assert!(!verify_password(&secret("Tr0ub4dor&3"), &hash));
+        assert!(!verify_password(
+            &secret("correct horse battery staple"),
+            "not-a-hash"
+        ));
+    }
+}
@@ -0,0 +1,368 @@
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, types::Uuid};
+use utoipa::ToSchema;
+
+use crate::problem::Problem;
+use crate::startup::AppState;
+
+/// Start of every key, so secret scanners can recognise them
+const KEY_PREFIX: &str = "nl_";
+
+/// What an API key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    #[serde(rename = "subscribers:read")]
+    SubscribersRead,
+    #[serde(rename = "subscribers:write")]
+    SubscribersWrite,
+    #[serde(rename = "issues:read")]
+    IssuesRead,
+    #[serde(rename = "issues:publish")]
+    IssuesPublish,
+    #[serde(rename = "api_keys:manage")]
+    ApiKeysManage,
+    /// Scraping `/metrics`
+    #[serde(rename = "metrics:read")]
+    MetricsRead,
+}
+
+impl Scope {
+    pub const ALL: [Self; 6] = [
+        Self::SubscribersRead,
+        Self::SubscribersWrite,
+        Self::IssuesRead,
+        Self::IssuesPublish,
+        Self::ApiKeysManage,
+        Self::MetricsRead,
+    ];
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::SubscribersRead => "subscribers:read",
+            Self::SubscribersWrite => "subscribers:write",
+            Self::IssuesRead => "issues:read",
+            Self::IssuesPublish => "issues:publish",
+            Self::ApiKeysManage => "api_keys:manage",
+            Self::MetricsRead => "metrics:read",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == value)
+            .ok_or_else(|| {
+                let known: Vec<_> = Self::ALL.iter().map(|scope| scope.as_str()).collect();
+                format!(
+                    "unknown scope `{value}`, expected one of {}",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// A key just created, the only time its secret is known
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Sent as `Authorization: Bearer <key>`, it cannot be shown again
+    pub key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeySummary {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Creates a key allowed `scopes` until `expires_at`, or until revoked when
+/// not set
+///
+/// # Errors
+///
+/// Database error
+#[tracing::instrument(name = "Creating an API key", skip(pool))]
+pub async fn create_api_key(
+    pool: &PgPool,
+    name: &str,
+    scopes: &[Scope],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<CreatedApiKey, sqlx::Error> {
+    let prefix = format!("{KEY_PREFIX}{}", hex::encode(rand::random::<[u8; 4]>()));
+    let key = format!("{prefix}_{}", hex::encode(rand::random::<[u8; 32]>()));
+    let mut scopes = scopes.to_vec();
+    scopes.sort_by_key(|scope| scope.as_str());
+    scopes.dedup();
+    let stored: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+    let id = sqlx::query_scalar!(
+        r#"
+        insert into api_key (name, prefix, secret_hash, scopes, expires_at)
+        values ($1, $2, $3, $4, $5)
+        returning id
+        "#,
+        name,
+        prefix,
+        hash(&key),
+        &stored as &[&str],
+        expires_at,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(CreatedApiKey {
+        id,
+        name: name.to_string(),
+        prefix,
+        scopes,
+        expires_at,
+        key,
+    })
+}
+
+/// Every key, revoked and expired ones included, newest first
+///
+/// # Errors
+///
+/// Database error
+pub async fn list_api_keys(pool: &PgPool) -> Result<Vec<ApiKeySummary>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        select id, name, prefix, scopes, created_at, last_used_at, expires_at, revoked_at
+        from api_key
+        order by created_at desc
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ApiKeySummary {
+            id: row.id,
+            name: row.name,
+            prefix: row.prefix,
+            scopes: parse_scopes(&row.scopes),
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            expires_at: row.expires_at,
+            revoked_at: row.revoked_at,
+        })
+        .collect())
+}
+
+/// Returns whether a key that was not revoked yet was found
+///
+/// # Errors
+///
+/// Database error
+#[tracing::instrument(name = "Revoking an API key", skip(pool))]
+pub async fn revoke_api_key(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let revoked = sqlx::query!(
+        "update api_key set revoked_at = now() where id = $1 and revoked_at is null",
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(revoked.rows_affected() == 1)
+}
+
+/// A scope as a type, for `ApiKey`
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+/// Marker types of the scopes, named like the variants of `Scope`
+pub mod scopes {
+    macro_rules! scopes {
+        ($($scope:ident),*) => {$(
+            pub struct $scope;
+
+            impl super::RequiredScope for $scope {
+                const SCOPE: super::Scope = super::Scope::$scope;
+            }
+        )*};
+    }
+
+    scopes!(
+        SubscribersRead,
+        SubscribersWrite,
+        IssuesRead,
+        IssuesPublish,
+        ApiKeysManage,
+        MetricsRead
+    );
+}
+
+/// A valid API key holding the scope `S`, sent as `Authorization: Bearer`
+pub struct ApiKey<S> {
+    pub id: Uuid,
+    pub prefix: String,
+    scope: PhantomData<fn() -> S>,
+}
+
+#[derive(Debug)]
+pub enum ApiKeyRejection {
+    Missing,
+    /// Unknown, expired or revoked
+    Invalid,
+    MissingScope(Scope),
+    Unavailable,
+}
+
+impl IntoResponse for ApiKeyRejection {
+    fn into_response(self) -> Response {
+        let (challenge, body) = match self {
+            Self::Missing => (
+                "Bearer".to_string(),
+                (StatusCode::UNAUTHORIZED, "Missing API key").into_response(),
+            ),
+            Self::Invalid => (
+                r#"Bearer error="invalid_token""#.to_string(),
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid, expired or revoked API key",
+                )
+                    .into_response(),
+            ),
+            Self::MissingScope(scope) => (
+                format!(
+                    r#"Bearer error="insufficient_scope", scope="{}""#,
+                    scope.as_str()
+                ),
+                Problem::forbidden(format!("The API key lacks the `{}` scope", scope.as_str()))
+                    .into_response(),
+            ),
+            Self::Unavailable => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        };
+        let challenge = HeaderValue::from_str(&challenge).expect("Scopes are valid header values");
+        ([(header::WWW_AUTHENTICATE, challenge)], body).into_response()
+    }
+}
+
+#[async_trait]
+impl<S: RequiredScope> FromRequestParts<AppState> for ApiKey<S> {
+    type Rejection = ApiKeyRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, ApiKeyRejection> {
+        let key = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiKeyRejection::Missing)?;
+        let Some(found) = authenticate(&state.pool, key.trim()).await.map_err(|e| {
+            tracing::error!("Failed to check the API key: {:?}", e);
+            ApiKeyRejection::Unavailable
+        })?
+        else {
+            tracing::warn!("Refused an invalid API key");
+            return Err(ApiKeyRejection::Invalid);
+        };
+        if !found.scopes.contains(&S::SCOPE) {
+            tracing::warn!(
+                prefix = found.prefix,
+                scope = S::SCOPE.as_str(),
+                "API key lacks the scope"
+            );
+            return Err(ApiKeyRejection::MissingScope(S::SCOPE));
+        }
+        Ok(Self {
+            id: found.id,
+            prefix: found.prefix,
+            scope: PhantomData,
+        })
+    }
+}
+
+struct FoundKey {
+    id: Uuid,
+    prefix: String,
+    scopes: Vec<Scope>,
+}
+
+/// Finds the usable key `key` and records its use
+async fn authenticate(pool: &PgPool, key: &str) -> Result<Option<FoundKey>, sqlx::Error> {
+    let Some((prefix, _secret)) = key.rsplit_once('_') else {
+        return Ok(None);
+    };
+    let row = sqlx::query!(
+        r#"
+        update api_key set last_used_at = now()
+        where prefix = $1
+            and secret_hash = $2
+            and revoked_at is null
+            and (expires_at is null or expires_at > now())
+        returning id, prefix, scopes
+        "#,
+        prefix,
+        hash(key),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|row| FoundKey {
+        id: row.id,
+        prefix: row.prefix,
+        scopes: parse_scopes(&row.scopes),
+    }))
+}
+
+/// Scopes no longer known are dropped
+fn parse_scopes(scopes: &[String]) -> Vec<Scope> {
+    scopes
+        .iter()
+        .filter_map(|scope| scope.parse().ok())
+        .collect()
+}
+
+fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scope;
+
+    #[test]
+    fn scopes_parse_from_their_name() {
+        for scope in Scope::ALL {
+            assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+            assert_eq!(
+                serde_json::to_value(scope).unwrap(),
+                serde_json::Value::from(scope.as_str())
+            );
+        }
+        assert!(
+            "subscribers:delete"
+                .parse::<Scope>()
+                .unwrap_err()
+                .contains("subscribers:read")
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use std::{future::Future, net::IpAddr, pin::Pin, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use serde::Deserialize;
+use sha2::Sha256;
+use snafu::{ResultExt, Snafu};
+
+use crate::configuration::{ChallengeProvider, Settings};
+use crate::domain::CreateSubscriber;
+use crate::telemetry::trace_headers;
+
+const TURNSTILE_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+const HCAPTCHA_VERIFY_URL: &str = "https://api.hcaptcha.com/siteverify";
+
+/// The challenge provider could not be reached or answered unexpectedly
+#[derive(Debug, Snafu)]
+#[snafu(display("Challenge provider request failed"))]
+pub struct ProviderError {
+    source: reqwest::Error,
+}
+
+pub type VerifyFuture<'a> = Pin<Box<dyn Future<Output = Result<bool, ProviderError>> + Send + 'a>>;
+
+/// Verifies the token a challenge widget (Turnstile, hCaptcha, ...) attached
+/// to a signup.
+pub trait ChallengeVerifier: Send + Sync {
+    /// Resolves to `true` when the provider accepts `token`.
+    fn verify<'a>(&'a self, token: &'a str, remote_ip: Option<IpAddr>) -> VerifyFuture<'a>;
+}
+
+/// Accepts a single, configured token so signups work without a provider.
+pub struct StubVerifier {
+    token: SecretBox<String>,
+}
+
+impl StubVerifier {
+    #[must_use]
+    pub const fn new(token: SecretBox<String>) -> Self {
+        Self { token }
+    }
+}
+
+impl ChallengeVerifier for StubVerifier {
+    fn verify<'a>(&'a self, token: &'a str, _remote_ip: Option<IpAddr>) -> VerifyFuture<'a> {
+        Box::pin(async move { Ok(token == self.token.expose_secret().as_str()) })
+    }
+}
+
+/// Verifies tokens against a `siteverify` endpoint, the API shared by
+/// Turnstile and hCaptcha.
+pub struct SiteVerifyVerifier {
+    client: reqwest::Client,
+    verify_url: String,
+    secret_key: SecretBox<String>,
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+impl SiteVerifyVerifier {
+    /// # Panics
+    ///
+    /// If the HTTP client cannot be built
+    #[must_use]
+    pub fn new(verify_url: String, secret_key: SecretBox<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Failed to build challenge HTTP client");
+        Self {
+            client,
+            verify_url,
+            secret_key,
+        }
+    }
+}
+
+impl ChallengeVerifier for SiteVerifyVerifier {
+    fn verify<'a>(&'a self, token: &'a str, remote_ip: Option<IpAddr>) -> VerifyFuture<'a> {
+        Box::pin(async move {
+            let mut form = vec![
+                ("secret", self.secret_key.expose_secret().clone()),
+                ("response", token.to_string()),
+            ];
+            if let Some(remote_ip) = remote_ip {
+                form.push(("remoteip", remote_ip.to_string()));
+            }
+            let response = self
+                .client
+                .post(&self.verify_url)
+                .headers(trace_headers())
+                .form(&form)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .context(ProviderSnafu)?
+                .json::<SiteVerifyResponse>()
+                .await
+                .context(ProviderSnafu)?;
+            Ok(response.success)
+        })
+    }
+}
+
+/// Why a signup was treated as automated
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+    Honeypot,
+    TooFast,
+    /// The render time was not issued by the server, or is in the future
+    InvalidRenderTime,
+    MissingChallenge,
+    FailedChallenge,
+}
+
+impl Rejection {
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Honeypot => "honeypot",
+            Self::TooFast => "too_fast",
+            Self::InvalidRenderTime => "invalid_rendered_at",
+            Self::MissingChallenge => "missing_challenge",
+            Self::FailedChallenge => "failed_challenge",
+        }
+    }
+}
+
+/// Honeypot, submission timing and challenge checks applied to signups.
+#[derive(Clone)]
+pub struct BotProtection {
+    honeypot: bool,
+    min_submit_time: Duration,
+    verifier: Option<Arc<dyn ChallengeVerifier>>,
+    /// Signs the render times handed out with the signup form
+    render_key: Arc<SecretBox<String>>,
+}
+
+impl BotProtection {
+    #[must_use]
+    pub fn from_settings(settings: &Settings) -> Self {
+        let render_key = settings.application.signing_key("rendered-at");
+        let settings = &settings.bot_protection;
+        let secret_key = || {
+            SecretBox::new(Box::new(
+                settings.challenge.secret_key.expose_secret().clone(),
+            ))
+        };
+        let verify_url = |default: &str| {
+            settings
+                .challenge
+                .verify_url
+                .clone()
+                .unwrap_or_else(|| default.to_string())
+        };
+        let verifier: Option<Arc<dyn ChallengeVerifier>> = match settings.challenge.provider {
+            ChallengeProvider::None => None,
+            ChallengeProvider::Stub => Some(Arc::new(StubVerifier::new(secret_key()))),
+            ChallengeProvider::Turnstile => Some(Arc::new(SiteVerifyVerifier::new(
+                verify_url(TURNSTILE_VERIFY_URL),
+                secret_key(),
+            ))),
+            ChallengeProvider::Hcaptcha => Some(Arc::new(SiteVerifyVerifier::new(
+                verify_url(HCAPTCHA_VERIFY_URL),
+                secret_key(),
+            ))),
+        };
+        Self::new(
+            settings.honeypot,
+            Duration::from_secs(settings.min_submit_seconds),
+            verifier,
+            render_key,
+        )
+    }
+
+    #[must_use]
+    pub fn new(
+        honeypot: bool,
+        min_submit_time: Duration,
+        verifier: Option<Arc<dyn ChallengeVerifier>>,
+        render_key: SecretBox<String>,
+    ) -> Self {
+        Self {
+            honeypot,
+            min_submit_time,
+            verifier,
+            render_key: Arc::new(render_key),
+        }
+    }
+
+    /// The `rendered_at` value of a form rendered now: the time in
+    /// milliseconds and its signature, so clients cannot backdate it
+    #[must_use]
+    pub fn rendered_at(&self) -> String {
+        let millis = Utc::now().timestamp_millis();
+        format!("{millis}.{}", self.sign_render_time(millis))
+    }
+
+    fn sign_render_time(&self, millis: i64) -> String {
+        let mut mac = self.render_mac();
+        mac.update(millis.to_string().as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn render_mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.render_key.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+
+    /// The time a `rendered_at` value was issued at, if it was by this server
+    fn verify_render_time(&self, rendered_at: &str) -> Option<i64> {
+        let (millis, signature) = rendered_at.split_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        let mut mac = self.render_mac();
+        mac.update(millis.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        millis.parse().ok()
+    }
+
+    /// # Errors
+    ///
+    /// - The honeypot field was filled in
+    /// - The form was submitted faster than a human could, or without its render time
+    /// - The render time was not issued by this server or is in the future
+    /// - The challenge token is missing or was rejected by the provider
+    pub async fn check(
+        &self,
+        submission: &CreateSubscriber,
+        remote_ip: Option<IpAddr>,
+    ) -> Result<(), Rejection> {
+        if self.honeypot && submission.website.as_deref().is_some_and(|v| !v.is_empty()) {
+            return Err(Rejection::Honeypot);
+        }
+        if !self.min_submit_time.is_zero() {
+            let Some(rendered_at) = submission.rendered_at.as_deref() else {
+                return Err(Rejection::TooFast);
+            };
+            let Some(rendered_at) = self.verify_render_time(rendered_at) else {
+                return Err(Rejection::InvalidRenderTime);
+            };
+            let elapsed = Utc::now().timestamp_millis() - rendered_at;
+            if elapsed < 0 {
+                return Err(Rejection::InvalidRenderTime);
+            }
+            let min_millis = i64::try_from(self.min_submit_time.as_millis()).unwrap_or(i64::MAX);
+            if elapsed < min_millis {
+                return Err(Rejection::TooFast);
+            }
+        }
+        if let Some(verifier) = &self.verifier {
+            let Some(token) = submission.challenge_token.as_deref() else {
+                return Err(Rejection::MissingChallenge);
+            };
+            match verifier.verify(token, remote_ip).await {
+                Ok(true) => {}
+                Ok(false) => return Err(Rejection::FailedChallenge),
+                Err(e) => {
+                    tracing::error!("Failed to verify challenge token: {:?}", e);
+                    return Err(Rejection::FailedChallenge);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use chrono::Utc;
+    use secrecy::SecretBox;
+
+    use super::{BotProtection, ChallengeVerifier, Rejection, StubVerifier};
+    use crate::domain::{CreateSubscriber, SubscriberEmail, SubscriberName};
+
+    fn submission() -> CreateSubscriber {
+        CreateSubscriber {
+            name: SubscriberName::parse("Ursula Le Guin".to_string()).unwrap(),
+            email: SubscriberEmail::parse("ursula@domain.com".to_string()).unwrap(),
+            website: None,
+            rendered_at: None,
+            challenge_token: None,
+        }
+    }
+
+    fn protection(
+        honeypot: bool,
+        min_submit_time: Duration,
+        verifier: Option<Arc<dyn ChallengeVerifier>>,
+    ) -> BotProtection {
+        let render_key = SecretBox::new(Box::new("render-key".to_string()));
+        BotProtection::new(honeypot, min_submit_time, verifier, render_key)
+    }
+
+    fn stub_protection() -> BotProtection {
+        let verifier = StubVerifier::new(SecretBox::new(Box::new("pass".to_string())));
+        protection(true, Duration::ZERO, Some(Arc::new(verifier)))
+    }
+
+    #[tokio::test]
+    async fn filled_in_honeypot_is_rejected() {
+        let protection = protection(true, Duration::ZERO, None);
+        let mut submission = submission();
+        submission.website = Some("https://spam.example.com".to_string());
+        assert_eq!(
+            protection.check(&submission, None).await,
+            Err(Rejection::Honeypot)
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_honeypot_is_accepted() {
+        let protection = protection(true, Duration::ZERO, None);
+        let mut submission = submission();
+        submission.website = Some(String::new());
+        assert_eq!(protection.check(&submission, None).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn submissions_faster_than_the_minimum_are_rejected() {
+        let protection = protection(false, Duration::from_secs(3), None);
+        let mut submission = submission();
+        assert_eq!(
+            protection.check(&submission, None).await,
+            Err(Rejection::TooFast)
+        );
+        submission.rendered_at = Some(protection.rendered_at());
+        assert_eq!(
+            protection.check(&submission, None).await,
+            Err(Rejection::TooFast)
+        );
+        let millis = Utc::now().timestamp_millis() - 5_000;
+        submission.rendered_at = Some(format!("{millis}.{}", protection.sign_render_time(millis)));
+        assert_eq!(protection.check(&submission, None).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn forged_render_times_are_rejected() {
+        let protection = protection(false, Duration::from_secs(3), None);
+        let mut submission = submission();
+        let an_hour_ago = Utc::now().timestamp_millis() - 3_600_000;
+        let in_an_hour = Utc::now().timestamp_millis() + 3_600_000;
+        let other_key = BotProtection::new(
+            false,
+            Duration::from_secs(3),
+            None,
+            SecretBox::new(Box::new("another-key".to_string())),
+        );
+        for forged in [
+            an_hour_ago.to_string(),
+            format!("{an_hour_ago}.00"),
+            format!("{an_hour_ago}.{}", other_key.sign_render_time(an_hour_ago)),
+            format!("{in_an_hour}.{}", protection.sign_render_time(in_an_hour)),
+        ] {
+            submission.rendered_at = Some(forged);
+            assert_eq!(
+                protection.check(&submission, None).await,
+                Err(Rejection::InvalidRenderTime)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn challenge_token_is_verified() {
+        let protection = stub_protection();
+        let mut submission = submission();
+        assert_eq!(
+            protection.check(&submission, None).await,
+            Err(Rejection::MissingChallenge)
+        );
+        submission.challenge_token = Some("wrong".to_string());
+        assert_eq!(
+            protection.check(&submission, None).await,
+            Err(Rejection::FailedChallenge)
+        );
+        submission.challenge_token = Some("pass".to_string());
+        assert_eq!(protection.check(&submission, None).await, Ok(()));
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::net::{SocketAddr, TcpListener};
+use std::{path::PathBuf, str::FromStr};
+
+use clap::{Parser, Subcommand};
+use miette::{IntoDiagnostic, Result};
+use secrecy::SecretBox;
+use sqlx::{Connection, PgConnection, PgPool, migrate::Migrator};
+
+use crate::admin::create_admin;
+use crate::api_keys::{Scope, create_api_key};
+use crate::configuration::{Settings, get_configuration};
+use crate::delivery;
+use crate::email_client::EmailClient;
+use crate::maintenance::spawn_sweeper;
+use crate::roles::Role;
+use crate::startup::generate_routes;
+use crate::subscriber_csv::{export_subscribers, import_subscribers};
+use crate::subscription_links::SubscriptionLinks;
+use crate::telemetry::init_tracing_subscriber;
+use crate::tls::serve_tls;
+use crate::tracking::Tracker;
+use crate::webhooks::{self, Webhooks};
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Every subcommand reads the same configuration as the server, so they can
+/// be run from the same image.
+#[derive(Parser)]
+#[command(version, about = "Newsletter API")]
+pub struct Cli {
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Apply the pending migrations then serve the API
+    Serve,
+    /// Apply the pending migrations
+    Migrate {
+        /// List the pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Load and validate the configuration
+    CheckConfig,
+    /// Create a user for the admin area
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        /// Read from the first line of stdin when not set
+        #[arg(long, env = "ADMIN_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        /// `admin`, `editor` or `viewer`
+        #[arg(long, default_value = "admin")]
+        role: Role,
+    },
+    /// Create an API key for an integration, the key is only printed once
+    CreateApiKey {
+        #[arg(long)]
+        name: String,
+        /// e.g. `subscribers:read`, repeat for several scopes
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<Scope>,
+        /// The key never expires when not set
+        #[arg(long)]
+        expires_in_days: Option<u32>,
+    },
+    /// Bulk operations on subscribers
+    #[command(subcommand)]
+    Subscriber(SubscriberCommand),
+}
+
+#[derive(Subcommand)]
+pub enum SubscriberCommand {
+    /// Add the subscribers of a CSV file with `email` and `name` columns
+    Import { path: PathBuf },
+    /// Write every subscriber as CSV
+    Export {
+        /// Written to stdout when not set
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    /// # Errors
+    ///
+    /// If the configuration is invalid or the subcommand fails
+    pub async fn run(self) -> Result<()> {
+        let configuration = get_configuration()?;
+        match self.command.unwrap_or(Command::Serve) {
+            Command::Serve => serve(&configuration).await,
+            Command::Migrate { dry_run: false } => {
+                migrate(&configuration).await?;
+                println!("Migrations applied");
+                Ok(())
+            }
+            Command::Migrate { dry_run: true } => {
+                for (version, description) in pending_migrations(&connect(&configuration)).await? {
+                    println!("{version} {description}");
+                }
+                Ok(())
+            }
+            Command::CheckConfig => {
+                println!(
+                    "Configuration is valid, serving on {}:{} as {}",
+                    configuration.application.host,
+                    configuration.application.port,
+                    configuration.application.base_url
+                );
+                Ok(())
+            }
+            Command::CreateAdmin {
+                username,
+                password,
+                role,
+            } => {
+                let password = match password {
+                    Some(password) => password,
+                    None => read_line()?,
+                };
+                let id = create_admin(
+                    &connect(&configuration),
+                    &username,
+                    &SecretBox::new(Box::new(password)),
+                    role,
+                )
+                .await?;
+                println!("Created {} `{username}` ({id})", role.as_str());
+                Ok(())
+            }
+            Command::CreateApiKey {
+                name,
+                scopes,
+                expires_in_days,
+            } => {
+                let expires_at = expires_in_days
+                    .map(|days| chrono::Utc::now() + chrono::Duration::days(days.into()));
+                let created = create_api_key(&connect(&configuration), &name, &scopes, expires_at)
+                    .await
+                    .into_diagnostic()?;
+                eprintln!("Created API key `{name}` ({})", created.prefix);
+                println!("{}", created.key);
+                Ok(())
+            }
+            Command::Subscriber(SubscriberCommand::Import { path }) => {
+                let file = std::fs::File::open(&path).into_diagnostic()?;
+                let summary = import_subscribers(&connect(&configuration), file).await?;
+                for (line, reason) in &summary.invalid {
+                    eprintln!("line {line}: {reason}");
+                }
+                println!(
+                    "Imported {}, already subscribed {}, invalid {}",
+                    summary.imported,
+                    summary.existing,
+                    summary.invalid.len()
+                );
+                Ok(())
+            }
+            Command::Subscriber(SubscriberCommand::Export { output }) => {
+                let pool = connect(&configuration);
+                let count = match output {
+                    Some(path) => {
+                        export_subscribers(&pool, std::fs::File::create(path).into_diagnostic()?)
+                            .await?
+                    }
+                    None => export_subscribers(&pool, std::io::stdout()).await?,
+                };
+                eprintln!("Exported {count} subscribers");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The pool of every command, logging its configuration
+fn connect(configuration: &Settings) -> PgPool {
+    let pool = &configuration.database.pool;
+    tracing::info!(
+        max_connections = pool.max_connections,
+        min_connections = pool.min_connections,
+        acquire_timeout_milliseconds = pool.acquire_timeout_milliseconds,
+        idle_timeout_seconds = ?pool.idle_timeout_seconds,
+        max_lifetime_seconds = ?pool.max_lifetime_seconds,
+        statement_timeout_milliseconds = pool.statement_timeout_milliseconds,
+        "Connecting to postgres with the pool configuration"
+    );
+    configuration
+        .database
+        .pool_options()
+        .connect_lazy_with(configuration.database.with_db())
+}
+
+/// Applies the pending migrations over their own connection, without the
+/// statement timeout of the pool
+async fn migrate(configuration: &Settings) -> Result<()> {
+    let mut connection = PgConnection::connect_with(&configuration.database.for_migrations())
+        .await
+        .into_diagnostic()?;
+    MIGRATOR.run(&mut connection).await.into_diagnostic()?;
+    connection.close().await.into_diagnostic()
+}
+
+fn read_line() -> Result<String> {
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .into_diagnostic()?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Migrations not applied yet, without creating the migrations table
+async fn pending_migrations(pool: &PgPool) -> Result<Vec<(i64, String)>> {
+    let table: Option<String> = sqlx::query_scalar("select to_regclass('_sqlx_migrations')::text")
+        .fetch_one(pool)
+        .await
+        .into_diagnostic()?;
+    let applied: HashSet<i64> = if table.is_some() {
+        sqlx::query_scalar("select version from _sqlx_migrations where success")
+            .fetch_all(pool)
+            .await
+            .into_diagnostic()?
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| {
+            !migration.migration_type.is_down_migration() && !applied.contains(&migration.version)
+        })
+        .map(|migration| (migration.version, migration.description.to_string()))
+        .collect())
+}
+
+async fn serve(configuration: &Settings) -> Result<()> {
+    let _tracing = init_tracing_subscriber("info", &configuration.telemetry)?;
+    let connection_pool = connect(configuration);
+    tracing::debug!("Running DB migrations");
+    migrate(configuration).await?;
+
+    spawn_sweeper(connection_pool.clone(), configuration);
+    delivery::spawn_worker(
+        connection_pool.clone(),
+        EmailClient::from_settings(&configuration.email_client),
+        SubscriptionLinks::from_settings(configuration),
+        Tracker::from_settings(configuration),
+    );
+    webhooks::spawn_worker(Webhooks::from_settings(
+        &configuration.webhooks,
+        connection_pool.clone(),
+    ));
+    let router = generate_routes(&connection_pool, configuration);
+    let address = format!(
+        "{}:{}",
+        configuration.application.host, configuration.application.port
+    );
+    let addr = SocketAddr::from_str(&address).expect("Failed to connect to address");
+    tracing::debug!("listening on {}", addr);
+    match &configuration.application.tls {
+        Some(tls) => serve_tls(TcpListener::bind(addr).into_diagnostic()?, router, tls).await,
+        None => axum::Server::bind(&addr)
+            .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .into_diagnostic(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::{Cli, Command, SubscriberCommand};
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn serve_is_the_default() {
+        assert!(Cli::try_parse_from(["z2p_axum"]).unwrap().command.is_none());
+        assert!(matches!(
+            Cli::try_parse_from(["z2p_axum", "migrate", "--dry-run"])
+                .unwrap()
+                .command,
+            Some(Command::Migrate { dry_run: true })
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["z2p_axum", "subscriber", "export", "-o", "out.csv"])
+                .unwrap()
+                .command,
+            Some(Command::Subscriber(SubscriberCommand::Export {
+                output: Some(_)
+            }))
+        ));
+    }
+}
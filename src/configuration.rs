@@ -1,15 +1,326 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use config::ConfigBuilder;
+use config::builder::DefaultState;
+use hmac::{Hmac, Mac};
+use log::LevelFilter;
+use miette::Diagnostic;
 use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use snafu::{ResultExt, Snafu};
+use sqlx::ConnectOptions;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use url::Url;
+
+use crate::domain::SubscriberEmail;
+use crate::webhooks::SubscriberEvent;
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub tracking: TrackingSettings,
+    pub webhooks: WebhookSettings,
+    pub email_client: EmailClientSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub telemetry: TelemetrySettings,
+    pub pages: PageSettings,
+    pub admin: AdminSettings,
+}
+
+/// The admin dashboard served under `/admin`
+#[derive(serde::Deserialize)]
+pub struct AdminSettings {
+    /// How long a login lasts without activity
+    pub session_ttl_minutes: u32,
+    /// Rows shown per page when browsing subscribers
+    pub page_size: u32,
+    pub login_rate_limit: LoginRateLimitSettings,
+}
+
+/// Login attempts allowed before the password is even checked, sharing the
+/// backend and trusted proxies of `application.rate_limit`
+#[derive(serde::Deserialize)]
+pub struct LoginRateLimitSettings {
+    pub per_ip: LimitSettings,
+    pub per_username: LimitSettings,
+}
+
+/// Hosted subscription pages
+#[derive(serde::Deserialize)]
+pub struct PageSettings {
+    pub theme: ThemeSettings,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ThemeSettings {
+    /// Shown in the title and heading of every page
+    pub site_name: String,
+    /// CSS hex colours, e.g. `#1d4ed8`
+    pub primary_color: String,
+    pub background_color: String,
+    pub text_color: String,
+    /// Its origin must be allowed by the `img-src` of
+    /// `application.security_headers.content_security_policy`
+    #[serde(default)]
+    pub logo_url: Option<String>,
+    /// Loaded after the built-in styles. Its origin must be allowed by
+    /// `application.security_headers.content_security_policy`.
+    #[serde(default)]
+    pub stylesheet_url: Option<String>,
 }
 
 #[derive(serde::Deserialize)]
 pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
+    /// Public URL the service is reachable at, used to build links in emails
+    pub base_url: String,
+    /// Key used to sign URLs handed out to subscribers. Required outside the
+    /// local environment, never committed.
+    #[serde(default)]
+    pub hmac_secret: SecretBox<String>,
+    pub rate_limit: RateLimitSettings,
+    /// Serve HTTPS, with HTTP/2 negotiated over ALPN, instead of plain HTTP
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+    pub cors: CorsSettings,
+    pub security_headers: SecurityHeaderSettings,
+    pub limits: RequestLimitSettings,
+    pub compression: CompressionSettings,
+    pub forms: FormSettings,
+    pub api_docs: ApiDocsSettings,
+}
+
+/// The Redoc bundle loaded by `/api/docs`
+#[derive(serde::Deserialize)]
+pub struct ApiDocsSettings {
+    /// A pinned version, whose origin must be allowed by the `script-src` of
+    /// `application.security_headers.content_security_policy`
+    pub redoc_url: String,
+    /// Subresource integrity digest of the bundle, e.g. `sha384-…`
+    #[serde(default)]
+    pub redoc_integrity: Option<String>,
+}
+
+/// Where browsers posting an HTML form to the subscribe endpoint are sent
+#[derive(serde::Deserialize)]
+pub struct FormSettings {
+    pub success_url: String,
+    /// Receives the reason as an `error` query parameter
+    pub error_url: String,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+pub struct RequestLimitSettings {
+    /// Larger request bodies are refused with a 413
+    pub body_limit_bytes: usize,
+    /// Clients taking longer to send the body get a 408
+    pub body_timeout_milliseconds: u64,
+    /// Requests taking longer to handle get a 503
+    pub request_timeout_milliseconds: u64,
+    /// Requests over this many in flight are shed with a 503
+    pub max_concurrent_requests: usize,
+}
+
+/// Response encodings offered to clients sending `Accept-Encoding`
+#[derive(serde::Deserialize)]
+pub struct CompressionSettings {
+    pub gzip: bool,
+    pub br: bool,
+    pub zstd: bool,
+}
+
+/// Cross-origin requests browsers are allowed to make, e.g. from the signup
+/// widget embedded on another site
+#[derive(serde::Deserialize)]
+pub struct CorsSettings {
+    /// Origins such as `https://example.com`, or `*` for any. The comma
+    /// separated `APP_APPLICATION__CORS__ALLOWED_ORIGINS` env var sets it.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response
+    pub max_age_seconds: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SecurityHeaderSettings {
+    /// `Strict-Transport-Security` max age, the header is not sent when 0
+    pub hsts_max_age_seconds: u64,
+    /// Sent with HTML pages only
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TlsSettings {
+    /// PEM certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM private key
+    pub key_path: PathBuf,
+    /// How often the files are checked for changes, the certificate is
+    /// reloaded without a restart when they do
+    #[serde(default = "default_tls_reload_interval")]
+    pub reload_interval_milliseconds: u64,
+}
+
+const fn default_tls_reload_interval() -> u64 {
+    10_000
+}
+
+#[derive(serde::Deserialize)]
+pub struct RateLimitSettings {
+    pub backend: RateLimitBackend,
+    pub per_ip: LimitSettings,
+    pub per_email: LimitSettings,
+    /// Reverse proxies whose `X-Forwarded-For` header names the client.
+    /// Requests from other peers are keyed on the peer address.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackend {
+    /// Counters local to this process
+    Memory,
+    /// Counters shared by every replica through the database
+    Postgres,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LimitSettings {
+    /// Requests allowed within a window
+    pub requests: u32,
+    pub window_seconds: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TrackingSettings {
+    /// Record opens and clicks for newsletter issues
+    pub enabled: bool,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TelemetrySettings {
+    /// Name the logs and exported traces are reported under
+    pub service_name: String,
+    /// Overridden by the `LOG_FORMAT` env var
+    pub log_format: LogFormat,
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    /// Traces are only exported when set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    pub otlp_protocol: OtlpProtocol,
+    /// Delay between two exports of the buffered spans
+    pub export_delay_milliseconds: u64,
+    pub redaction: RedactionSettings,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RedactionSettings {
+    /// How personal data is written to logs and traces
+    pub mode: RedactionMode,
+    /// Key for the `hash` mode, so hashes cannot be reversed by guessing.
+    /// Required outside the local environment, never committed.
+    #[serde(default)]
+    pub key: SecretBox<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionMode {
+    /// Values are logged in clear text
+    Off,
+    /// Only the first character, and the domain of emails, are kept
+    Mask,
+    /// Values are replaced by a keyed hash, so a subscriber can still be
+    /// followed across log lines
+    Hash,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    Pretty,
+    /// Bunyan compatible JSON lines, including the fields of the enclosing spans
+    Json,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+pub enum OtlpProtocol {
+    #[serde(rename = "http/protobuf")]
+    HttpProtobuf,
+    #[serde(rename = "http/json")]
+    HttpJson,
+}
+
+/// The Postmark compatible API newsletter issues are sent through
+#[derive(serde::Deserialize)]
+pub struct EmailClientSettings {
+    pub base_url: String,
+    pub sender_email: String,
+    #[serde(default)]
+    pub authorization_token: SecretBox<String>,
+    pub timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebhookSettings {
+    pub endpoints: Vec<WebhookEndpointSettings>,
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub retry_base_delay_milliseconds: u64,
+    pub timeout_milliseconds: u64,
+    /// How long the worker waits before looking again when no delivery is due
+    pub poll_interval_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebhookEndpointSettings {
+    pub url: String,
+    /// Key used to sign the payloads sent to this endpoint
+    pub secret: SecretBox<String>,
+    /// Events delivered to this endpoint, all of them when empty
+    #[serde(default)]
+    pub events: Vec<SubscriberEvent>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct BotProtectionSettings {
+    /// Reject submissions that fill in the hidden `website` field
+    pub honeypot: bool,
+    /// Minimum time between rendering the signup form and submitting it, 0 disables the check
+    pub min_submit_seconds: u64,
+    pub challenge: ChallengeSettings,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChallengeSettings {
+    pub provider: ChallengeProvider,
+    /// Provider secret, or the only token accepted by the `stub` provider
+    pub secret_key: SecretBox<String>,
+    /// Overrides the provider's verification endpoint
+    #[serde(default)]
+    pub verify_url: Option<String>,
+    /// Public key rendering the widget on the hosted subscribe page
+    #[serde(default)]
+    pub site_key: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeProvider {
+    None,
+    Stub,
+    Turnstile,
+    Hcaptcha,
 }
 
 #[derive(serde::Deserialize)]
@@ -19,41 +330,890 @@ pub struct DatabaseSettings {
     pub port: u16,
     pub host: String,
     pub database_name: String,
+    /// Shorthand for `ssl_mode: require`, ignored when `ssl_mode` is set
+    #[serde(default)]
+    pub require_ssl: bool,
+    #[serde(default)]
+    pub ssl_mode: Option<DatabaseSslMode>,
+    /// CA certificate the server certificate is verified against, the
+    /// system roots are used when unset
+    #[serde(default)]
+    pub ssl_root_cert: Option<PathBuf>,
+    /// Reported to the server, visible in `pg_stat_activity`
+    pub application_name: String,
+    /// Level executed statements are logged at by sqlx
+    pub statement_log_level: LevelFilter,
+    pub pool: PoolSettings,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    /// Connections kept open even when idle
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing
+    pub acquire_timeout_milliseconds: u64,
+    /// Idle connections above `min_connections` are closed after this long
+    #[serde(default)]
+    pub idle_timeout_seconds: Option<u64>,
+    /// Connections are recycled after this long
+    #[serde(default)]
+    pub max_lifetime_seconds: Option<u64>,
+    /// Statements running longer are cancelled by the server, 0 disables it
+    pub statement_timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatabaseSslMode {
+    Disable,
+    /// Use TLS when the server supports it
+    Prefer,
+    /// Use TLS without verifying the server certificate
+    Require,
+    /// Use TLS and check the server certificate and host name
+    VerifyFull,
 }
 
 impl DatabaseSettings {
     #[must_use]
-    pub fn connection_string(&self) -> SecretBox<String> {
-        SecretBox::new(Box::new(format!(
-            "postgres://{}:{}@{}:{}/{}",
-            self.username,
-            self.password.expose_secret(),
-            self.host,
-            self.port,
-            self.database_name
-        )))
+    pub fn ssl_mode(&self) -> DatabaseSslMode {
+        self.ssl_mode.unwrap_or(if self.require_ssl {
+            DatabaseSslMode::Require
+        } else {
+            DatabaseSslMode::Prefer
+        })
+    }
+
+    /// Options to connect to the server without selecting a database
+    #[must_use]
+    pub fn without_db(&self) -> PgConnectOptions {
+        let ssl_mode = match self.ssl_mode() {
+            DatabaseSslMode::Disable => PgSslMode::Disable,
+            DatabaseSslMode::Prefer => PgSslMode::Prefer,
+            DatabaseSslMode::Require => PgSslMode::Require,
+            DatabaseSslMode::VerifyFull => PgSslMode::VerifyFull,
+        };
+        let options = PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(&self.username)
+            .password(self.password.expose_secret())
+            .ssl_mode(ssl_mode)
+            .application_name(&self.application_name)
+            .log_statements(self.statement_log_level);
+        match &self.ssl_root_cert {
+            Some(path) => options.ssl_root_cert(path),
+            None => options,
+        }
+    }
+
+    /// Options to run migrations with, which are exempt from the statement
+    /// timeout as rewriting a large table can take longer
+    #[must_use]
+    pub fn for_migrations(&self) -> PgConnectOptions {
+        self.without_db().database(&self.database_name)
+    }
+
+    /// Options to connect to the application database
+    #[must_use]
+    pub fn with_db(&self) -> PgConnectOptions {
+        self.for_migrations().options([(
+            "statement_timeout",
+            self.pool.statement_timeout_milliseconds.to_string(),
+        )])
+    }
+
+    #[must_use]
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.pool.max_connections)
+            .min_connections(self.pool.min_connections)
+            .acquire_timeout(Duration::from_millis(
+                self.pool.acquire_timeout_milliseconds,
+            ))
+            .idle_timeout(self.pool.idle_timeout_seconds.map(Duration::from_secs))
+            .max_lifetime(self.pool.max_lifetime_seconds.map(Duration::from_secs))
+            // Feeds `PoolAcquireLayer`, which filters the events to this level
+            .acquire_time_level(log::LevelFilter::Trace)
+    }
+}
+
+/// The environment the service runs in, selecting `configuration/{environment}.yaml`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Environment {
+    Local,
+    Production,
+}
+
+impl Environment {
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Production => "production",
+        }
+    }
+}
+
+impl TryFrom<&str> for Environment {
+    type Error = ConfigurationError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "local" => Ok(Self::Local),
+            "production" => Ok(Self::Production),
+            _ => UnknownEnvironmentSnafu { value }.fail(),
+        }
     }
 }
 
-/// Retrieves the configuration from env vars and file
+#[derive(Debug, Snafu, Diagnostic)]
+pub enum ConfigurationError {
+    #[snafu(display("`{value}` is not a supported environment"))]
+    #[diagnostic(
+        code(configuration::environment),
+        help("Set APP_ENVIRONMENT to `local` or `production`")
+    )]
+    UnknownEnvironment { value: String },
+    #[snafu(display("Failed to load the configuration: {source}"))]
+    #[diagnostic(code(configuration::load))]
+    Load { source: config::ConfigError },
+    #[snafu(display("The configuration is invalid"))]
+    #[diagnostic(code(configuration::invalid))]
+    Invalid {
+        #[related]
+        problems: Vec<InvalidSetting>,
+    },
+}
+
+/// A value that deserialized fine but cannot work
+#[derive(Debug, Snafu, Diagnostic)]
+#[snafu(display("`{key}` {reason}"))]
+#[diagnostic(code(configuration::invalid_setting), help("Set in {origin}"))]
+pub struct InvalidSetting {
+    pub key: String,
+    pub reason: String,
+    /// File or environment the value came from
+    pub origin: String,
+}
+
+/// Retrieves the configuration from the files and the `APP_` env vars
 /// # Panics
 ///
 ///  If `std::env::current_dir()` returns an error.
 /// # Errors
 ///
-/// `ConfigError` If the setting file fails to be deserialized
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+/// - `APP_ENVIRONMENT` is not a known environment
+/// - The setting files or env vars fail to be deserialized
+/// - The resulting settings are invalid
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
-    let configuration_directory = base_path.join("configuration");
-    let environment: String = std::env::var("APP_ENVIRONMENT").unwrap_or_else(|_| "local".into());
-    let environment_filename = format!("{environment}.yaml");
-    let settings = config::Config::builder()
-        .add_source(config::File::from(
-            configuration_directory.join("base.yaml"),
-        ))
+    load_configuration(&base_path.join("configuration"), std::env::vars().collect())
+}
+
+/// Reads `base.yaml` then the environment file from `directory`, and lets
+/// `APP_` prefixed `vars` override any key, `__` separating nested keys:
+/// `APP_DATABASE__HOST` sets `database.host`.
+pub(crate) fn load_configuration(
+    directory: &Path,
+    vars: config::Map<String, String>,
+) -> Result<Settings, ConfigurationError> {
+    let environment =
+        Environment::try_from(vars.get("APP_ENVIRONMENT").map_or("local", String::as_str))?;
+    let log_format = vars.get("LOG_FORMAT").cloned();
+    let config = config::Config::builder()
+        .add_source(config::File::from(directory.join("base.yaml")))
         .add_source(config::File::from(
-            configuration_directory.join(&environment_filename),
+            directory.join(format!("{}.yaml", environment.as_str())),
         ))
-        .build()?;
-    settings.try_deserialize::<Settings>()
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("application.cors.allowed_origins")
+                .with_list_parse_key("application.cors.allowed_methods")
+                .with_list_parse_key("application.cors.allowed_headers")
+                .with_list_parse_key("application.rate_limit.trusted_proxies")
+                .source(Some(vars)),
+        )
+        .set_override_option("telemetry.log_format", log_format)
+        .and_then(ConfigBuilder::<DefaultState>::build)
+        .context(LoadSnafu)?;
+    let settings = config
+        .clone()
+        .try_deserialize::<Settings>()
+        .context(LoadSnafu)?;
+
+    let problems: Vec<InvalidSetting> = settings
+        .validate(environment)
+        .into_iter()
+        .map(|(key, reason)| InvalidSetting {
+            origin: origin_of(&config, &key).unwrap_or_else(|| "the defaults".to_string()),
+            key,
+            reason,
+        })
+        .collect();
+    if problems.is_empty() {
+        Ok(settings)
+    } else {
+        InvalidSnafu { problems }.fail()
+    }
+}
+
+/// Where the value at `key`, e.g. `webhooks.endpoints[0].url`, was set
+fn origin_of(config: &config::Config, key: &str) -> Option<String> {
+    let mut value = config::Value::from(config::Source::collect(config).ok()?);
+    for segment in key.split('.') {
+        let (name, index) = match segment.split_once('[') {
+            Some((name, index)) => (name, index.trim_end_matches(']').parse::<usize>().ok()),
+            None => (segment, None),
+        };
+        value = value.into_table().ok()?.remove(name)?;
+        if let Some(index) = index {
+            value = value.into_array().ok()?.into_iter().nth(index)?;
+        }
+    }
+    value.origin().map(ToString::to_string)
+}
+
+/// Settings that deserialized fine but cannot work, as `(key, reason)`
+#[derive(Default)]
+struct Problems(Vec<(String, String)>);
+
+impl Problems {
+    fn check(&mut self, valid: bool, key: &str, reason: &str) {
+        if !valid {
+            self.0.push((key.to_string(), reason.to_string()));
+        }
+    }
+
+    fn check_http_url(&mut self, value: &str, key: &str) {
+        self.check(is_http_url(value), key, "must be an http(s) URL");
+    }
+
+    fn check_limit(&mut self, limit: &LimitSettings, key: &str) {
+        self.check(
+            limit.requests > 0,
+            &format!("{key}.requests"),
+            "must be positive",
+        );
+        self.check(
+            limit.window_seconds > 0,
+            &format!("{key}.window_seconds"),
+            "must be positive",
+        );
+    }
+}
+
+/// The key committed in `local.yaml`, refused anywhere else
+const LOCAL_HMAC_SECRET: &str =
+    "super-long-and-secret-random-key-needed-to-verify-message-integrity";
+
+fn is_http_url(value: &str) -> bool {
+    Url::parse(value).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// A subresource integrity digest, e.g. `sha384-` followed by base64
+fn is_integrity_digest(value: &str) -> bool {
+    value.split_once('-').is_some_and(|(algorithm, digest)| {
+        matches!(algorithm, "sha256" | "sha384" | "sha512")
+            && !digest.is_empty()
+            && digest
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='))
+    })
+}
+
+impl Settings {
+    /// Checks what deserialization cannot, returning the offending keys
+    fn validate(&self, environment: Environment) -> Vec<(String, String)> {
+        let mut problems = Problems::default();
+        self.application.validate(environment, &mut problems);
+        self.database.validate(environment, &mut problems);
+        self.webhooks.validate(&mut problems);
+        self.bot_protection.validate(&mut problems);
+        self.telemetry.validate(environment, &mut problems);
+        self.email_client.validate(environment, &mut problems);
+        self.pages.validate(&mut problems);
+        self.admin.validate(&mut problems);
+        problems.0
+    }
+}
+
+impl ApplicationSettings {
+    /// Key derived from `hmac_secret` for a single `purpose`, so a signature
+    /// made for one use is never accepted by another
+    #[must_use]
+    pub fn signing_key(&self, purpose: &str) -> SecretBox<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(purpose.as_bytes());
+        SecretBox::new(Box::new(hex::encode(mac.finalize().into_bytes())))
+    }
+
+    fn validate(&self, environment: Environment, problems: &mut Problems) {
+        problems.check_http_url(&self.base_url, "application.base_url");
+        let hmac_secret = self.hmac_secret.expose_secret();
+        problems.check(
+            hmac_secret.len() >= 32,
+            "application.hmac_secret",
+            "must be at least 32 characters long",
+        );
+        problems.check(
+            hmac_secret != LOCAL_HMAC_SECRET || environment == Environment::Local,
+            "application.hmac_secret",
+            "is the key of the local environment, which is public",
+        );
+        if let Some(tls) = &self.tls {
+            problems.check(
+                tls.cert_path.is_file(),
+                "application.tls.cert_path",
+                "is not a readable file",
+            );
+            problems.check(
+                tls.key_path.is_file(),
+                "application.tls.key_path",
+                "is not a readable file",
+            );
+            problems.check(
+                tls.reload_interval_milliseconds > 0,
+                "application.tls.reload_interval_milliseconds",
+                "must be positive",
+            );
+        }
+        problems.check_http_url(&self.forms.success_url, "application.forms.success_url");
+        problems.check_http_url(&self.forms.error_url, "application.forms.error_url");
+        self.limits.validate(problems);
+        self.cors.validate(problems);
+        self.api_docs.validate(environment, problems);
+        self.security_headers.validate(problems);
+        problems.check_limit(&self.rate_limit.per_ip, "application.rate_limit.per_ip");
+        problems.check_limit(
+            &self.rate_limit.per_email,
+            "application.rate_limit.per_email",
+        );
+    }
+}
+
+impl RequestLimitSettings {
+    fn validate(&self, problems: &mut Problems) {
+        for (key, value) in [
+            ("application.limits.body_limit_bytes", self.body_limit_bytes),
+            (
+                "application.limits.max_concurrent_requests",
+                self.max_concurrent_requests,
+            ),
+        ] {
+            problems.check(value > 0, key, "must be positive");
+        }
+        for (key, value) in [
+            (
+                "application.limits.body_timeout_milliseconds",
+                self.body_timeout_milliseconds,
+            ),
+            (
+                "application.limits.request_timeout_milliseconds",
+                self.request_timeout_milliseconds,
+            ),
+        ] {
+            problems.check(value > 0, key, "must be positive");
+        }
+    }
+}
+
+impl CorsSettings {
+    fn validate(&self, problems: &mut Problems) {
+        for (index, origin) in self.allowed_origins.iter().enumerate() {
+            problems.check(
+                origin == "*" && self.allowed_origins.len() == 1
+                    || origin != "*"
+                        && Url::parse(origin).is_ok_and(|url| {
+                            matches!(url.scheme(), "http" | "https")
+                                && url.origin().ascii_serialization() == *origin
+                        }),
+                &format!("application.cors.allowed_origins[{index}]"),
+                "must be `*` alone or a scheme, host and optional port without a trailing slash",
+            );
+        }
+        for (index, method) in self.allowed_methods.iter().enumerate() {
+            problems.check(
+                method.parse::<Method>().is_ok(),
+                &format!("application.cors.allowed_methods[{index}]"),
+                "is not an HTTP method",
+            );
+        }
+        for (index, name) in self.allowed_headers.iter().enumerate() {
+            problems.check(
+                name.parse::<HeaderName>().is_ok(),
+                &format!("application.cors.allowed_headers[{index}]"),
+                "is not a header name",
+            );
+        }
+    }
+}
+
+impl ApiDocsSettings {
+    fn validate(&self, environment: Environment, problems: &mut Problems) {
+        problems.check_http_url(&self.redoc_url, "application.api_docs.redoc_url");
+        problems.check(
+            self.redoc_integrity
+                .as_deref()
+                .map_or(environment == Environment::Local, is_integrity_digest),
+            "application.api_docs.redoc_integrity",
+            "must be a `sha256-`, `sha384-` or `sha512-` base64 digest, required outside the local environment",
+        );
+    }
+}
+
+impl SecurityHeaderSettings {
+    /// Whether `Strict-Transport-Security` is sent
+    #[must_use]
+    pub const fn sends_hsts(&self) -> bool {
+        self.hsts_max_age_seconds > 0
+    }
+
+    /// `Strict-Transport-Security`, when sent
+    #[must_use]
+    pub fn strict_transport_security(&self) -> Option<String> {
+        self.sends_hsts()
+            .then(|| format!("max-age={}; includeSubDomains", self.hsts_max_age_seconds))
+    }
+
+    fn validate(&self, problems: &mut Problems) {
+        if let Some(hsts) = self.strict_transport_security() {
+            problems.check(
+                HeaderValue::from_str(&hsts).is_ok(),
+                "application.security_headers.hsts_max_age_seconds",
+                "does not make a valid header value",
+            );
+        }
+        problems.check(
+            HeaderValue::from_str(&self.content_security_policy).is_ok(),
+            "application.security_headers.content_security_policy",
+            "is not a valid header value",
+        );
+        problems.check(
+            HeaderValue::from_str(&self.referrer_policy).is_ok(),
+            "application.security_headers.referrer_policy",
+            "is not a valid header value",
+        );
+    }
+}
+
+impl DatabaseSettings {
+    fn validate(&self, environment: Environment, problems: &mut Problems) {
+        problems.check(
+            self.ssl_mode() == DatabaseSslMode::VerifyFull || environment == Environment::Local,
+            "database.ssl_mode",
+            "must be `verify-full` outside the local environment",
+        );
+        problems.check(
+            !self.database_name.is_empty(),
+            "database.database_name",
+            "must not be empty",
+        );
+        problems.check(
+            self.pool.max_connections > 0,
+            "database.pool.max_connections",
+            "must be positive",
+        );
+        problems.check(
+            self.pool.min_connections <= self.pool.max_connections,
+            "database.pool.min_connections",
+            "must not exceed `database.pool.max_connections`",
+        );
+        if let Some(path) = &self.ssl_root_cert {
+            problems.check(
+                path.is_file(),
+                "database.ssl_root_cert",
+                "is not a readable file",
+            );
+        }
+    }
+}
+
+impl WebhookSettings {
+    fn validate(&self, problems: &mut Problems) {
+        problems.check(
+            self.max_attempts > 0,
+            "webhooks.max_attempts",
+            "must be positive",
+        );
+        problems.check(
+            self.poll_interval_milliseconds > 0,
+            "webhooks.poll_interval_milliseconds",
+            "must be positive",
+        );
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            problems.check_http_url(&endpoint.url, &format!("webhooks.endpoints[{index}].url"));
+            problems.check(
+                !endpoint.secret.expose_secret().is_empty(),
+                &format!("webhooks.endpoints[{index}].secret"),
+                "must not be empty",
+            );
+        }
+    }
+}
+
+impl BotProtectionSettings {
+    fn validate(&self, problems: &mut Problems) {
+        let challenge = &self.challenge;
+        if matches!(
+            challenge.provider,
+            ChallengeProvider::Turnstile | ChallengeProvider::Hcaptcha | ChallengeProvider::Stub
+        ) {
+            problems.check(
+                !challenge.secret_key.expose_secret().is_empty(),
+                "bot_protection.challenge.secret_key",
+                "is required by the challenge provider",
+            );
+        }
+        if let Some(url) = &challenge.verify_url {
+            problems.check_http_url(url, "bot_protection.challenge.verify_url");
+        }
+    }
+}
+
+impl TelemetrySettings {
+    fn validate(&self, environment: Environment, problems: &mut Problems) {
+        if let Some(endpoint) = &self.otlp_endpoint {
+            problems.check_http_url(endpoint, "telemetry.otlp_endpoint");
+        }
+        problems.check(
+            !self.redaction.key.expose_secret().is_empty()
+                || environment == Environment::Local && self.redaction.mode != RedactionMode::Hash,
+            "telemetry.redaction.key",
+            "is required outside the local environment and to hash personal data",
+        );
+    }
+}
+
+impl EmailClientSettings {
+    fn validate(&self, environment: Environment, problems: &mut Problems) {
+        problems.check_http_url(&self.base_url, "email_client.base_url");
+        problems.check(
+            SubscriberEmail::parse(self.sender_email.clone()).is_ok(),
+            "email_client.sender_email",
+            "must be an email address",
+        );
+        problems.check(
+            !self.authorization_token.expose_secret().is_empty()
+                || environment == Environment::Local,
+            "email_client.authorization_token",
+            "is required outside the local environment",
+        );
+        problems.check(
+            self.timeout_milliseconds > 0,
+            "email_client.timeout_milliseconds",
+            "must be positive",
+        );
+    }
+}
+
+impl PageSettings {
+    fn validate(&self, problems: &mut Problems) {
+        let theme = &self.theme;
+        let is_hex_color = |value: &str| {
+            value.strip_prefix('#').is_some_and(|hex| {
+                matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+            })
+        };
+        for (key, color) in [
+            ("pages.theme.primary_color", &theme.primary_color),
+            ("pages.theme.background_color", &theme.background_color),
+            ("pages.theme.text_color", &theme.text_color),
+        ] {
+            problems.check(
+                is_hex_color(color),
+                key,
+                "must be a hex colour like `#1d4ed8`",
+            );
+        }
+        for (key, url) in [
+            ("pages.theme.logo_url", &theme.logo_url),
+            ("pages.theme.stylesheet_url", &theme.stylesheet_url),
+        ] {
+            if let Some(url) = url {
+                problems.check_http_url(url, key);
+            }
+        }
+    }
+}
+
+impl AdminSettings {
+    fn validate(&self, problems: &mut Problems) {
+        problems.check(
+            self.session_ttl_minutes > 0,
+            "admin.session_ttl_minutes",
+            "must be positive",
+        );
+        problems.check(
+            (1..=500).contains(&self.page_size),
+            "admin.page_size",
+            "must be between 1 and 500",
+        );
+        problems.check_limit(
+            &self.login_rate_limit.per_ip,
+            "admin.login_rate_limit.per_ip",
+        );
+        problems.check_limit(
+            &self.login_rate_limit.per_username,
+            "admin.login_rate_limit.per_username",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use secrecy::{ExposeSecret, SecretBox};
+    use sqlx::postgres::PgSslMode;
+
+    use super::{
+        ConfigurationError, DatabaseSettings, DatabaseSslMode, PoolSettings, load_configuration,
+    };
+
+    const PRODUCTION_HMAC_SECRET: &str = "a-production-key-of-at-least-32-characters";
+
+    fn load(vars: &[(&str, &str)]) -> Result<super::Settings, ConfigurationError> {
+        load_configuration(
+            std::path::Path::new("configuration"),
+            vars.iter()
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect(),
+        )
+    }
+
+    fn settings(require_ssl: bool, ssl_mode: Option<DatabaseSslMode>) -> DatabaseSettings {
+        DatabaseSettings {
+            username: "postgres".to_string(),
+            password: SecretBox::new(Box::new("password".to_string())),
+            port: 5432,
+            host: "db.internal".to_string(),
+            database_name: "newsletter".to_string(),
+            require_ssl,
+            ssl_mode,
+            ssl_root_cert: None,
+            application_name: "z2p-axum".to_string(),
+            statement_log_level: log::LevelFilter::Debug,
+            pool: PoolSettings {
+                max_connections: 20,
+                min_connections: 2,
+                acquire_timeout_milliseconds: 2000,
+                idle_timeout_seconds: Some(600),
+                max_lifetime_seconds: None,
+                statement_timeout_milliseconds: 5000,
+            },
+        }
+    }
+
+    #[test]
+    fn require_ssl_is_honoured_unless_a_mode_is_set() {
+        assert!(matches!(
+            settings(false, None).with_db().get_ssl_mode(),
+            PgSslMode::Prefer
+        ));
+        assert!(matches!(
+            settings(true, None).with_db().get_ssl_mode(),
+            PgSslMode::Require
+        ));
+        assert!(matches!(
+            settings(true, Some(DatabaseSslMode::VerifyFull))
+                .with_db()
+                .get_ssl_mode(),
+            PgSslMode::VerifyFull
+        ));
+    }
+
+    #[test]
+    fn production_verifies_the_database_certificate() {
+        let settings = load(&[
+            ("APP_ENVIRONMENT", "production"),
+            ("APP_BOT_PROTECTION__CHALLENGE__SECRET_KEY", "challenge"),
+            ("APP_TELEMETRY__REDACTION__KEY", "redaction"),
+            ("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN", "token"),
+            ("APP_APPLICATION__API_DOCS__REDOC_INTEGRITY", "sha384-abc"),
+            ("APP_APPLICATION__HMAC_SECRET", PRODUCTION_HMAC_SECRET),
+        ])
+        .unwrap();
+        assert!(matches!(
+            settings.database.with_db().get_ssl_mode(),
+            PgSslMode::VerifyFull
+        ));
+        assert!(settings.database.ssl_root_cert.is_some());
+
+        let Err(ConfigurationError::Invalid { problems }) = load(&[
+            ("APP_ENVIRONMENT", "production"),
+            ("APP_DATABASE__SSL_MODE", "require"),
+        ]) else {
+            panic!("the configuration was accepted");
+        };
+        assert!(problems.iter().any(|p| p.key == "database.ssl_mode"));
+    }
+
+    #[test]
+    fn connect_options_carry_the_settings() {
+        let options = settings(false, Some(DatabaseSslMode::Disable)).with_db();
+        assert_eq!(options.get_host(), "db.internal");
+        assert_eq!(options.get_database(), Some("newsletter"));
+        assert_eq!(options.get_application_name(), Some("z2p-axum"));
+        assert_eq!(settings(false, None).without_db().get_database(), None);
+    }
+
+    #[test]
+    fn pool_options_carry_the_settings() {
+        let settings = settings(false, None);
+        let pool = settings.pool_options();
+        assert_eq!(pool.get_max_connections(), 20);
+        assert_eq!(pool.get_min_connections(), 2);
+        assert_eq!(pool.get_acquire_timeout(), Duration::from_secs(2));
+        assert_eq!(pool.get_idle_timeout(), Some(Duration::from_secs(600)));
+        assert_eq!(pool.get_max_lifetime(), None);
+        assert!(
+            settings
+                .with_db()
+                .get_options()
+                .is_some_and(|options| options.contains("statement_timeout=5000"))
+        );
+        assert_eq!(settings.for_migrations().get_options(), None);
+    }
+
+    #[test]
+    fn nested_keys_are_overridden_by_app_env_vars() {
+        let settings = load(&[
+            ("APP_DATABASE__HOST", "db.internal"),
+            ("APP_APPLICATION__RATE_LIMIT__PER_IP__REQUESTS", "7"),
+        ])
+        .unwrap();
+        assert_eq!(settings.database.host, "db.internal");
+        assert_eq!(settings.application.rate_limit.per_ip.requests, 7);
+    }
+
+    #[test]
+    fn unknown_environment_is_rejected() {
+        assert!(matches!(
+            load(&[("APP_ENVIRONMENT", "staging")]),
+            Err(ConfigurationError::UnknownEnvironment { value }) if value == "staging"
+        ));
+    }
+
+    #[test]
+    fn invalid_values_are_reported_with_their_key_and_origin() {
+        let Err(ConfigurationError::Invalid { problems }) = load(&[
+            ("APP_APPLICATION__BASE_URL", "not a url"),
+            ("APP_WEBHOOKS__MAX_ATTEMPTS", "0"),
+        ]) else {
+            panic!("the configuration was accepted");
+        };
+        let keys: Vec<_> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, ["application.base_url", "webhooks.max_attempts"]);
+        assert_eq!(problems[0].origin, "the environment");
+    }
+
+    #[test]
+    fn cors_lists_are_comma_separated_in_env_vars() {
+        let settings = load(&[(
+            "APP_APPLICATION__CORS__ALLOWED_ORIGINS",
+            "https://example.com,https://www.example.com",
+        )])
+        .unwrap();
+        assert_eq!(
+            settings.application.cors.allowed_origins,
+            ["https://example.com", "https://www.example.com"]
+        );
+
+        let Err(ConfigurationError::Invalid { problems }) = load(&[(
+            "APP_APPLICATION__CORS__ALLOWED_ORIGINS",
+            "https://example.com/,*",
+        )]) else {
+            panic!("the configuration was accepted");
+        };
+        let keys: Vec<_> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "application.cors.allowed_origins[0]",
+                "application.cors.allowed_origins[1]"
+            ]
+        );
+    }
+
+    #[test]
+    fn tls_files_must_exist() {
+        let Err(ConfigurationError::Invalid { problems }) = load(&[
+            ("APP_APPLICATION__TLS__CERT_PATH", "missing/cert.pem"),
+            ("APP_APPLICATION__TLS__KEY_PATH", "missing/key.pem"),
+        ]) else {
+            panic!("the configuration was accepted");
+        };
+        let keys: Vec<_> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            ["application.tls.cert_path", "application.tls.key_path"]
+        );
+    }
+
+    #[test]
+    fn production_requires_the_challenge_secret() {
+        let Err(ConfigurationError::Invalid { problems }) =
+            load(&[("APP_ENVIRONMENT", "production")])
+        else {
+            panic!("the configuration was accepted");
+        };
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.key == "bot_protection.challenge.secret_key"
+                    && p.origin.ends_with("base.yaml"))
+        );
+        assert!(problems.iter().any(|p| p.key == "telemetry.redaction.key"));
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.key == "email_client.authorization_token")
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.key == "application.api_docs.redoc_integrity")
+        );
+    }
+
+    #[test]
+    fn production_requires_its_own_hmac_secret() {
+        let Err(ConfigurationError::Invalid { problems }) =
+            load(&[("APP_ENVIRONMENT", "production")])
+        else {
+            panic!("the configuration was accepted");
+        };
+        assert!(problems.iter().any(|p| p.key == "application.hmac_secret"));
+
+        let Err(ConfigurationError::Invalid { problems }) = load(&[
+            ("APP_ENVIRONMENT", "production"),
+            ("APP_APPLICATION__HMAC_SECRET", super::LOCAL_HMAC_SECRET),
+        ]) else {
+            panic!("the configuration was accepted");
+        };
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.key == "application.hmac_secret"
+                    && p.reason.contains("local environment"))
+        );
+        assert!(load(&[]).is_ok());
+    }
+
+    #[test]
+    fn signing_keys_differ_by_purpose() {
+        let settings = load(&[]).unwrap().application;
+        let key = |purpose| settings.signing_key(purpose).expose_secret().clone();
+        assert_ne!(key("admin-login"), key("tracking"));
+        assert_ne!(key("tracking"), key("subscription-links"));
+        assert_ne!(&key("tracking"), settings.hmac_secret.expose_secret());
+        assert_eq!(key("tracking"), key("tracking"));
+    }
 }
@@ -0,0 +1,329 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Uuid;
+
+use crate::pages::{Pages, escape, field};
+use crate::roles::{Permission, Role};
+use crate::sessions::Session;
+
+/// A subscriber as listed in the dashboard
+pub struct SubscriberRow {
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub status_changed_at: DateTime<Utc>,
+}
+
+/// Filters of the subscriber list, kept in the links to other pages
+#[derive(Default)]
+pub struct SubscriberFilter<'a> {
+    pub status: Option<&'a str>,
+    /// Part of the email or name
+    pub search: Option<&'a str>,
+    /// Starts at 1
+    pub page: u32,
+}
+
+pub struct IssueRow {
+    pub id: Uuid,
+    pub title: String,
+    pub author: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct UserRow {
+    pub id: Uuid,
+    pub username: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Values of the compose form, echoed back when it has errors
+#[derive(Default)]
+pub struct IssueFormValues<'a> {
+    pub title: &'a str,
+    pub text_content: &'a str,
+    pub html_content: &'a str,
+}
+
+#[derive(Default)]
+pub struct IssueFormErrors {
+    pub title: Option<&'static str>,
+    pub text_content: Option<&'static str>,
+    pub html_content: Option<&'static str>,
+}
+
+impl Pages {
+    #[must_use]
+    pub fn login(&self, csrf_token: &str, notice: Option<&str>) -> String {
+        let mut content = notice_paragraph(notice);
+        content.push_str(r#"<form method="post" action="/admin/login">"#);
+        csrf_field(&mut content, csrf_token);
+        field(&mut content, "username", "text", "Username", "", None);
+        field(&mut content, "password", "password", "Password", "", None);
+        content.push_str(r#"<button type="submit">Log in</button></form>"#);
+        self.render("Log in", &content, "")
+    }
+
+    #[must_use]
+    pub fn dashboard(&self, session: &Session, statuses: &[(String, i64)], issues: i64) -> String {
+        let mut content = String::from("<table><tr><th>Subscribers</th><th></th></tr>");
+        for (status, count) in statuses {
+            let _ = write!(
+                content,
+                r#"<tr><td><a href="/admin/subscribers?status={status}">{status}</a></td><td>{count}</td></tr>"#,
+                status = escape(status),
+            );
+        }
+        let _ = write!(
+            content,
+            r#"</table><p><a href="/admin/issues">{issues} issues</a>"#
+        );
+        if session.can(Permission::IssuesWrite) {
+            content.push_str(r#" · <a href="/admin/issues/new">Compose an issue</a>"#);
+        }
+        content.push_str("</p>");
+        self.admin_layout(session, "Dashboard", &content)
+    }
+
+    #[must_use]
+    pub fn subscribers(
+        &self,
+        session: &Session,
+        rows: &[SubscriberRow],
+        filter: &SubscriberFilter<'_>,
+        has_next: bool,
+    ) -> String {
+        let mut content = String::from(r#"<form method="get" action="/admin/subscribers">"#);
+        field(
+            &mut content,
+            "search",
+            "search",
+            "Email or name",
+            filter.search.unwrap_or_default(),
+            None,
+        );
+        content.push_str(r#"<label for="status">Status</label><select id="status" name="status"><option value="">Any</option>"#);
+        for status in ["pending", "confirmed", "unsubscribed"] {
+            let selected = if filter.status == Some(status) {
+                " selected"
+            } else {
+                ""
+            };
+            let _ = write!(
+                content,
+                r#"<option value="{status}"{selected}>{status}</option>"#
+            );
+        }
+        content.push_str(r#"</select><button type="submit">Filter</button></form>"#);
+
+        content.push_str("<table><tr><th>Email</th><th>Name</th><th>Status</th><th>Frequency</th><th>Paused until</th><th>Since</th></tr>");
+        for row in rows {
+            let _ = write!(
+                content,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&row.email),
+                escape(&row.name),
+                escape(&row.status),
+                escape(&row.frequency),
+                row.paused_until
+                    .filter(|until| *until > Utc::now())
+                    .map(|until| until.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                row.status_changed_at.format("%Y-%m-%d"),
+            );
+        }
+        content.push_str("</table>");
+        if rows.is_empty() {
+            content.push_str("<p>No subscriber matches.</p>");
+        }
+
+        let mut pages = Vec::new();
+        if filter.page > 1 {
+            pages.push(format!(
+                r#"<a href="{}" rel="prev">Previous</a>"#,
+                subscribers_url(filter, filter.page - 1)
+            ));
+        }
+        if has_next {
+            pages.push(format!(
+                r#"<a href="{}" rel="next">Next</a>"#,
+                subscribers_url(filter, filter.page + 1)
+            ));
+        }
+        if !pages.is_empty() {
+            let _ = write!(content, "<p>{}</p>", pages.join(" · "));
+        }
+        self.admin_layout(session, "Subscribers", &content)
+    }
+
+    #[must_use]
+    pub fn issues(&self, session: &Session, rows: &[IssueRow]) -> String {
+        let mut content = String::new();
+        if session.can(Permission::IssuesWrite) {
+            content.push_str(r#"<p><a href="/admin/issues/new">Compose an issue</a></p>"#);
+        }
+        content.push_str("<table><tr><th>Title</th><th>Author</th><th>Created</th></tr>");
+        for row in rows {
+            let _ = write!(
+                content,
+                r#"<tr id="issue-{}"><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                row.id,
+                escape(&row.title),
+                escape(row.author.as_deref().unwrap_or("unknown")),
+                row.created_at.format("%Y-%m-%d %H:%M"),
+            );
+        }
+        content.push_str("</table>");
+        self.admin_layout(session, "Issues", &content)
+    }
+
+    #[must_use]
+    pub fn compose_issue(
+        &self,
+        session: &Session,
+        values: &IssueFormValues<'_>,
+        errors: &IssueFormErrors,
+    ) -> String {
+        let mut content = String::from(r#"<form method="post" action="/admin/issues" novalidate>"#);
+        csrf_field(&mut content, &session.csrf_token);
+        field(
+            &mut content,
+            "title",
+            "text",
+            "Title",
+            values.title,
+            errors.title,
+        );
+        textarea(
+            &mut content,
+            "text_content",
+            "Plain text",
+            values.text_content,
+            errors.text_content,
+        );
+        textarea(
+            &mut content,
+            "html_content",
+            "HTML",
+            values.html_content,
+            errors.html_content,
+        );
+        content.push_str(r#"<button type="submit">Save issue</button></form>"#);
+        self.admin_layout(session, "Compose an issue", &content)
+    }
+
+    /// Every user with a form changing their role, except for the user
+    /// looking at the page
+    #[must_use]
+    pub fn users(&self, session: &Session, rows: &[UserRow]) -> String {
+        let mut content =
+            String::from("<table><tr><th>Username</th><th>Role</th><th>Since</th></tr>");
+        for row in rows {
+            let _ = write!(content, "<tr><td>{}</td><td>", escape(&row.username));
+            if session.admin_user_id == row.id {
+                content.push_str(&escape(&row.role));
+            } else {
+                let _ = write!(
+                    content,
+                    r#"<form method="post" action="/admin/users/{}/role">"#,
+                    row.id
+                );
+                csrf_field(&mut content, &session.csrf_token);
+                let _ = write!(
+                    content,
+                    r#"<select name="role" aria-label="Role of {}">"#,
+                    escape(&row.username)
+                );
+                for role in Role::ALL {
+                    let selected = if row.role == role.as_str() {
+                        " selected"
+                    } else {
+                        ""
+                    };
+                    let _ = write!(
+                        content,
+                        r#"<option value="{role}"{selected}>{role}</option>"#,
+                        role = role.as_str()
+                    );
+                }
+                content.push_str(r#"</select><button type="submit">Change</button></form>"#);
+            }
+            let _ = write!(
+                content,
+                "</td><td>{}</td></tr>",
+                row.created_at.format("%Y-%m-%d")
+            );
+        }
+        content.push_str("</table>");
+        self.admin_layout(session, "Users", &content)
+    }
+
+    /// Navigation, logout button and flash message above `content`
+    fn admin_layout(&self, session: &Session, title: &str, content: &str) -> String {
+        let mut page = String::from(
+            r#"<nav><a href="/admin">Dashboard</a><a href="/admin/subscribers">Subscribers</a><a href="/admin/issues">Issues</a>"#,
+        );
+        if session.can(Permission::UsersManage) {
+            page.push_str(r#"<a href="/admin/users">Users</a>"#);
+        }
+        page.push_str(r#"<form method="post" action="/admin/logout">"#);
+        csrf_field(&mut page, &session.csrf_token);
+        let _ = write!(
+            page,
+            r#"<button type="submit">Log out {}</button></form></nav>"#,
+            escape(&session.username)
+        );
+        page.push_str(&notice_paragraph(session.flash.as_deref()));
+        page.push_str(content);
+        self.render(title, &page, "wide")
+    }
+}
+
+fn notice_paragraph(message: Option<&str>) -> String {
+    message
+        .map(|message| format!(r#"<p class="notice" role="status">{}</p>"#, escape(message)))
+        .unwrap_or_default()
+}
+
+fn csrf_field(content: &mut String, csrf_token: &str) {
+    let _ = write!(
+        content,
+        r#"<input type="hidden" name="csrf_token" value="{}">"#,
+        escape(csrf_token)
+    );
+}
+
+fn textarea(content: &mut String, name: &str, label: &str, value: &str, error: Option<&str>) {
+    let _ = write!(
+        content,
+        r#"<label for="{name}">{label}</label><textarea id="{name}" name="{name}" rows="12" required"#
+    );
+    match error {
+        Some(error) => {
+            let _ = write!(
+                content,
+                r#" aria-invalid="true" aria-describedby="{name}-error">{}</textarea><p class="error" id="{name}-error">{error}</p>"#,
+                escape(value)
+            );
+        }
+        None => {
+            let _ = write!(content, ">{}</textarea>", escape(value));
+        }
+    }
+}
+
+fn subscribers_url(filter: &SubscriberFilter<'_>, page: u32) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(status) = filter.status {
+        query.append_pair("status", status);
+    }
+    if let Some(search) = filter.search {
+        query.append_pair("search", search);
+    }
+    query.append_pair("page", &page.to_string());
+    escape(&format!("/admin/subscribers?{}", query.finish()))
+}
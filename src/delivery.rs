@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use sqlx::{Acquire, PgExecutor, PgPool, Postgres, types::Uuid};
+
+use crate::email_client::{EmailClient, EmailHeader};
+use crate::pages::escape;
+use crate::subscription_links::{LinkAction, SubscriptionLinks};
+use crate::tracking::Tracker;
+
+/// How long the worker waits before looking again when nothing is queued
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+/// How long the worker waits after a database error
+const ERROR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Outcome of a single attempt to deliver an issue to a subscriber
+#[derive(Debug)]
+pub enum DeliveryOutcome {
+    Sent,
+    Failed(String),
+    Bounced(String),
+    /// The recipient is on a suppression list and was skipped
+    Suppressed,
+}
+
+impl DeliveryOutcome {
+    #[must_use]
+    pub const fn status(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Failed(_) => "failed",
+            Self::Bounced(_) => "bounced",
+            Self::Suppressed => "suppressed",
+        }
+    }
+
+    #[must_use]
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Self::Failed(e) | Self::Bounced(e) => Some(e),
+            Self::Sent | Self::Suppressed => None,
+        }
+    }
+}
+
+/// Marks the start of a send and queues the issue for every confirmed
+/// subscriber. Returns the number of recipients queued.
+///
+/// Subscribers who paused delivery or were already sent an issue within
+/// their chosen frequency are skipped, failed and bounced deliveries do not
+/// count. Runs in a transaction, nested in the one of `connection` if any.
+///
+/// # Errors
+///
+/// - The issue has already been sent
+/// - Database error
+#[tracing::instrument(name = "Starting the send of an issue", skip(connection))]
+pub async fn start_send(
+    connection: impl Acquire<'_, Database = Postgres>,
+    issue_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = connection.begin().await?;
+    sqlx::query!("insert into issue_send (issue_id) values ($1)", issue_id)
+        .execute(&mut *transaction)
+        .await?;
+    let queued = sqlx::query!(
+        r#"
+        insert into issue_delivery (issue_id, subscriber_id, subscriber_email)
+        select $1, s.id, s.email from subscriber s
+        where s.status = 'confirmed'
+            and (s.paused_until is null or s.paused_until <= now())
+            and (s.frequency = 'every_issue' or not exists (
+                select 1 from issue_delivery d
+                join issue_send i on i.issue_id = d.issue_id
+                where d.subscriber_id = s.id
+                    and d.status = 'sent'
+                    and i.started_at > now() - case s.frequency
+                        when 'weekly' then interval '7 days'
+                        else interval '30 days'
+                    end
+            ))
+        "#,
+        issue_id
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if queued == 0 {
+        finish_send(&mut *transaction, issue_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(queued)
+}
+
+/// Records the outcome of an attempt to deliver an issue to one subscriber.
+///
+/// # Errors
+///
+/// - Database error
+#[tracing::instrument(name = "Recording a delivery outcome", skip(executor))]
+pub async fn record_outcome(
+    executor: impl PgExecutor<'_>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    outcome: &DeliveryOutcome,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update issue_delivery
+        set status = $3,
+            last_error = case when $3 = 'sent' then null else coalesce($4, last_error) end,
+            attempts = attempts + 1,
+            updated_at = now()
+        where issue_id = $1 and subscriber_id = $2
+        "#,
+        issue_id,
+        subscriber_id,
+        outcome.status(),
+        outcome.error(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Marks a send as finished once every recipient has been attempted.
+///
+/// # Errors
+///
+/// - Database error
+#[tracing::instrument(name = "Finishing the send of an issue", skip(executor))]
+pub async fn finish_send(executor: impl PgExecutor<'_>, issue_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "update issue_send set finished_at = now() where issue_id = $1",
+        issue_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Whether `deliver_next` found a queued delivery
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    Delivered,
+    EmptyQueue,
+}
+
+/// Sends the oldest queued delivery and records its outcome, finishing the
+/// send of its issue once no recipient is left queued. Concurrent workers
+/// skip the deliveries locked by each other.
+///
+/// Every email carries the unsubscribe link of its recipient, in its body
+/// and in the `List-Unsubscribe` headers of RFC 8058 one-click unsubscribe.
+/// With tracking enabled, the links and open pixel of the body are signed
+/// for the recipient as it is sent, the issue being stored once.
+///
+/// # Errors
+///
+/// Database error, failures to send are recorded as the outcome
+#[tracing::instrument(
+    name = "Delivering an issue",
+    skip_all,
+    fields(issue_id, subscriber_id)
+)]
+pub async fn deliver_next(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    links: &SubscriptionLinks,
+    tracker: &Tracker,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(delivery) = sqlx::query!(
+        r#"
+        select
+            d.issue_id, d.subscriber_id, d.subscriber_email, i.title, i.text_content,
+            i.html_content
+        from issue_delivery d
+        join newsletter_issue i on i.id = d.issue_id
+        where d.status = 'queued'
+        order by d.updated_at
+        limit 1
+        for update of d skip locked
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let span = tracing::Span::current();
+    span.record("issue_id", tracing::field::display(delivery.issue_id));
+    span.record(
+        "subscriber_id",
+        tracing::field::display(delivery.subscriber_id),
+    );
+
+    let html_content = tracker.instrument_html(
+        &delivery.html_content,
+        delivery.issue_id,
+        delivery.subscriber_id,
+    );
+    let unsubscribe_url = links.url(LinkAction::Unsubscribe, delivery.subscriber_id);
+    let list_unsubscribe = format!("<{unsubscribe_url}>");
+    let outcome = match email_client
+        .send_email(
+            &delivery.subscriber_email,
+            &delivery.title,
+            &with_unsubscribe_link(&html_content, &unsubscribe_url),
+            &format!(
+                "{}\n\nUnsubscribe: {unsubscribe_url}\n",
+                delivery.text_content
+            ),
+            &[
+                EmailHeader {
+                    name: "List-Unsubscribe",
+                    value: &list_unsubscribe,
+                },
+                EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                },
+            ],
+        )
+        .await
+    {
+        Ok(()) => DeliveryOutcome::Sent,
+        Err(e) => {
+            tracing::warn!("Failed to send the issue: {e}");
+            DeliveryOutcome::Failed(e.to_string())
+        }
+    };
+    record_outcome(
+        &mut *transaction,
+        delivery.issue_id,
+        delivery.subscriber_id,
+        &outcome,
+    )
+    .await?;
+    let done = sqlx::query_scalar!(
+        r#"
+        select not exists (
+            select 1 from issue_delivery where issue_id = $1 and status = 'queued'
+        ) as "done!"
+        "#,
+        delivery.issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    if done {
+        finish_send(&mut *transaction, delivery.issue_id).await?;
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::Delivered)
+}
+
+/// Adds a paragraph with the unsubscribe link at the end of the body of
+/// `html`
+fn with_unsubscribe_link(html: &str, unsubscribe_url: &str) -> String {
+    let paragraph = format!(
+        r#"<p><a href="{}">Unsubscribe</a></p>"#,
+        escape(unsubscribe_url)
+    );
+    let mut html = html.to_string();
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(index) => html.insert_str(index, &paragraph),
+        None => html.push_str(&paragraph),
+    }
+    html
+}
+
+/// Delivers the queued issues in the background for as long as the process
+/// runs
+pub fn spawn_worker(
+    pool: PgPool,
+    email_client: EmailClient,
+    links: SubscriptionLinks,
+    tracker: Tracker,
+) {
+    tokio::spawn(async move {
+        loop {
+            match deliver_next(&pool, &email_client, &links, &tracker).await {
+                Ok(ExecutionOutcome::Delivered) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(IDLE_INTERVAL).await,
+                Err(e) => {
+                    tracing::error!("Failed to deliver the queued issues: {:?}", e);
+                    tokio::time::sleep(ERROR_INTERVAL).await;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,40 @@
+/// How often a subscriber is willing to receive an issue, stored in
+/// `subscriber.frequency`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailFrequency {
+    EveryIssue,
+    /// At most one issue every 7 days
+    Weekly,
+    /// At most one issue every 30 days
+    Monthly,
+}
+
+impl EmailFrequency {
+    pub const ALL: [Self; 3] = [Self::EveryIssue, Self::Weekly, Self::Monthly];
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::EveryIssue => "every_issue",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    /// Shown to subscribers in the preference centre
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::EveryIssue => "Every issue",
+            Self::Weekly => "At most one issue a week",
+            Self::Monthly => "At most one issue a month",
+        }
+    }
+
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == value)
+    }
+}
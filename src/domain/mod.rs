@@ -1,7 +1,11 @@
+mod email_frequency;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_status;
 
+pub use email_frequency::EmailFrequency;
 pub use new_subscriber::CreateSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_status::SubscriberStatus;
@@ -1,10 +1,22 @@
 use serde::Deserialize;
+use utoipa::ToSchema;
 
 use crate::domain::subscriber_email::SubscriberEmail;
 use crate::domain::subscriber_name::SubscriberName;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct CreateSubscriber {
     pub name: SubscriberName,
     pub email: SubscriberEmail,
+    /// Honeypot field hidden from humans, only bots fill it in
+    #[serde(default)]
+    pub website: Option<String>,
+    /// When the signup form was rendered, as issued by the server in the
+    /// form: a Unix timestamp in milliseconds, a dot and its signature
+    #[serde(default)]
+    pub rendered_at: Option<String>,
+    /// Token produced by the challenge widget on the signup form. Plain HTML
+    /// forms post it under the name the widget gives its hidden field.
+    #[serde(default, alias = "cf-turnstile-response", alias = "h-captcha-response")]
+    pub challenge_token: Option<String>,
 }
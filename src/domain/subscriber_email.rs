@@ -1,10 +1,14 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
 use serde::Deserialize;
 use snafu::{Whatever, whatever};
+use utoipa::ToSchema;
 use validator::ValidateEmail;
 
-#[derive(Debug, Deserialize)]
+use crate::redaction;
+
+#[derive(Deserialize, ToSchema)]
+#[schema(example = "ursula@domain.com", format = Email)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
@@ -29,9 +33,18 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+/// Redacted according to the configured mode, use `as_ref` for the address
 impl Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_ref())
+        write!(f, "{}", redaction::email(self.as_ref()))
+    }
+}
+
+impl Debug for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SubscriberEmail")
+            .field(&redaction::email(self.as_ref()))
+            .finish()
     }
 }
 
@@ -66,6 +79,13 @@ mod tests {
         assert!(SubscriberEmail::parse(email).is_ok());
     }
 
+    #[test]
+    fn email_is_redacted_when_formatted() {
+        let email = SubscriberEmail::parse("ursula@domain.com".to_string()).unwrap();
+        assert!(!format!("{email} {email:?}").contains("ursula"));
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
@@ -1,15 +1,28 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
 use serde::Deserialize;
 use snafu::{Whatever, prelude::*};
+use utoipa::ToSchema;
 use validator::ValidateLength;
 
-#[derive(Debug, Deserialize)]
+use crate::redaction;
+
+#[derive(Deserialize, ToSchema)]
+#[schema(example = "Ursula Le Guin")]
 pub struct SubscriberName(String);
 
+/// Redacted according to the configured mode, use `as_ref` for the name
 impl Display for SubscriberName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_ref())
+        write!(f, "{}", redaction::name(self.as_ref()))
+    }
+}
+
+impl Debug for SubscriberName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SubscriberName")
+            .field(&redaction::name(self.as_ref()))
+            .finish()
     }
 }
 
@@ -0,0 +1,28 @@
+/// Where a subscriber is in the double opt-in lifecycle, stored in
+/// `subscriber.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberStatus {
+    /// Signed up from the hosted pages, waiting for the address to be confirmed
+    Pending,
+    /// Receives the issues
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriberStatus {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+        }
+    }
+
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        [Self::Pending, Self::Confirmed, Self::Unsubscribed]
+            .into_iter()
+            .find(|status| status.as_str() == value)
+    }
+}
@@ -0,0 +1,88 @@
+use std::{sync::Arc, time::Duration};
+
+use secrecy::{ExposeSecret, SecretBox};
+use serde::Serialize;
+
+use crate::configuration::EmailClientSettings;
+
+/// Header carrying the token of the email API
+const TOKEN_HEADER: &str = "X-Postmark-Server-Token";
+
+/// Sends emails through a Postmark compatible HTTP API
+#[derive(Clone)]
+pub struct EmailClient {
+    http_client: reqwest::Client,
+    base_url: String,
+    sender: String,
+    authorization_token: Arc<SecretBox<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+/// A header added to the email, e.g. `List-Unsubscribe`
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+impl EmailClient {
+    /// # Panics
+    ///
+    /// If the HTTP client cannot be built
+    #[must_use]
+    pub fn from_settings(settings: &EmailClientSettings) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .expect("Failed to build the email HTTP client");
+        Self {
+            http_client,
+            base_url: settings.base_url.trim_end_matches('/').to_string(),
+            sender: settings.sender_email.clone(),
+            authorization_token: Arc::new(SecretBox::new(Box::new(
+                settings.authorization_token.expose_secret().clone(),
+            ))),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// The API could not be reached or refused the email
+    #[tracing::instrument(name = "Sending an email", skip_all)]
+    pub async fn send_email(
+        &self,
+        recipient: &str,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<(), reqwest::Error> {
+        self.http_client
+            .post(format!("{}/email", self.base_url))
+            .header(TOKEN_HEADER, self.authorization_token.expose_secret())
+            .json(&SendEmailRequest {
+                from: &self.sender,
+                to: recipient,
+                subject,
+                html_body: html_content,
+                text_body: text_content,
+                headers,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
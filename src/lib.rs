@@ -1,5 +1,29 @@
+pub mod admin;
+pub mod api_keys;
+pub mod bot_protection;
+pub mod cli;
 pub mod configuration;
+pub mod dashboard;
+pub mod delivery;
 pub mod domain;
+pub mod email_client;
+pub mod maintenance;
+pub mod openapi;
+pub mod pages;
+pub mod problem;
+pub mod prometheus;
+pub mod rate_limit;
+pub mod redaction;
+pub mod request_id;
+pub mod request_limits;
+pub mod roles;
 pub mod routes;
+pub mod security_headers;
+pub mod sessions;
 pub mod startup;
+pub mod subscriber_csv;
+pub mod subscription_links;
 pub mod telemetry;
+pub mod tls;
+pub mod tracking;
+pub mod webhooks;
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use crate::configuration::Settings;
+use crate::rate_limit;
+use crate::sessions;
+
+/// How often expired rows are deleted
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically deletes rows that expired, so the tables they live in do not
+/// grow without bound, and creates next month's engagement event partition
+pub fn spawn_sweeper(pool: PgPool, configuration: &Settings) {
+    let limits = &configuration.application.rate_limit;
+    let login_limits = &configuration.admin.login_rate_limit;
+    let longest_window = [
+        &limits.per_ip,
+        &limits.per_email,
+        &login_limits.per_ip,
+        &login_limits.per_username,
+    ]
+    .into_iter()
+    .map(|limit| limit.window_seconds)
+    .max()
+    .unwrap_or_default();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match rate_limit::delete_expired_windows(&pool, longest_window).await {
+                Ok(deleted) => tracing::debug!(deleted, "Swept expired rate limit windows"),
+                Err(e) => tracing::error!("Failed to sweep the rate limit windows: {:?}", e),
+            }
+            match sessions::delete_expired_sessions(&pool).await {
+                Ok(deleted) => tracing::debug!(deleted, "Swept expired dashboard sessions"),
+                Err(e) => tracing::error!("Failed to sweep the dashboard sessions: {:?}", e),
+            }
+            if let Err(e) = create_next_engagement_partition(&pool).await {
+                tracing::error!("Failed to create the engagement event partition: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Events land in the default partition until their month's one exists
+async fn create_next_engagement_partition(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!("select create_engagement_event_partition(now() + interval '1 month')")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
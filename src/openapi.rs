@@ -0,0 +1,51 @@
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::routes;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Newsletter API"),
+    paths(
+        routes::health_check,
+        routes::metrics,
+        routes::openapi_json,
+        routes::api_docs,
+        routes::create_subscriber,
+        routes::issue_engagement,
+        routes::issue_delivery_report,
+        routes::list_subscribers,
+        routes::add_confirmed_subscriber,
+        routes::publish_issue,
+        routes::create_key,
+        routes::list_keys,
+        routes::revoke_key,
+        routes::track_open,
+        routes::track_click,
+    ),
+    tags(
+        (name = "health", description = "Service status"),
+        (name = "metrics", description = "Prometheus scrape target"),
+        (name = "docs", description = "This document"),
+        (name = "subscribers", description = "Newsletter signups"),
+        (name = "admin", description = "Integrations, authenticated with a scoped API key"),
+        (name = "tracking", description = "Links embedded in sent emails"),
+    ),
+    modifiers(&ApiKeyAuth)
+)]
+pub struct ApiDoc;
+
+/// Keys created with `z2p_axum create-api-key` or `POST /api/v1/admin/api-keys`
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "api_key",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+    }
+}
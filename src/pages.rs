@@ -0,0 +1,418 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use crate::configuration::{ChallengeProvider, Settings, ThemeSettings};
+use crate::domain::EmailFrequency;
+
+/// Values typed into the subscribe form, echoed back when it has errors
+#[derive(Default)]
+pub struct SubscribeFormValues<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+}
+
+/// Problems shown next to the fields of the subscribe and preference forms,
+/// or above them when they are not about a single field
+#[derive(Default)]
+pub struct FormErrors {
+    pub name: Option<&'static str>,
+    pub email: Option<&'static str>,
+    pub form: Option<&'static str>,
+}
+
+/// Current preferences of a subscriber, or the ones they just submitted
+pub struct PreferenceValues<'a> {
+    pub name: &'a str,
+    pub frequency: EmailFrequency,
+    pub paused_until: Option<DateTime<Utc>>,
+}
+
+/// Offered in the preference centre, any number of days up to
+/// `MAX_PAUSE_DAYS` is accepted
+const PAUSE_OPTIONS: [(i32, &str); 3] = [(7, "1 week"), (30, "1 month"), (90, "3 months")];
+
+pub const MAX_PAUSE_DAYS: i32 = 365;
+
+/// An email sent by the service itself rather than through an issue
+pub struct Email {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Renders the hosted subscription pages with the configured theme
+#[derive(Clone)]
+pub struct Pages {
+    theme: Arc<ThemeSettings>,
+    challenge: Option<Challenge>,
+}
+
+#[derive(Clone)]
+enum Challenge {
+    /// Local development, the token is typed in
+    Stub,
+    Turnstile {
+        site_key: String,
+    },
+    Hcaptcha {
+        site_key: String,
+    },
+}
+
+impl Pages {
+    #[must_use]
+    pub fn from_settings(settings: &Settings) -> Self {
+        let challenge = &settings.bot_protection.challenge;
+        let challenge = match (challenge.provider, challenge.site_key.clone()) {
+            (ChallengeProvider::Stub, _) => Some(Challenge::Stub),
+            (ChallengeProvider::Turnstile, Some(site_key)) => {
+                Some(Challenge::Turnstile { site_key })
+            }
+            (ChallengeProvider::Hcaptcha, Some(site_key)) => Some(Challenge::Hcaptcha { site_key }),
+            _ => None,
+        };
+        Self {
+            theme: Arc::new(settings.pages.theme.clone()),
+            challenge,
+        }
+    }
+
+    /// The signup form. `rendered_at`, signed by `BotProtection`, feeds the
+    /// minimum submit time check.
+    #[must_use]
+    pub fn subscribe(
+        &self,
+        values: &SubscribeFormValues<'_>,
+        errors: &FormErrors,
+        rendered_at: &str,
+    ) -> String {
+        let mut content = String::new();
+        if let Some(error) = errors.form {
+            let _ = write!(content, r#"<p class="error" role="alert">{error}</p>"#);
+        }
+        content.push_str(r#"<form method="post" action="/subscribe" novalidate>"#);
+        field(
+            &mut content,
+            "name",
+            "text",
+            "Name",
+            values.name,
+            errors.name,
+        );
+        field(
+            &mut content,
+            "email",
+            "email",
+            "Email",
+            values.email,
+            errors.email,
+        );
+        // Hidden from humans, see `bot_protection`
+        content.push_str(
+            r#"<div class="trap" aria-hidden="true"><label>Website <input name="website" tabindex="-1" autocomplete="off"></label></div>"#,
+        );
+        let _ = write!(
+            content,
+            r#"<input type="hidden" name="rendered_at" value="{}">"#,
+            escape(rendered_at)
+        );
+        match &self.challenge {
+            Some(Challenge::Stub) => field(
+                &mut content,
+                "challenge_token",
+                "text",
+                "Challenge token",
+                "",
+                None,
+            ),
+            Some(Challenge::Turnstile { site_key }) => {
+                let _ = write!(
+                    content,
+                    r#"<div class="cf-turnstile" data-sitekey="{}"></div><script src="https://challenges.cloudflare.com/turnstile/v0/api.js" async defer></script>"#,
+                    escape(site_key)
+                );
+            }
+            Some(Challenge::Hcaptcha { site_key }) => {
+                let _ = write!(
+                    content,
+                    r#"<div class="h-captcha" data-sitekey="{}"></div><script src="https://js.hcaptcha.com/1/api.js" async defer></script>"#,
+                    escape(site_key)
+                );
+            }
+            None => {}
+        }
+        content.push_str(r#"<button type="submit">Subscribe</button></form>"#);
+        self.layout("Subscribe", &content)
+    }
+
+    #[must_use]
+    pub fn check_inbox(&self) -> String {
+        self.layout(
+            "Check your inbox",
+            "<p>We sent you an email with a link to confirm your subscription.</p>",
+        )
+    }
+
+    /// The email asking `name` to follow `confirmation_url`
+    #[must_use]
+    pub fn confirmation_email(&self, name: &str, confirmation_url: &str) -> Email {
+        let site_name = &self.theme.site_name;
+        Email {
+            subject: format!("Confirm your subscription to {site_name}"),
+            html: format!(
+                r#"<p>Hi {},</p><p>Please <a href="{}">confirm your subscription</a> to {}.</p><p>If you did not sign up, you can ignore this email.</p>"#,
+                escape(name),
+                escape(confirmation_url),
+                escape(site_name)
+            ),
+            text: format!(
+                "Hi {name},\n\nPlease confirm your subscription to {site_name}:\n{confirmation_url}\n\nIf you did not sign up, you can ignore this email.\n"
+            ),
+        }
+    }
+
+    #[must_use]
+    pub fn confirmed(&self) -> String {
+        self.layout(
+            "Subscription confirmed",
+            "<p>Thanks, you will receive the next issue.</p>",
+        )
+    }
+
+    /// Asks before unsubscribing, so link scanners following the link do
+    /// not unsubscribe anyone. The form posts back to the same signed URL.
+    #[must_use]
+    pub fn unsubscribe(&self) -> String {
+        self.layout(
+            "Unsubscribe",
+            r#"<p>You will no longer receive any issue.</p><form method="post"><button type="submit">Unsubscribe</button></form>"#,
+        )
+    }
+
+    #[must_use]
+    pub fn unsubscribed(&self) -> String {
+        self.layout(
+            "You are unsubscribed",
+            "<p>Sorry to see you go, you will not receive any more issues.</p>",
+        )
+    }
+
+    /// The preference centre. The form posts back to the same signed URL.
+    #[must_use]
+    pub fn preferences(
+        &self,
+        values: &PreferenceValues<'_>,
+        errors: &FormErrors,
+        saved: bool,
+        unsubscribe_url: &str,
+    ) -> String {
+        let mut content = String::new();
+        if saved {
+            content.push_str(r#"<p class="notice" role="status">Your preferences were saved.</p>"#);
+        }
+        if let Some(error) = errors.form {
+            let _ = write!(content, r#"<p class="error" role="alert">{error}</p>"#);
+        }
+        content.push_str(r#"<form method="post" novalidate>"#);
+        field(
+            &mut content,
+            "name",
+            "text",
+            "Name",
+            values.name,
+            errors.name,
+        );
+
+        content.push_str(
+            r#"<label for="frequency">Emails</label><select id="frequency" name="frequency">"#,
+        );
+        for frequency in EmailFrequency::ALL {
+            let selected = if frequency == values.frequency {
+                " selected"
+            } else {
+                ""
+            };
+            let _ = write!(
+                content,
+                r#"<option value="{}"{selected}>{}</option>"#,
+                frequency.as_str(),
+                frequency.label()
+            );
+        }
+        content.push_str("</select>");
+
+        let paused_until = values.paused_until.filter(|until| *until > Utc::now());
+        content.push_str(r#"<label for="pause_days">Pause delivery</label><select id="pause_days" name="pause_days">"#);
+        match paused_until {
+            Some(until) => {
+                let _ = write!(
+                    content,
+                    r#"<option value="" selected>Paused until {}</option><option value="0">Resume now</option>"#,
+                    until.format("%B %-d, %Y")
+                );
+            }
+            None => content.push_str(r#"<option value="" selected>Do not pause</option>"#),
+        }
+        for (days, label) in PAUSE_OPTIONS {
+            let _ = write!(content, r#"<option value="{days}">For {label}</option>"#);
+        }
+        content.push_str(r#"</select><button type="submit">Save preferences</button></form>"#);
+        let _ = write!(
+            content,
+            r#"<p><a href="{}">Unsubscribe from all issues</a></p>"#,
+            escape(unsubscribe_url)
+        );
+        self.layout("Your preferences", &content)
+    }
+
+    #[must_use]
+    pub fn invalid_link(&self) -> String {
+        self.layout(
+            "Invalid link",
+            "<p>This link is invalid or no longer valid. Please use the link from your most recent email.</p>",
+        )
+    }
+
+    fn layout(&self, title: &str, content: &str) -> String {
+        self.render(title, content, "")
+    }
+
+    /// Wraps `content` in the themed page. `class` is set on `<main>`,
+    /// `wide` fits tables.
+    pub(crate) fn render(&self, title: &str, content: &str, class: &str) -> String {
+        let theme = &*self.theme;
+        let site_name = escape(&theme.site_name);
+        let stylesheet = theme
+            .stylesheet_url
+            .as_deref()
+            .map(|url| format!(r#"<link rel="stylesheet" href="{}">"#, escape(url)))
+            .unwrap_or_default();
+        let logo = theme
+            .logo_url
+            .as_deref()
+            .map(|url| {
+                format!(
+                    r#"<img class="logo" src="{}" alt="{site_name}">"#,
+                    escape(url)
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title} · {site_name}</title>
+    <style>
+      :root {{ --primary: {primary}; --background: {background}; --text: {text}; }}
+      body {{ margin: 0; font-family: system-ui, sans-serif; background: var(--background); color: var(--text); }}
+      main {{ max-width: 28rem; margin: 4rem auto; padding: 0 1rem; }}
+      main.wide {{ max-width: 64rem; margin-top: 2rem; }}
+      .logo {{ max-height: 3rem; }}
+      label {{ display: block; margin-top: 1rem; }}
+      input, select, textarea {{ display: block; width: 100%; box-sizing: border-box; padding: 0.5rem; margin-top: 0.25rem; }}
+      button {{ margin-top: 1.5rem; padding: 0.6rem 1.2rem; border: 0; border-radius: 0.3rem; background: var(--primary); color: #fff; cursor: pointer; }}
+      .error {{ color: #b91c1c; margin: 0.25rem 0 0; }}
+      .notice {{ color: var(--primary); }}
+      nav {{ display: flex; gap: 1rem; align-items: baseline; }}
+      nav form {{ margin-left: auto; }}
+      nav button {{ margin: 0; }}
+      table {{ width: 100%; border-collapse: collapse; margin-top: 1rem; }}
+      th, td {{ text-align: left; padding: 0.4rem; border-bottom: 1px solid #cbd5e1; }}
+      .trap {{ position: absolute; left: -10000px; }}
+    </style>
+    {stylesheet}
+  </head>
+  <body>
+    <main class="{class}">
+      {logo}
+      <h1>{title}</h1>
+      {content}
+    </main>
+  </body>
+</html>
+"#,
+            primary = theme.primary_color,
+            background = theme.background_color,
+            text = theme.text_color,
+        )
+    }
+}
+
+pub(crate) fn field(
+    content: &mut String,
+    name: &str,
+    kind: &str,
+    label: &str,
+    value: &str,
+    error: Option<&str>,
+) {
+    let _ = write!(
+        content,
+        r#"<label for="{name}">{label}</label><input id="{name}" name="{name}" type="{kind}" value="{}" required"#,
+        escape(value)
+    );
+    match error {
+        Some(error) => {
+            let _ = write!(
+                content,
+                r#" aria-invalid="true" aria-describedby="{name}-error"><p class="error" id="{name}-error">{error}</p>"#
+            );
+        }
+        None => content.push('>'),
+    }
+}
+
+/// Escapes text for HTML content and quoted attribute values
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FormErrors, Pages, SubscribeFormValues, escape};
+    use crate::configuration::get_configuration;
+
+    #[test]
+    fn values_are_escaped() {
+        assert_eq!(
+            escape(r#"<script>"a" & 'b'</script>"#),
+            "&lt;script&gt;&quot;a&quot; &amp; &#39;b&#39;&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn field_errors_are_shown_next_to_the_field() {
+        let pages = Pages::from_settings(&get_configuration().unwrap());
+        let html = pages.subscribe(
+            &SubscribeFormValues {
+                name: "<b>Ursula</b>",
+                email: "ursula",
+            },
+            &FormErrors {
+                email: Some("Please enter a valid email address"),
+                ..FormErrors::default()
+            },
+            "0.00",
+        );
+        assert!(html.contains(r#"value="&lt;b&gt;Ursula&lt;/b&gt;""#));
+        assert!(html.contains(
+            r#"aria-invalid="true" aria-describedby="email-error"><p class="error" id="email-error">Please enter a valid email address</p>"#
+        ));
+        assert!(!html.contains("name-error"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use axum::{body::Body, extract::MatchedPath, middleware::Next, response::Response};
+use hyper::Request;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::PgPool;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+const REQUEST_DURATION: &str = "http_request_duration_seconds";
+const POOL_ACQUIRE_DURATION: &str = "db_pool_acquire_duration_seconds";
+
+/// Latency buckets, in seconds, shared by the request and pool histograms
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Installs the global metrics recorder on first use and returns a handle
+/// to render it. Every app in the process shares the same recorder.
+///
+/// # Panics
+///
+/// If another global recorder was already installed.
+pub fn recorder() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Full(REQUEST_DURATION.to_string()),
+                    &LATENCY_BUCKETS,
+                )
+                .and_then(|builder| {
+                    builder.set_buckets_for_metric(
+                        Matcher::Full(POOL_ACQUIRE_DURATION.to_string()),
+                        &LATENCY_BUCKETS,
+                    )
+                })
+                .and_then(PrometheusBuilder::install_recorder)
+                .expect("Failed to install the metrics recorder")
+        })
+        .clone()
+}
+
+/// Counts requests and records their latency, labelled by method, matched
+/// route and status. Unmatched requests are not recorded to keep the label
+/// cardinality bounded.
+pub async fn track_requests(request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(path) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!(REQUEST_DURATION, &labels).record(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Snapshots the pool size and idle connections, called on every scrape.
+pub fn record_pool_metrics(pool: &PgPool) {
+    metrics::gauge!("db_pool_connections").set(pool.size());
+    metrics::gauge!("db_pool_idle_connections")
+        .set(u32::try_from(pool.num_idle()).unwrap_or(u32::MAX));
+    metrics::gauge!("db_pool_max_connections").set(pool.options().get_max_connections());
+}
+
+/// Records `db_pool_acquire_duration_seconds` from the event the pool emits
+/// on every acquire, so queries run straight on the pool are measured too.
+/// Needs the pool built with an `acquire_time_level`.
+pub struct PoolAcquireLayer;
+
+impl PoolAcquireLayer {
+    /// Target of the events emitted by the sqlx pool
+    pub const TARGET: &'static str = "sqlx::pool::acquire";
+}
+
+impl<S: Subscriber> Layer<S> for PoolAcquireLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != Self::TARGET {
+            return;
+        }
+        let mut visitor = AcquiredAfter(None);
+        event.record(&mut visitor);
+        if let Some(seconds) = visitor.0 {
+            metrics::histogram!(POOL_ACQUIRE_DURATION).record(seconds);
+        }
+    }
+}
+
+/// Reads the wait, in seconds, out of an acquire event
+struct AcquiredAfter(Option<f64>);
+
+impl Visit for AcquiredAfter {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        // Spelled this way by sqlx
+        if field.name() == "aquired_after_secs" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
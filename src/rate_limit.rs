@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::PgPool;
+use url::form_urlencoded;
+
+use crate::configuration::{
+    LimitSettings, LoginRateLimitSettings, RateLimitBackend, RateLimitSettings,
+};
+use crate::redaction;
+
+/// Expired in-memory windows are only pruned once more keys than this are held
+const IN_MEMORY_PRUNE_THRESHOLD: usize = 10_000;
+
+enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[derive(Clone)]
+enum Backend {
+    InMemory(Arc<Mutex<HashMap<String, (Instant, u32)>>>),
+    /// Shares the counters between every replica using the same database
+    Postgres(PgPool),
+}
+
+/// Fixed-window rate limiter for the public subscribe endpoint, keyed on the
+/// client IP and on the email being subscribed. Keys are stored hashed.
+#[derive(Clone)]
+pub struct RateLimiter {
+    backend: Backend,
+    per_ip: LimitSettings,
+    per_email: LimitSettings,
+    trusted_proxies: Arc<[IpAddr]>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn from_settings(settings: &RateLimitSettings, pool: PgPool) -> Self {
+        let backend = match settings.backend {
+            RateLimitBackend::Memory => Backend::InMemory(Arc::default()),
+            RateLimitBackend::Postgres => Backend::Postgres(pool),
+        };
+        Self {
+            backend,
+            per_ip: settings.per_ip.clone(),
+            per_email: settings.per_email.clone(),
+            trusted_proxies: settings.trusted_proxies.clone().into(),
+        }
+    }
+
+    /// The client behind the trusted proxies, read from `X-Forwarded-For`
+    /// right to left, or the peer itself when it is not a trusted proxy
+    #[must_use]
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let mut client = peer?;
+        if !self.trusted_proxies.contains(&client) {
+            return Some(client);
+        }
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.trusted_proxies.contains(&ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    async fn hit(&self, key: &str, limit: &LimitSettings) -> Result<Decision, sqlx::Error> {
+        let window = Duration::from_secs(limit.window_seconds);
+        let (hits, remaining) = match &self.backend {
+            Backend::InMemory(windows) => hit_in_memory(windows, key, window),
+            Backend::Postgres(pool) => {
+                #[allow(clippy::cast_precision_loss)]
+                let window_seconds = limit.window_seconds as f64;
+                let record = sqlx::query!(
+                    r#"
+                    insert into rate_limit_bucket (key, window_start, hits)
+                    values ($1, now(), 1)
+                    on conflict (key) do update set
+                        window_start = case
+                            when rate_limit_bucket.window_start + make_interval(secs => $2) <= now()
+                            then now()
+                            else rate_limit_bucket.window_start
+                        end,
+                        hits = case
+                            when rate_limit_bucket.window_start + make_interval(secs => $2) <= now()
+                            then 1
+                            else rate_limit_bucket.hits + 1
+                        end
+                    returning
+                        hits,
+                        extract(epoch from window_start + make_interval(secs => $2) - now())::float8
+                            as "remaining_seconds!"
+                    "#,
+                    key,
+                    window_seconds,
+                )
+                .fetch_one(pool)
+                .await?;
+                (
+                    u32::try_from(record.hits).unwrap_or(u32::MAX),
+                    Duration::try_from_secs_f64(record.remaining_seconds).unwrap_or_default(),
+                )
+            }
+        };
+        if hits > limit.requests {
+            Ok(Decision::Limited {
+                retry_after: remaining,
+            })
+        } else {
+            Ok(Decision::Allowed)
+        }
+    }
+
+    /// Checks every key in order, stopping at the first one over its limit.
+    /// Errors from the backend let the request through rather than locking
+    /// everyone out.
+    async fn check(&self, keys: &[(String, &LimitSettings)]) -> Decision {
+        for (key, limit) in keys {
+            match self.hit(key, limit).await {
+                Ok(Decision::Allowed) => {}
+                Ok(limited) => return limited,
+                Err(e) => tracing::error!("Failed to check rate limit: {:?}", e),
+            }
+        }
+        Decision::Allowed
+    }
+}
+
+/// Limits dashboard logins per client IP and per username, checked before
+/// the password is hashed. Shares the backend of the subscribe limiter.
+#[derive(Clone)]
+pub struct LoginLimiter {
+    limiter: RateLimiter,
+    per_ip: LimitSettings,
+    per_username: LimitSettings,
+}
+
+impl LoginLimiter {
+    #[must_use]
+    pub fn new(limiter: RateLimiter, settings: &LoginRateLimitSettings) -> Self {
+        Self {
+            limiter,
+            per_ip: settings.per_ip.clone(),
+            per_username: settings.per_username.clone(),
+        }
+    }
+
+    /// Counts a login attempt, returning whether it may go on
+    pub async fn allow(&self, peer: Option<IpAddr>, headers: &HeaderMap, username: &str) -> bool {
+        let client_ip = self.limiter.client_ip(peer, headers);
+        let client = client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        let keys = [
+            (
+                format!("login_ip:{}", redaction::fingerprint(&client)),
+                &self.per_ip,
+            ),
+            (
+                format!(
+                    "login_user:{}",
+                    redaction::fingerprint(&username.to_lowercase())
+                ),
+                &self.per_username,
+            ),
+        ];
+        if let Decision::Limited { .. } = self.limiter.check(&keys).await {
+            tracing::warn!(
+                client_ip = client_ip.map_or_else(|| "unknown".to_string(), redaction::ip),
+                "Login rate limit exceeded"
+            );
+            return false;
+        }
+        true
+    }
+}
+
+fn hit_in_memory(
+    windows: &Mutex<HashMap<String, (Instant, u32)>>,
+    key: &str,
+    window: Duration,
+) -> (u32, Duration) {
+    let now = Instant::now();
+    let mut windows = windows.lock().expect("rate limit mutex poisoned");
+    if windows.len() > IN_MEMORY_PRUNE_THRESHOLD {
+        windows.retain(|_, (start, _)| now.duration_since(*start) < window);
+    }
+    let (start, hits) = windows.entry(key.to_string()).or_insert((now, 0));
+    if now.duration_since(*start) >= window {
+        *start = now;
+        *hits = 0;
+    }
+    *hits += 1;
+    let (hits, elapsed) = (*hits, now.duration_since(*start));
+    drop(windows);
+    (hits, window.saturating_sub(elapsed))
+}
+
+/// Deletes the Postgres windows older than `longest_window_seconds`, which
+/// can no longer limit anyone. Returns how many were deleted.
+///
+/// # Errors
+///
+/// Database error
+pub async fn delete_expired_windows(
+    pool: &PgPool,
+    longest_window_seconds: u64,
+) -> Result<u64, sqlx::Error> {
+    #[allow(clippy::cast_precision_loss)]
+    let window_seconds = longest_window_seconds as f64;
+    let deleted = sqlx::query!(
+        "delete from rate_limit_bucket where window_start + make_interval(secs => $1) <= now()",
+        window_seconds,
+    )
+    .execute(pool)
+    .await?;
+    Ok(deleted.rows_affected())
+}
+
+/// Middleware limiting subscription attempts per client IP and per email.
+pub async fn limit_subscriptions(
+    State(limiter): State<RateLimiter>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let client_ip = limiter.client_ip(peer, request.headers());
+
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let is_form = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    let email = if is_form {
+        form_urlencoded::parse(&bytes)
+            .find(|(key, _)| key == "email")
+            .map(|(_, email)| email.to_lowercase())
+    } else {
+        serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|payload| payload["email"].as_str().map(str::to_lowercase))
+    };
+
+    let client = client_ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    let mut keys = vec![(
+        format!("ip:{}", redaction::fingerprint(&client)),
+        &limiter.per_ip,
+    )];
+    if let Some(email) = email {
+        keys.push((
+            format!("email:{}", redaction::fingerprint(&email)),
+            &limiter.per_email,
+        ));
+    }
+    if let Decision::Limited { retry_after } = limiter.check(&keys).await {
+        tracing::warn!(
+            client_ip = client_ip.map_or_else(|| "unknown".to_string(), redaction::ip),
+            "Rate limit exceeded"
+        );
+        let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.max(1).to_string())],
+            "Too many requests",
+        )
+            .into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
@@ -0,0 +1,179 @@
+use std::net::IpAddr;
+use std::sync::{LazyLock, OnceLock};
+
+use hmac::{Hmac, Mac};
+use miette::Diagnostic;
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use snafu::Snafu;
+
+use crate::configuration::{RedactionMode, RedactionSettings};
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// Applied until `init` runs, e.g. in unit tests
+static UNCONFIGURED: LazyLock<Redactor> =
+    LazyLock::new(|| Redactor::new(RedactionMode::Mask, SecretBox::default()));
+
+/// Sets the process-wide redaction applied when personal data is formatted.
+/// Values formatted before are masked.
+///
+/// # Errors
+///
+/// The redaction was already set by an earlier call
+pub fn init(settings: &RedactionSettings) -> Result<(), AlreadyInitialised> {
+    REDACTOR
+        .set(Redactor::new(
+            settings.mode,
+            SecretBox::new(Box::new(settings.key.expose_secret().clone())),
+        ))
+        .map_err(|_| AlreadyInitialised)
+}
+
+#[derive(Debug, Snafu, Diagnostic)]
+#[snafu(display("The redaction of personal data is already configured"))]
+pub struct AlreadyInitialised;
+
+fn current() -> &'static Redactor {
+    REDACTOR.get().unwrap_or(&UNCONFIGURED)
+}
+
+/// Formats an email address according to the configured redaction mode
+#[must_use]
+pub fn email(value: &str) -> String {
+    current().email(value)
+}
+
+/// Formats a person's name according to the configured redaction mode
+#[must_use]
+pub fn name(value: &str) -> String {
+    current().name(value)
+}
+
+/// Formats a client IP according to the configured redaction mode
+#[must_use]
+pub fn ip(value: IpAddr) -> String {
+    current().ip(value)
+}
+
+/// Keyed hash of `value` whatever the redaction mode, for identifiers that
+/// are stored but never read back, e.g. rate limit keys
+#[must_use]
+pub fn fingerprint(value: &str) -> String {
+    hex::encode(current().mac(value))
+}
+
+struct Redactor {
+    mode: RedactionMode,
+    key: SecretBox<String>,
+}
+
+impl Redactor {
+    const fn new(mode: RedactionMode, key: SecretBox<String>) -> Self {
+        Self { mode, key }
+    }
+
+    /// Masking keeps the domain, hashing ignores case so an address is
+    /// always hashed the same way.
+    fn email(&self, value: &str) -> String {
+        match self.mode {
+            RedactionMode::Off => value.to_string(),
+            RedactionMode::Mask => match value.rsplit_once('@') {
+                Some((local, domain)) => format!("{}@{domain}", mask(local)),
+                None => mask(value),
+            },
+            RedactionMode::Hash => self.hash(&value.to_lowercase()),
+        }
+    }
+
+    fn name(&self, value: &str) -> String {
+        match self.mode {
+            RedactionMode::Off => value.to_string(),
+            RedactionMode::Mask => mask(value),
+            RedactionMode::Hash => self.hash(value),
+        }
+    }
+
+    /// Masking keeps the network part of the address
+    fn ip(&self, value: IpAddr) -> String {
+        match (self.mode, value) {
+            (RedactionMode::Off, _) => value.to_string(),
+            (RedactionMode::Mask, IpAddr::V4(ip)) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{a}.{b}.{c}.*")
+            }
+            (RedactionMode::Mask, IpAddr::V6(ip)) => {
+                let [a, b, c, ..] = ip.segments();
+                format!("{a:x}:{b:x}:{c:x}:*")
+            }
+            (RedactionMode::Hash, _) => self.hash(&value.to_string()),
+        }
+    }
+
+    fn hash(&self, value: &str) -> String {
+        format!("hmac:{}", hex::encode(&self.mac(value)[..8]))
+    }
+
+    fn mac(&self, value: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.key.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Keeps the first character only
+fn mask(value: &str) -> String {
+    value
+        .chars()
+        .next()
+        .map_or_else(String::new, |first| format!("{first}***"))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretBox;
+
+    use super::Redactor;
+    use crate::configuration::RedactionMode;
+
+    fn redactor(mode: RedactionMode) -> Redactor {
+        Redactor::new(mode, SecretBox::new(Box::new("redaction-key".to_string())))
+    }
+
+    #[test]
+    fn off_keeps_values() {
+        let redactor = redactor(RedactionMode::Off);
+        assert_eq!(redactor.email("ursula@domain.com"), "ursula@domain.com");
+        assert_eq!(redactor.name("Ursula Le Guin"), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn mask_keeps_the_first_character_and_the_domain() {
+        let redactor = redactor(RedactionMode::Mask);
+        assert_eq!(redactor.email("ursula@domain.com"), "u***@domain.com");
+        assert_eq!(redactor.email("not-an-email"), "n***");
+        assert_eq!(redactor.name("Ursula Le Guin"), "U***");
+        assert_eq!(redactor.name(""), "");
+        assert_eq!(redactor.ip("203.0.113.42".parse().unwrap()), "203.0.113.*");
+        assert_eq!(
+            redactor.ip("2001:db8:85a3::8a2e:370:7334".parse().unwrap()),
+            "2001:db8:85a3:*"
+        );
+    }
+
+    #[test]
+    fn hash_is_stable_and_keyed() {
+        let redactor = redactor(RedactionMode::Hash);
+        let hashed = redactor.email("ursula@domain.com");
+        assert!(!hashed.contains("ursula"));
+        assert_eq!(hashed, redactor.email("Ursula@Domain.com"));
+        assert_ne!(hashed, redactor.email("ursula@domain.org"));
+
+        let other_key = Redactor::new(
+            RedactionMode::Hash,
+            SecretBox::new(Box::new("another-key".to_string())),
+        );
+        assert_ne!(hashed, other_key.email("ursula@domain.com"));
+    }
+}
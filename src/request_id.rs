@@ -0,0 +1,138 @@
+use axum::{
+    body::{Body, Full, boxed},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::{Request, StatusCode};
+
+use crate::problem::PROBLEM_CONTENT_TYPE;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from a client
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+/// Drops a client request id that is not 1 to 64 ASCII letters, digits or
+/// dashes, so a new one is generated instead. Ids end up in logs and error
+/// pages, which must not be fed arbitrary text.
+pub async fn drop_invalid_request_id(mut request: Request<Body>, next: Next<Body>) -> Response {
+    let valid = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .is_none_or(|value| is_valid_request_id(value.as_bytes()));
+    if !valid {
+        tracing::debug!("Replacing an invalid request id");
+        request.headers_mut().remove(REQUEST_ID_HEADER);
+    }
+    next.run(request).await
+}
+
+fn is_valid_request_id(value: &[u8]) -> bool {
+    (1..=MAX_REQUEST_ID_LENGTH).contains(&value.len())
+        && value
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b'-')
+}
+
+/// Adds the request id to the body of error responses, so a complaint can
+/// be matched with the logs.
+///
+/// JSON objects and problem details gain a `request_id` field. HTML pages
+/// show the id at the end of their content. Plain text and empty bodies
+/// become `{"error": ..., "request_id": ...}` for clients accepting JSON,
+/// and gain a line with the id otherwise. Other content types are left
+/// alone.
+pub async fn include_request_id_in_errors(request: Request<Body>, next: Next<Body>) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let accepts_json = accepts_json(request.headers());
+    let response = next.run(request).await;
+    let Some(request_id) = request_id else {
+        return response;
+    };
+    if !(response.status().is_client_error() || response.status().is_server_error()) {
+        return response;
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let is_problem = content_type.starts_with(PROBLEM_CONTENT_TYPE);
+    let is_json = content_type.starts_with("application/json") || is_problem;
+    let is_html = content_type.starts_with("text/html");
+    if !(is_json || is_html || content_type.is_empty() || content_type.starts_with("text/plain")) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let message = String::from_utf8_lossy(&bytes);
+    let (body, rewritten_type) = if is_html {
+        (with_request_id_paragraph(&message, &request_id), None)
+    } else {
+        match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(serde_json::Value::Object(mut object)) if is_json => {
+                object.insert("request_id".to_string(), request_id.into());
+                (serde_json::Value::Object(object).to_string(), None)
+            }
+            _ => {
+                let message = if message.is_empty() {
+                    parts.status.canonical_reason().unwrap_or_default()
+                } else {
+                    &message
+                };
+                if accepts_json {
+                    let body = serde_json::json!({ "error": message, "request_id": request_id });
+                    (body.to_string(), Some("application/json"))
+                } else {
+                    (
+                        format!("{message}\nRequest id: {request_id}\n"),
+                        Some("text/plain; charset=utf-8"),
+                    )
+                }
+            }
+        }
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if let Some(content_type) = rewritten_type.filter(|_| !is_problem) {
+        parts
+            .headers
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    }
+    Response::from_parts(parts, boxed(Full::from(body)))
+}
+
+/// Whether the client takes JSON: API clients send no `Accept`, `*/*` or a
+/// JSON type, browsers ask for HTML first
+fn accepts_json(headers: &HeaderMap) -> bool {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    accept.is_empty()
+        || accept.contains("json")
+        || (accept.contains("*/*") && !accept.contains("text/html"))
+}
+
+/// Ids are validated or generated, so they need no escaping
+fn with_request_id_paragraph(html: &str, request_id: &str) -> String {
+    let paragraph = format!(r#"<p class="request-id">Request id: <code>{request_id}</code></p>"#);
+    let lowercase = html.to_ascii_lowercase();
+    let mut html = html.to_string();
+    match lowercase
+        .rfind("</main>")
+        .or_else(|| lowercase.rfind("</body>"))
+    {
+        Some(index) => html.insert_str(index, &paragraph),
+        None => html.push_str(&paragraph),
+    }
+    html
+}
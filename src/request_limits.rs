@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use axum::{
+    BoxError, Router,
+    body::{Body, HttpBody},
+    error_handling::HandleErrorLayer,
+    extract::State,
+    http::{Request, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use tower::ServiceBuilder;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::compression::CompressionLayer;
+
+use crate::configuration::{CompressionSettings, RequestLimitSettings};
+
+/// Bounds the size of request bodies, how long they take to arrive and to
+/// be handled, and how many requests are handled at once.
+///
+/// The body is buffered, so the middleware reading it further in do not
+/// need to bound it again.
+pub fn request_limits<S>(router: Router<S>, settings: RequestLimitSettings) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(middleware::from_fn_with_state(settings, limit_body))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(overloaded))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(
+                    settings.max_concurrent_requests,
+                ))
+                .timeout(Duration::from_millis(settings.request_timeout_milliseconds)),
+        )
+}
+
+/// Compresses the responses with the encodings enabled in `settings`
+#[must_use]
+pub fn compression(settings: &CompressionSettings) -> CompressionLayer {
+    CompressionLayer::new()
+        .gzip(settings.gzip)
+        .br(settings.br)
+        .zstd(settings.zstd)
+        .no_deflate()
+}
+
+async fn limit_body(
+    State(settings): State<RequestLimitSettings>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > settings.body_limit_bytes) {
+        return payload_too_large();
+    }
+
+    let (parts, mut body) = request.into_parts();
+    let mut bytes = Vec::with_capacity(declared.unwrap_or_default());
+    let read = async {
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            if bytes.len() + chunk.len() > settings.body_limit_bytes {
+                return Ok(false);
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok::<_, hyper::Error>(true)
+    };
+    match tokio::time::timeout(
+        Duration::from_millis(settings.body_timeout_milliseconds),
+        read,
+    )
+    .await
+    {
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => return payload_too_large(),
+        Ok(Err(_)) => return StatusCode::BAD_REQUEST.into_response(),
+        Err(_) => {
+            tracing::warn!("Request body was not received in time");
+            return (StatusCode::REQUEST_TIMEOUT, "Request body timed out").into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+fn payload_too_large() -> Response {
+    (StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large").into_response()
+}
+
+async fn overloaded(error: BoxError) -> Response {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        tracing::warn!("Request timed out");
+        (StatusCode::SERVICE_UNAVAILABLE, "Request timed out").into_response()
+    } else if error.is::<tower::load_shed::error::Overloaded>() {
+        tracing::warn!("Too many requests in flight, shedding load");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, "1")],
+            "Too many requests in flight",
+        )
+            .into_response()
+    } else {
+        tracing::error!("Unhandled error: {error}");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+}
@@ -0,0 +1,182 @@
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+
+use crate::problem::Problem;
+use crate::sessions::{AdminSession, Session};
+use crate::startup::AppState;
+
+/// What an admin user may do, stored in `admin_user.role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Everything, including managing the other users
+    Admin,
+    /// Reads the list and writes issues
+    Editor,
+    /// Only reads
+    Viewer,
+}
+
+/// What a dashboard page needs from the role of its user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    SubscribersRead,
+    IssuesRead,
+    IssuesWrite,
+    UsersManage,
+    /// Logging out, which every role may do
+    SessionEnd,
+}
+
+impl Role {
+    pub const ALL: [Self; 3] = [Self::Admin, Self::Editor, Self::Viewer];
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Editor => "editor",
+            Self::Viewer => "viewer",
+        }
+    }
+
+    /// The permission matrix
+    #[must_use]
+    pub const fn permissions(self) -> &'static [Permission] {
+        match self {
+            Self::Admin => &[
+                Permission::SubscribersRead,
+                Permission::IssuesRead,
+                Permission::IssuesWrite,
+                Permission::UsersManage,
+                Permission::SessionEnd,
+            ],
+            Self::Editor => &[
+                Permission::SubscribersRead,
+                Permission::IssuesRead,
+                Permission::IssuesWrite,
+                Permission::SessionEnd,
+            ],
+            Self::Viewer => &[
+                Permission::SubscribersRead,
+                Permission::IssuesRead,
+                Permission::SessionEnd,
+            ],
+        }
+    }
+
+    #[must_use]
+    pub fn can(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str() == value)
+            .ok_or_else(|| format!("unknown role `{value}`, expected admin, editor or viewer"))
+    }
+}
+
+impl Permission {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::SubscribersRead => "subscribers:read",
+            Self::IssuesRead => "issues:read",
+            Self::IssuesWrite => "issues:write",
+            Self::UsersManage => "users:manage",
+            Self::SessionEnd => "session:end",
+        }
+    }
+}
+
+/// A permission as a type, for `Authorized`
+pub trait RequiredPermission {
+    const PERMISSION: Permission;
+}
+
+/// Marker types of the permissions, named like the variants of `Permission`
+pub mod permissions {
+    macro_rules! permissions {
+        ($($permission:ident),*) => {$(
+            pub struct $permission;
+
+            impl super::RequiredPermission for $permission {
+                const PERMISSION: super::Permission = super::Permission::$permission;
+            }
+        )*};
+    }
+
+    permissions!(
+        SubscribersRead,
+        IssuesRead,
+        IssuesWrite,
+        UsersManage,
+        SessionEnd
+    );
+}
+
+/// A logged in session whose role grants `P`. Visitors without a session are
+/// redirected to the login page, users whose role lacks `P` get a 403.
+pub struct Authorized<P> {
+    pub session: Session,
+    permission: PhantomData<fn() -> P>,
+}
+
+#[async_trait]
+impl<P: RequiredPermission> FromRequestParts<AppState> for Authorized<P> {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Response> {
+        let AdminSession(session) = AdminSession::from_request_parts(parts, state).await?;
+        if !session.can(P::PERMISSION) {
+            let role = session.role().map_or("none", Role::as_str);
+            tracing::warn!(
+                username = session.username,
+                role,
+                permission = P::PERMISSION.as_str(),
+                "Refused a user lacking the permission"
+            );
+            return Err(Problem::forbidden(format!(
+                "The `{role}` role lacks the `{}` permission",
+                P::PERMISSION.as_str()
+            ))
+            .into_response());
+        }
+        Ok(Self {
+            session,
+            permission: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+
+    #[test]
+    fn roles_are_ordered_by_what_they_can_do() {
+        for role in Role::ALL {
+            assert_eq!(role.as_str().parse::<Role>(), Ok(role));
+            assert!(role.can(Permission::SubscribersRead));
+            assert!(role.can(Permission::IssuesRead));
+            assert!(role.can(Permission::SessionEnd));
+        }
+        assert!(Role::Admin.can(Permission::UsersManage));
+        assert!(!Role::Editor.can(Permission::UsersManage));
+        assert!(Role::Editor.can(Permission::IssuesWrite));
+        assert!(!Role::Viewer.can(Permission::IssuesWrite));
+        assert!("owner".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api_keys::{
+    ApiKey,
+    scopes::{IssuesPublish, SubscribersRead, SubscribersWrite},
+};
+use crate::delivery;
+use crate::domain::{CreateSubscriber, SubscriberStatus};
+use crate::routes::subscriptions::{Subscriber, add_subscriber};
+use crate::startup::AppState;
+
+/// Most subscribers returned by one request
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberDetails {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub status: String,
+    pub frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub status_changed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SubscriberListQuery {
+    /// `pending`, `confirmed` or `unsubscribed`
+    #[serde(default)]
+    status: Option<String>,
+    /// Defaults to 100, at most 1000
+    #[serde(default)]
+    limit: Option<i64>,
+    #[serde(default)]
+    offset: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PublishIssue {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PublishedIssue {
+    /// Also identifies the send in the engagement and delivery reports
+    pub issue_id: Uuid,
+    /// Subscribers the issue was queued for
+    pub queued: u64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/subscribers",
+    tag = "admin",
+    params(SubscriberListQuery),
+    security(("api_key" = ["subscribers:read"])),
+    responses(
+        (status = 200, description = "Subscribers, most recently changed first", body = [SubscriberDetails]),
+        (status = 400, description = "Unknown status"),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `subscribers:read` scope"),
+    )
+)]
+pub async fn list_subscribers(
+    State(state): State<AppState>,
+    _key: ApiKey<SubscribersRead>,
+    Query(query): Query<SubscriberListQuery>,
+) -> Response {
+    let status = match query.status.as_deref().map(SubscriberStatus::parse) {
+        None => None,
+        Some(Some(status)) => Some(status.as_str()),
+        Some(None) => return (StatusCode::BAD_REQUEST, "Unknown status").into_response(),
+    };
+    let subscribers = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        select id, name, email, status, frequency, paused_until, status_changed_at
+        from subscriber
+        where $1::text is null or status = $1
+        order by status_changed_at desc, email
+        limit $2 offset $3
+        "#,
+        status,
+        query.limit.unwrap_or(100).clamp(1, MAX_LIMIT),
+        query.offset.unwrap_or(0).max(0),
+    )
+    .fetch_all(&state.pool)
+    .await;
+    match subscribers {
+        Ok(subscribers) => Json(subscribers).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list the subscribers: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Bot protection is skipped since the client is trusted, and the
+/// subscriber is confirmed straight away
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/subscribers",
+    tag = "admin",
+    request_body = CreateSubscriber,
+    security(("api_key" = ["subscribers:write"])),
+    responses(
+        (status = 201, description = "Confirmed subscriber created", body = Subscriber),
+        (status = 400, description = "Invalid name or email"),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `subscribers:write` scope"),
+        (status = 409, description = "The email is already subscribed"),
+    )
+)]
+#[tracing::instrument(name = "Adding a subscriber through the API", skip(state, key, new_subscriber), fields(by = %key.prefix))]
+pub async fn add_confirmed_subscriber(
+    State(state): State<AppState>,
+    key: ApiKey<SubscribersWrite>,
+    Json(new_subscriber): Json<CreateSubscriber>,
+) -> Response {
+    match add_subscriber(&state, &new_subscriber, SubscriberStatus::Confirmed).await {
+        Ok(subscriber) => (StatusCode::CREATED, Json(subscriber)).into_response(),
+        Err(refusal) if refusal.reason == "duplicate_email" => {
+            (StatusCode::CONFLICT, "Already subscribed").into_response()
+        }
+        Err(refusal) => (refusal.status, refusal.message).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/issues",
+    tag = "admin",
+    request_body = PublishIssue,
+    security(("api_key" = ["issues:publish"])),
+    responses(
+        (status = 201, description = "Issue stored and queued for every subscriber due one", body = PublishedIssue),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `issues:publish` scope"),
+        (status = 422, description = "Empty title or content"),
+    )
+)]
+#[tracing::instrument(name = "Publishing an issue through the API", skip(state, key, issue), fields(by = %key.prefix))]
+pub async fn publish_issue(
+    State(state): State<AppState>,
+    key: ApiKey<IssuesPublish>,
+    Json(issue): Json<PublishIssue>,
+) -> Response {
+    let title = issue.title.trim();
+    if title.is_empty()
+        || issue.text_content.trim().is_empty()
+        || issue.html_content.trim().is_empty()
+    {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The title, text and HTML content are required",
+        )
+            .into_response();
+    }
+    match store_and_queue(&state, &issue, title, key.id).await {
+        Ok((issue_id, queued)) => (
+            StatusCode::CREATED,
+            Json(PublishedIssue { issue_id, queued }),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to publish the issue: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Stores the issue and queues it in one transaction, so a failure leaves
+/// neither behind and the client can safely retry.
+async fn store_and_queue(
+    state: &AppState,
+    issue: &PublishIssue,
+    title: &str,
+    api_key_id: Uuid,
+) -> Result<(Uuid, u64), sqlx::Error> {
+    let mut transaction = state.pool.begin().await?;
+    let issue_id = sqlx::query_scalar!(
+        r#"
+        insert into newsletter_issue (title, text_content, html_content, created_by_api_key)
+        values ($1, $2, $3, $4)
+        returning id
+        "#,
+        title,
+        issue.text_content,
+        issue.html_content,
+        api_key_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let queued = delivery::start_send(&mut *transaction, issue_id).await?;
+    transaction.commit().await?;
+    Ok((issue_id, queued))
+}
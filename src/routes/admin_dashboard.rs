@@ -0,0 +1,384 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Form,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use secrecy::SecretBox;
+use serde::Deserialize;
+use sqlx::types::Uuid;
+
+use crate::admin::check_credentials;
+use crate::dashboard::{
+    IssueFormErrors, IssueFormValues, IssueRow, SubscriberFilter, SubscriberRow, UserRow,
+};
+use crate::problem::Problem;
+use crate::roles::{
+    Authorized, Role,
+    permissions::{IssuesRead, IssuesWrite, SessionEnd, SubscribersRead, UsersManage},
+};
+use crate::sessions::LOGIN_PATH;
+use crate::startup::AppState;
+
+/// Longest issue title accepted
+const MAX_TITLE_LENGTH: usize = 200;
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    #[serde(default)]
+    username: String,
+    password: SecretBox<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SubscriberQuery {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    search: Option<String>,
+    #[serde(default)]
+    page: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct IssueForm {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+}
+
+#[derive(Deserialize)]
+pub struct RoleForm {
+    #[serde(default)]
+    role: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    #[serde(default)]
+    notice: Option<String>,
+}
+
+/// Messages the login page shows, named in its `notice` parameter since
+/// visitors have no session to carry a flash
+fn login_notice(notice: &str) -> Option<&'static str> {
+    match notice {
+        "invalid_credentials" => Some("Invalid username or password"),
+        "rate_limited" => Some("Too many login attempts, try again later"),
+        "logged_out" => Some("You are logged out"),
+        _ => None,
+    }
+}
+
+/// The form carries a CSRF token signed from a cookie nonce, so no session is
+/// stored until the login succeeds
+pub async fn login_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    match state.sessions.load(&headers).await {
+        Ok(Some(_)) => return Redirect::to("/admin").into_response(),
+        Ok(None) => {}
+        Err(e) => return internal_error(&e),
+    }
+    let (csrf_token, cookie) = state.sessions.login_csrf_token(&headers);
+    let page = Html(
+        state
+            .pages
+            .login(&csrf_token, query.notice.as_deref().and_then(login_notice)),
+    );
+    match cookie {
+        Some(cookie) => ([(header::SET_COOKIE, cookie)], page).into_response(),
+        None => page.into_response(),
+    }
+}
+
+/// Starts a new session, replacing any the browser had, so a session
+/// planted before login is useless afterwards
+#[tracing::instrument(name = "Logging in to the dashboard", skip(state, connect_info, headers, form), fields(username = %form.username))]
+pub async fn login(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let peer = connect_info.map(|ConnectInfo(address)| address.ip());
+    if !state
+        .login_limiter
+        .allow(peer, &headers, &form.username)
+        .await
+    {
+        return Redirect::to(&format!("{LOGIN_PATH}?notice=rate_limited")).into_response();
+    }
+    let admin_user_id = match check_credentials(&state.pool, &form.username, form.password).await {
+        Ok(id) => id,
+        Err(e) => return internal_error(&e),
+    };
+    let Some(admin_user_id) = admin_user_id else {
+        tracing::warn!("Invalid credentials");
+        return Redirect::to(&format!("{LOGIN_PATH}?notice=invalid_credentials")).into_response();
+    };
+    match state.sessions.load(&headers).await {
+        Ok(Some(previous)) => {
+            if let Err(e) = state.sessions.end(&previous).await {
+                return internal_error(&e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => return internal_error(&e),
+    }
+    let welcome = format!("Welcome back, {}", form.username);
+    match state.sessions.start(admin_user_id, Some(&welcome)).await {
+        Ok((cookie, _)) => ([(header::SET_COOKIE, cookie)], Redirect::to("/admin")).into_response(),
+        Err(e) => internal_error(&e),
+    }
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<SessionEnd>,
+) -> Response {
+    if let Err(e) = state.sessions.end(&session).await {
+        return internal_error(&e);
+    }
+    Redirect::to(&format!("{LOGIN_PATH}?notice=logged_out")).into_response()
+}
+
+pub async fn dashboard(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<SubscribersRead>,
+) -> Response {
+    let statuses = sqlx::query!(
+        r#"select status, count(*) as "count!" from subscriber group by status order by status"#
+    )
+    .fetch_all(&state.pool)
+    .await;
+    let issues = sqlx::query_scalar!(r#"select count(*) as "count!" from newsletter_issue"#)
+        .fetch_one(&state.pool)
+        .await;
+    match (statuses, issues) {
+        (Ok(statuses), Ok(issues)) => {
+            let statuses: Vec<_> = statuses
+                .into_iter()
+                .map(|row| (row.status, row.count))
+                .collect();
+            Html(state.pages.dashboard(&session, &statuses, issues)).into_response()
+        }
+        (Err(e), _) | (_, Err(e)) => internal_error(&e),
+    }
+}
+
+pub async fn subscribers_page(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<SubscribersRead>,
+    Query(query): Query<SubscriberQuery>,
+) -> Response {
+    let filter = SubscriberFilter {
+        status: query.status.as_deref().filter(|status| !status.is_empty()),
+        search: query
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|search| !search.is_empty()),
+        page: query.page.unwrap_or(1).max(1),
+    };
+    let page_size = i64::from(state.admin_page_size);
+    // One more row tells whether there is a next page
+    let rows = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        select email, name, status, frequency, paused_until, status_changed_at
+        from subscriber
+        where ($1::text is null or status = $1)
+            and ($2::text is null
+                or strpos(lower(email), lower($2)) > 0
+                or strpos(lower(name), lower($2)) > 0)
+        order by status_changed_at desc, email
+        limit $3 offset $4
+        "#,
+        filter.status,
+        filter.search,
+        page_size + 1,
+        (i64::from(filter.page) - 1) * page_size,
+    )
+    .fetch_all(&state.pool)
+    .await;
+    match rows {
+        Ok(mut rows) => {
+            let has_next = rows.len() > state.admin_page_size as usize;
+            rows.truncate(state.admin_page_size as usize);
+            Html(state.pages.subscribers(&session, &rows, &filter, has_next)).into_response()
+        }
+        Err(e) => internal_error(&e),
+    }
+}
+
+pub async fn issues_page(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<IssuesRead>,
+) -> Response {
+    let rows = sqlx::query_as!(
+        IssueRow,
+        r#"
+        select i.id, i.title, coalesce(u.username, 'API key ' || k.name) as author, i.created_at
+        from newsletter_issue i
+            left join admin_user u on u.id = i.created_by
+            left join api_key k on k.id = i.created_by_api_key
+        order by i.created_at desc
+        "#
+    )
+    .fetch_all(&state.pool)
+    .await;
+    match rows {
+        Ok(rows) => Html(state.pages.issues(&session, &rows)).into_response(),
+        Err(e) => internal_error(&e),
+    }
+}
+
+pub async fn compose_issue_page(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<IssuesWrite>,
+) -> Html<String> {
+    Html(state.pages.compose_issue(
+        &session,
+        &IssueFormValues::default(),
+        &IssueFormErrors::default(),
+    ))
+}
+
+#[tracing::instrument(name = "Saving an issue", skip(state, session, form))]
+pub async fn create_issue(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<IssuesWrite>,
+    Form(form): Form<IssueForm>,
+) -> Response {
+    let title = form.title.trim();
+    let errors = IssueFormErrors {
+        title: if title.is_empty() {
+            Some("Please enter a title")
+        } else if title.chars().count() > MAX_TITLE_LENGTH {
+            Some("Please keep the title under 200 characters")
+        } else {
+            None
+        },
+        text_content: form
+            .text_content
+            .trim()
+            .is_empty()
+            .then_some("Please enter the plain text version"),
+        html_content: form
+            .html_content
+            .trim()
+            .is_empty()
+            .then_some("Please enter the HTML version"),
+    };
+    if errors.title.is_some() || errors.text_content.is_some() || errors.html_content.is_some() {
+        let values = IssueFormValues {
+            title: &form.title,
+            text_content: &form.text_content,
+            html_content: &form.html_content,
+        };
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Html(state.pages.compose_issue(&session, &values, &errors)),
+        )
+            .into_response();
+    }
+
+    let saved = sqlx::query_scalar!(
+        r#"
+        insert into newsletter_issue (title, text_content, html_content, created_by)
+        values ($1, $2, $3, $4)
+        returning id
+        "#,
+        title,
+        form.text_content,
+        form.html_content,
+        session.admin_user_id,
+    )
+    .fetch_one(&state.pool)
+    .await;
+    let issue_id = match saved {
+        Ok(issue_id) => issue_id,
+        Err(e) => return internal_error(&e),
+    };
+    tracing::info!(%issue_id, "Issue saved");
+    if let Err(e) = state
+        .sessions
+        .flash(&session, &format!("Saved “{title}”"))
+        .await
+    {
+        return internal_error(&e);
+    }
+    Redirect::to("/admin/issues").into_response()
+}
+
+pub async fn users_page(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<UsersManage>,
+) -> Response {
+    let rows = sqlx::query_as!(
+        UserRow,
+        "select id, username, role, created_at from admin_user order by username"
+    )
+    .fetch_all(&state.pool)
+    .await;
+    match rows {
+        Ok(rows) => Html(state.pages.users(&session, &rows)).into_response(),
+        Err(e) => internal_error(&e),
+    }
+}
+
+/// Users cannot change their own role, so the last admin cannot lock
+/// everyone out
+#[tracing::instrument(
+    name = "Changing the role of an admin user",
+    skip(state, session, form)
+)]
+pub async fn update_role(
+    State(state): State<AppState>,
+    Authorized { session, .. }: Authorized<UsersManage>,
+    Path(user_id): Path<Uuid>,
+    Form(form): Form<RoleForm>,
+) -> Response {
+    let role = match form.role.parse::<Role>() {
+        Ok(role) => role,
+        Err(e) => return Problem::new(StatusCode::UNPROCESSABLE_ENTITY, e).into_response(),
+    };
+    let message = if session.admin_user_id == user_id {
+        "You cannot change your own role".to_string()
+    } else {
+        let updated = sqlx::query_scalar!(
+            "update admin_user set role = $2 where id = $1 returning username",
+            user_id,
+            role.as_str(),
+        )
+        .fetch_optional(&state.pool)
+        .await;
+        match updated {
+            Ok(Some(username)) => {
+                tracing::info!(username, role = role.as_str(), "Role changed");
+                format!("{username} is now {}", role.as_str())
+            }
+            Ok(None) => {
+                return Problem::new(StatusCode::NOT_FOUND, "No such user").into_response();
+            }
+            Err(e) => return internal_error(&e),
+        }
+    };
+    if let Err(e) = state.sessions.flash(&session, &message).await {
+        return internal_error(&e);
+    }
+    Redirect::to("/admin/users").into_response()
+}
+
+fn internal_error(e: &dyn std::fmt::Debug) -> Response {
+    tracing::error!("Failed to serve the dashboard: {:?}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
@@ -0,0 +1,125 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::types::Uuid;
+use utoipa::ToSchema;
+
+use crate::api_keys::{
+    ApiKey, ApiKeySummary, CreatedApiKey, Scope, create_api_key, list_api_keys, revoke_api_key,
+    scopes::ApiKeysManage,
+};
+use crate::startup::AppState;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKey {
+    /// What the key is for, e.g. the integration using it
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// The key never expires when not set
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/api-keys",
+    tag = "admin",
+    request_body = CreateApiKey,
+    security(("api_key" = ["api_keys:manage"])),
+    responses(
+        (status = 201, description = "Key created, its secret is only shown in this response", body = CreatedApiKey),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `api_keys:manage` scope"),
+        (status = 422, description = "Missing name or scopes, or an expiry in the past"),
+    )
+)]
+#[tracing::instrument(name = "Creating an API key through the API", skip(state, key, request), fields(by = %key.prefix))]
+pub async fn create_key(
+    State(state): State<AppState>,
+    key: ApiKey<ApiKeysManage>,
+    Json(request): Json<CreateApiKey>,
+) -> Response {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "The key needs a name").into_response();
+    }
+    if request.scopes.is_empty() {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The key needs at least one scope",
+        )
+            .into_response();
+    }
+    if request
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The expiry must be in the future",
+        )
+            .into_response();
+    }
+    match create_api_key(&state.pool, name, &request.scopes, request.expires_at).await {
+        Ok(created) => (StatusCode::CREATED, Json(created)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to create the API key: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/api-keys",
+    tag = "admin",
+    security(("api_key" = ["api_keys:manage"])),
+    responses(
+        (status = 200, description = "Every key, newest first, without their secret", body = [ApiKeySummary]),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `api_keys:manage` scope"),
+    )
+)]
+pub async fn list_keys(State(state): State<AppState>, _key: ApiKey<ApiKeysManage>) -> Response {
+    match list_api_keys(&state.pool).await {
+        Ok(keys) => Json(keys).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list the API keys: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/api-keys/{key_id}",
+    tag = "admin",
+    params(("key_id" = Uuid, Path, description = "API key")),
+    security(("api_key" = ["api_keys:manage"])),
+    responses(
+        (status = 204, description = "Key revoked, it is refused from now on"),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `api_keys:manage` scope"),
+        (status = 404, description = "No such key, or already revoked"),
+    )
+)]
+#[tracing::instrument(name = "Revoking an API key through the API", skip(state, key), fields(by = %key.prefix))]
+pub async fn revoke_key(
+    State(state): State<AppState>,
+    key: ApiKey<ApiKeysManage>,
+    Path(key_id): Path<Uuid>,
+) -> Response {
+    match revoke_api_key(&state.pool, key_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "No such API key").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to revoke the API key: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
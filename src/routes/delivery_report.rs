@@ -0,0 +1,120 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, types::Uuid};
+use utoipa::ToSchema;
+
+use crate::api_keys::{ApiKey, scopes::IssuesRead};
+use crate::startup::AppState;
+
+#[derive(Serialize, ToSchema)]
+pub struct DeliveryReport {
+    pub issue_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub queued: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub bounced: i64,
+    pub suppressed: i64,
+    pub failed_recipients: Vec<FailedRecipient>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct FailedRecipient {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub last_error: Option<String>,
+    pub attempts: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/issues/{issue_id}/report",
+    tag = "admin",
+    params(("issue_id" = Uuid, Path, description = "Newsletter issue")),
+    security(("api_key" = ["issues:read"])),
+    responses(
+        (status = 200, description = "Delivery status counts", body = DeliveryReport),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `issues:read` scope"),
+        (status = 404, description = "The issue has not been sent"),
+    )
+)]
+/// # Errors
+///
+/// 404 when the issue has not been sent, 500 on database errors
+#[tracing::instrument(name = "Fetching issue delivery report", skip(state, _key))]
+pub async fn issue_delivery_report(
+    State(state): State<AppState>,
+    _key: ApiKey<IssuesRead>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    match fetch_report(issue_id, &state.pool).await {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Issue has not been sent".to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[tracing::instrument(name = "reading delivery status from the database", skip(pool))]
+async fn fetch_report(
+    issue_id: Uuid,
+    pool: &Pool<Postgres>,
+) -> Result<Option<DeliveryReport>, sqlx::Error> {
+    let Some(send) = sqlx::query!(
+        "select started_at, finished_at from issue_send where issue_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let counts = sqlx::query!(
+        r#"
+        select
+            count(*) filter (where status = 'queued') as "queued!",
+            count(*) filter (where status = 'sent') as "sent!",
+            count(*) filter (where status = 'failed') as "failed!",
+            count(*) filter (where status = 'bounced') as "bounced!",
+            count(*) filter (where status = 'suppressed') as "suppressed!"
+        from issue_delivery
+        where issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let failed_recipients = sqlx::query_as!(
+        FailedRecipient,
+        r#"
+        select subscriber_id, subscriber_email as email, status, last_error, attempts, updated_at
+        from issue_delivery
+        where issue_id = $1 and status in ('failed', 'bounced')
+        order by updated_at, subscriber_email
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(DeliveryReport {
+        issue_id,
+        started_at: send.started_at,
+        finished_at: send.finished_at,
+        queued: counts.queued,
+        sent: counts.sent,
+        failed: counts.failed,
+        bounced: counts.bounced,
+        suppressed: counts.suppressed,
+        failed_recipients,
+    }))
+}
@@ -0,0 +1,54 @@
+use axum::{Json, extract::State, response::Html};
+use utoipa::OpenApi;
+
+use crate::configuration::ApiDocsSettings;
+use crate::openapi::ApiDoc;
+use crate::pages::escape;
+use crate::startup::AppState;
+
+/// The page of `/api/docs`, loading the pinned Redoc bundle and checking
+/// its digest when one is configured
+#[must_use]
+pub fn docs_page(settings: &ApiDocsSettings) -> String {
+    let integrity = settings
+        .redoc_integrity
+        .as_deref()
+        .map(|digest| format!(r#" integrity="{}" crossorigin="anonymous""#, escape(digest)))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>Newsletter API</title>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    <redoc spec-url="/api/openapi.json"></redoc>
+    <script src="{}"{integrity}></script>
+  </body>
+</html>
+"#,
+        escape(&settings.redoc_url)
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "docs",
+    responses((status = 200, description = "OpenAPI 3.1 document", content_type = "application/json"))
+)]
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/docs",
+    tag = "docs",
+    responses((status = 200, description = "Interactive documentation", content_type = "text/html"))
+)]
+pub async fn api_docs(State(state): State<AppState>) -> Html<String> {
+    Html(state.api_docs.to_string())
+}
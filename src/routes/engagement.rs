@@ -0,0 +1,123 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, types::Uuid};
+use utoipa::ToSchema;
+
+use crate::api_keys::{ApiKey, scopes::IssuesRead};
+use crate::startup::AppState;
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueEngagement {
+    pub issue_id: Uuid,
+    pub recipients: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    pub total_opens: i64,
+    pub total_clicks: i64,
+    pub open_rate: f64,
+    pub click_rate: f64,
+    pub links: Vec<LinkClicks>,
+}
+
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/issues/{issue_id}/engagement",
+    tag = "admin",
+    params(("issue_id" = Uuid, Path, description = "Newsletter issue")),
+    security(("api_key" = ["issues:read"])),
+    responses(
+        (status = 200, description = "Open and click rates", body = IssueEngagement),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `issues:read` scope"),
+    )
+)]
+/// # Errors
+///
+/// 500 on database errors
+#[tracing::instrument(name = "Fetching issue engagement", skip(state, _key))]
+pub async fn issue_engagement(
+    State(state): State<AppState>,
+    _key: ApiKey<IssuesRead>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    match fetch_engagement(issue_id, &state.pool).await {
+        Ok(engagement) => Ok(Json(engagement)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+#[tracing::instrument(name = "reading engagement events from the database", skip(pool))]
+async fn fetch_engagement(
+    issue_id: Uuid,
+    pool: &Pool<Postgres>,
+) -> Result<IssueEngagement, sqlx::Error> {
+    let recipients = sqlx::query_scalar!(
+        r#"select count(*) as "count!" from issue_delivery where issue_id = $1 and status = 'sent'"#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let totals = sqlx::query!(
+        r#"
+        select
+            count(distinct subscriber_id) filter (where kind = 'open') as "unique_opens!",
+            count(distinct subscriber_id) filter (where kind = 'click') as "unique_clicks!",
+            count(*) filter (where kind = 'open') as "total_opens!",
+            count(*) filter (where kind = 'click') as "total_clicks!"
+        from engagement_event
+        where issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        select
+            url as "url!",
+            count(*) as "clicks!",
+            count(distinct subscriber_id) as "unique_clicks!"
+        from engagement_event
+        where issue_id = $1 and kind = 'click'
+        group by url
+        order by 2 desc, 1
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(IssueEngagement {
+        issue_id,
+        recipients,
+        unique_opens: totals.unique_opens,
+        unique_clicks: totals.unique_clicks,
+        total_opens: totals.total_opens,
+        total_clicks: totals.total_clicks,
+        open_rate: rate(totals.unique_opens, recipients),
+        click_rate: rate(totals.unique_clicks, recipients),
+        links,
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn rate(count: i64, recipients: i64) -> f64 {
+    if recipients == 0 {
+        0.0
+    } else {
+        count as f64 / recipients as f64
+    }
+}
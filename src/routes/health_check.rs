@@ -1,5 +1,11 @@
 use axum::http::StatusCode;
 
+#[utoipa::path(
+    get,
+    path = "/healthcheck",
+    tag = "health",
+    responses((status = 200, description = "The service is up"))
+)]
 pub async fn health_check() -> StatusCode {
     StatusCode::OK
 }
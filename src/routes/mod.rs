@@ -1,5 +1,23 @@
+mod admin_api;
+mod admin_dashboard;
+mod api_keys;
+mod delivery_report;
+mod docs;
+mod engagement;
 mod health_check;
+mod pages;
+mod prometheus;
 mod subscriptions;
+mod tracking;
 
+pub use admin_api::*;
+pub use admin_dashboard::*;
+pub use api_keys::*;
+pub use delivery_report::*;
+pub use docs::*;
+pub use engagement::*;
 pub use health_check::*;
+pub use pages::*;
+pub use prometheus::*;
 pub use subscriptions::*;
+pub use tracking::*;
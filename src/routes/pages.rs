@@ -0,0 +1,468 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Form,
+    extract::{ConnectInfo, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{PgPool, types::Uuid};
+
+use crate::domain::{
+    CreateSubscriber, EmailFrequency, SubscriberEmail, SubscriberName, SubscriberStatus,
+};
+use crate::pages::{FormErrors, MAX_PAUSE_DAYS, PreferenceValues, SubscribeFormValues};
+use crate::routes::subscriptions::{Subscriber, subscribe};
+use crate::startup::AppState;
+use crate::subscription_links::LinkAction;
+use crate::webhooks::SubscriberEvent;
+
+/// Fields of the hosted subscribe form, kept as typed so they can be shown
+/// again next to their errors
+#[derive(Deserialize)]
+pub struct SubscribeForm {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    website: Option<String>,
+    #[serde(default)]
+    rendered_at: Option<String>,
+    #[serde(default, alias = "cf-turnstile-response", alias = "h-captcha-response")]
+    challenge_token: Option<String>,
+}
+
+/// Query of the signed links handed out to subscribers
+#[derive(Deserialize)]
+pub struct SignedLink {
+    subscriber_id: Uuid,
+    sig: String,
+}
+
+/// Fields of the preference centre form
+#[derive(Deserialize)]
+pub struct PreferencesForm {
+    #[serde(default)]
+    name: String,
+    frequency: String,
+    /// Empty keeps the current pause, 0 resumes delivery
+    #[serde(default)]
+    pause_days: String,
+}
+
+struct Preferences {
+    name: String,
+    frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    status: String,
+}
+
+#[derive(Deserialize)]
+pub struct SubscribePageQuery {
+    /// Why the previous submission was refused before reaching the handler
+    #[serde(default)]
+    error: Option<String>,
+}
+
+pub async fn subscribe_page(
+    State(state): State<AppState>,
+    Query(query): Query<SubscribePageQuery>,
+) -> Html<String> {
+    let errors = FormErrors {
+        form: match query.error.as_deref() {
+            Some("rate_limited") => Some("Too many attempts, please try again later"),
+            Some("too_large") => Some("Your submission is too large"),
+            Some("timed_out") => Some("Your submission took too long to arrive, please try again"),
+            _ => None,
+        },
+        ..FormErrors::default()
+    };
+    Html(state.pages.subscribe(
+        &SubscribeFormValues::default(),
+        &errors,
+        &state.bot_protection.rendered_at(),
+    ))
+}
+
+/// Validates the form like `create_subscriber` and shows the problems
+/// inline. Subscribers created here confirm their address before receiving
+/// any issue.
+pub async fn submit_subscribe_page(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Form(form): Form<SubscribeForm>,
+) -> Response {
+    let name = SubscriberName::parse(form.name.trim().to_string()).ok();
+    let email = SubscriberEmail::parse(form.email.trim().to_string()).ok();
+    let mut errors = FormErrors {
+        name: name.is_none().then_some(
+            "Please enter your name, 3 to 256 characters without brackets, quotes or slashes",
+        ),
+        email: email
+            .is_none()
+            .then_some("Please enter a valid email address"),
+        form: None,
+    };
+    let status = if let (Some(name), Some(email)) = (name, email) {
+        let new_subscriber = CreateSubscriber {
+            name,
+            email,
+            website: form.website.clone(),
+            rendered_at: form.rendered_at.clone(),
+            challenge_token: form.challenge_token.clone(),
+        };
+        let remote_ip = connect_info.map(|ConnectInfo(address)| address.ip());
+        match subscribe(
+            &state,
+            remote_ip,
+            &new_subscriber,
+            SubscriberStatus::Pending,
+        )
+        .await
+        {
+            Ok(_) => return Redirect::to("/subscribe/check-inbox").into_response(),
+            Err(refusal) => {
+                match refusal.reason {
+                    "duplicate_email" => errors.email = Some("This address is already subscribed"),
+                    "rejected" => {
+                        errors.form = Some("We could not verify your submission, please try again");
+                    }
+                    _ => errors.form = Some("Something went wrong, please try again later"),
+                }
+                refusal.status
+            }
+        }
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    let values = SubscribeFormValues {
+        name: &form.name,
+        email: &form.email,
+    };
+    (
+        status,
+        Html(
+            state
+                .pages
+                .subscribe(&values, &errors, &state.bot_protection.rendered_at()),
+        ),
+    )
+        .into_response()
+}
+
+pub async fn check_inbox_page(State(state): State<AppState>) -> Html<String> {
+    Html(state.pages.check_inbox())
+}
+
+#[tracing::instrument(name = "Confirming a subscription", skip(state, link), fields(subscriber_id = %link.subscriber_id))]
+pub async fn confirm_subscription(
+    State(state): State<AppState>,
+    Query(link): Query<SignedLink>,
+) -> Response {
+    if !state
+        .links
+        .verify(LinkAction::Confirm, link.subscriber_id, &link.sig)
+    {
+        return invalid_link(&state);
+    }
+    match set_status(
+        &state.pool,
+        link.subscriber_id,
+        &[SubscriberStatus::Pending],
+        SubscriberStatus::Confirmed,
+    )
+    .await
+    {
+        Ok(Some(subscriber)) => {
+            let payload = state.links.attach(&subscriber, subscriber.id, false);
+            state
+                .webhooks
+                .dispatch(SubscriberEvent::Confirmed, &payload)
+                .await;
+            Html(state.pages.confirmed()).into_response()
+        }
+        // Following the link again is harmless
+        Ok(None) => match current_status(&state.pool, link.subscriber_id).await {
+            Ok(Some(SubscriberStatus::Confirmed)) => Html(state.pages.confirmed()).into_response(),
+            Ok(_) => invalid_link(&state),
+            Err(e) => database_error(&e),
+        },
+        Err(e) => database_error(&e),
+    }
+}
+
+pub async fn unsubscribe_page(
+    State(state): State<AppState>,
+    Query(link): Query<SignedLink>,
+) -> Response {
+    if !state
+        .links
+        .verify(LinkAction::Unsubscribe, link.subscriber_id, &link.sig)
+    {
+        return invalid_link(&state);
+    }
+    Html(state.pages.unsubscribe()).into_response()
+}
+
+/// Posted by the unsubscribe page, or directly by mail clients supporting
+/// RFC 8058 one-click unsubscribe
+#[tracing::instrument(name = "Unsubscribing", skip(state, link), fields(subscriber_id = %link.subscriber_id))]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(link): Query<SignedLink>,
+) -> Response {
+    if !state
+        .links
+        .verify(LinkAction::Unsubscribe, link.subscriber_id, &link.sig)
+    {
+        return invalid_link(&state);
+    }
+    match set_status(
+        &state.pool,
+        link.subscriber_id,
+        &[SubscriberStatus::Pending, SubscriberStatus::Confirmed],
+        SubscriberStatus::Unsubscribed,
+    )
+    .await
+    {
+        Ok(Some(subscriber)) => {
+            state
+                .webhooks
+                .dispatch(SubscriberEvent::Unsubscribed, &subscriber)
+                .await;
+        }
+        // Already unsubscribed, or deleted since
+        Ok(None) => {}
+        Err(e) => return database_error(&e),
+    }
+    Html(state.pages.unsubscribed()).into_response()
+}
+
+pub async fn preferences_page(
+    State(state): State<AppState>,
+    Query(link): Query<SignedLink>,
+) -> Response {
+    if !state
+        .links
+        .verify(LinkAction::ManagePreferences, link.subscriber_id, &link.sig)
+    {
+        return invalid_link(&state);
+    }
+    let preferences = match preferences(&state.pool, link.subscriber_id).await {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => return invalid_link(&state),
+        Err(e) => return database_error(&e),
+    };
+    if preferences.status == SubscriberStatus::Unsubscribed.as_str() {
+        return Html(state.pages.unsubscribed()).into_response();
+    }
+    render_preferences(
+        &state,
+        link.subscriber_id,
+        &preferences,
+        &FormErrors::default(),
+        false,
+    )
+    .into_response()
+}
+
+/// Saves the preference centre form. The name is validated like on signup
+/// and shown again with its error when invalid.
+#[tracing::instrument(name = "Updating subscriber preferences", skip(state, link, form), fields(subscriber_id = %link.subscriber_id))]
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    Query(link): Query<SignedLink>,
+    Form(form): Form<PreferencesForm>,
+) -> Response {
+    if !state
+        .links
+        .verify(LinkAction::ManagePreferences, link.subscriber_id, &link.sig)
+    {
+        return invalid_link(&state);
+    }
+    let Some(frequency) = EmailFrequency::parse(&form.frequency) else {
+        let errors = FormErrors {
+            form: Some("Please choose how often you receive emails from the list"),
+            ..FormErrors::default()
+        };
+        return refuse_preferences(&state, link.subscriber_id, form.name, None, &errors).await;
+    };
+    let pause_days = match form.pause_days.as_str() {
+        "" => None,
+        days => match days.parse::<i32>() {
+            Ok(days) if (0..=MAX_PAUSE_DAYS).contains(&days) => Some(days),
+            _ => {
+                let errors = FormErrors {
+                    form: Some("Please choose how long to pause delivery from the list"),
+                    ..FormErrors::default()
+                };
+                return refuse_preferences(
+                    &state,
+                    link.subscriber_id,
+                    form.name,
+                    Some(frequency),
+                    &errors,
+                )
+                .await;
+            }
+        },
+    };
+    let Ok(name) = SubscriberName::parse(form.name.trim().to_string()) else {
+        let errors = FormErrors {
+            name: Some(
+                "Please enter your name, 3 to 256 characters without brackets, quotes or slashes",
+            ),
+            ..FormErrors::default()
+        };
+        return refuse_preferences(
+            &state,
+            link.subscriber_id,
+            form.name,
+            Some(frequency),
+            &errors,
+        )
+        .await;
+    };
+
+    let updated = sqlx::query_as!(
+        Preferences,
+        r#"
+        update subscriber set
+            name = $2,
+            frequency = $3,
+            paused_until = case
+                when $4::int is null then paused_until
+                when $4 = 0 then null
+                else now() + make_interval(days => $4)
+            end
+        where id = $1 and status <> 'unsubscribed'
+        returning name, frequency, paused_until, status
+        "#,
+        link.subscriber_id,
+        name.as_ref(),
+        frequency.as_str(),
+        pause_days,
+    )
+    .fetch_optional(&state.pool)
+    .await;
+    match updated {
+        Ok(Some(preferences)) => render_preferences(
+            &state,
+            link.subscriber_id,
+            &preferences,
+            &FormErrors::default(),
+            true,
+        )
+        .into_response(),
+        // Unsubscribed in the meantime, or deleted
+        Ok(None) => Html(state.pages.unsubscribed()).into_response(),
+        Err(e) => database_error(&e),
+    }
+}
+
+/// Shows the preference centre again with `errors`, keeping the submitted
+/// name and, when valid, frequency
+async fn refuse_preferences(
+    state: &AppState,
+    subscriber_id: Uuid,
+    name: String,
+    frequency: Option<EmailFrequency>,
+    errors: &FormErrors,
+) -> Response {
+    let preferences = match preferences(&state.pool, subscriber_id).await {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => return invalid_link(state),
+        Err(e) => return database_error(&e),
+    };
+    let submitted = Preferences {
+        name,
+        frequency: frequency.map_or(preferences.frequency, |frequency| {
+            frequency.as_str().to_string()
+        }),
+        ..preferences
+    };
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        render_preferences(state, subscriber_id, &submitted, errors, false),
+    )
+        .into_response()
+}
+
+fn render_preferences(
+    state: &AppState,
+    subscriber_id: Uuid,
+    preferences: &Preferences,
+    errors: &FormErrors,
+    saved: bool,
+) -> Html<String> {
+    let values = PreferenceValues {
+        name: &preferences.name,
+        frequency: EmailFrequency::parse(&preferences.frequency)
+            .unwrap_or(EmailFrequency::EveryIssue),
+        paused_until: preferences.paused_until,
+    };
+    Html(state.pages.preferences(
+        &values,
+        errors,
+        saved,
+        &state.links.url(LinkAction::Unsubscribe, subscriber_id),
+    ))
+}
+
+async fn preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Preferences>, sqlx::Error> {
+    sqlx::query_as!(
+        Preferences,
+        "select name, frequency, paused_until, status from subscriber where id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+fn invalid_link(state: &AppState) -> Response {
+    (StatusCode::BAD_REQUEST, Html(state.pages.invalid_link())).into_response()
+}
+
+fn database_error(e: &sqlx::Error) -> Response {
+    tracing::error!("Failed to update the subscriber: {:?}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// Moves the subscriber to `to`, returning it when it was in one of `from`
+async fn set_status(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    from: &[SubscriberStatus],
+    to: SubscriberStatus,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    let from: Vec<&str> = from.iter().map(|status| status.as_str()).collect();
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        update subscriber set status = $3, status_changed_at = now()
+        where id = $1 and status = any($2)
+        returning id, name, email
+        "#,
+        subscriber_id,
+        &from as &[&str],
+        to.as_str(),
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+async fn current_status(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberStatus>, sqlx::Error> {
+    let status = sqlx::query_scalar!("select status from subscriber where id = $1", subscriber_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(status.as_deref().and_then(SubscriberStatus::parse))
+}
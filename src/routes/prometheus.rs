@@ -0,0 +1,28 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::api_keys::{ApiKey, scopes::MetricsRead};
+use crate::prometheus::record_pool_metrics;
+use crate::startup::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    security(("api_key" = ["metrics:read"])),
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain"),
+        (status = 401, description = "Missing, invalid, expired or revoked API key"),
+        (status = 403, description = "The API key lacks the `metrics:read` scope"),
+    )
+)]
+pub async fn metrics(
+    State(state): State<AppState>,
+    _key: ApiKey<MetricsRead>,
+) -> impl IntoResponse {
+    record_pool_metrics(&state.pool);
+    state.metrics.run_upkeep();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
@@ -1,59 +1,414 @@
-use crate::domain::{CreateSubscriber, SubscriberEmail, SubscriberName};
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
-use serde::Serialize;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::domain::{CreateSubscriber, SubscriberEmail, SubscriberName, SubscriberStatus};
+use axum::{
+    Form, Json, async_trait,
+    body::Body,
+    extract::{
+        ConnectInfo, FromRequest, State,
+        rejection::{FormRejection, JsonRejection},
+    },
+    http::{Method, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
 use sqlx::{FromRow, Pool, Postgres, types::Uuid};
+use url::Url;
+use utoipa::ToSchema;
 
+use crate::configuration::FormSettings;
 use crate::startup::AppState;
+use crate::subscription_links::LinkAction;
+use crate::webhooks::SubscriberEvent;
 
-#[derive(Serialize, FromRow)]
-struct Subscriber {
+#[derive(Serialize, FromRow, ToSchema)]
+pub struct Subscriber {
     pub id: Uuid,
     pub name: String,
     pub email: String,
 }
 
+/// A body sent as JSON by API clients, or as a form by a plain HTML `<form>`
+pub enum Submission<T> {
+    Json(T),
+    Form(T),
+}
+
+pub enum SubmissionRejection {
+    Json(JsonRejection),
+    Form(FormRejection),
+}
+
+impl IntoResponse for SubmissionRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Json(rejection) => rejection.into_response(),
+            Self::Form(rejection) => rejection.into_response(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S, Body> for Submission<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = SubmissionRejection;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_form = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+        if is_form {
+            Form::from_request(request, state)
+                .await
+                .map(|Form(value)| Self::Form(value))
+                .map_err(SubmissionRejection::Form)
+        } else {
+            Json::from_request(request, state)
+                .await
+                .map(|Json(value)| Self::Json(value))
+                .map_err(SubmissionRejection::Json)
+        }
+    }
+}
+
+/// Pages form submissions are redirected to, with `303 See Other`
+#[derive(Clone)]
+pub struct FormRedirects {
+    success: Url,
+    error: Url,
+}
+
+impl FormRedirects {
+    /// # Panics
+    ///
+    /// If the URLs do not parse, which the configuration validation rules out
+    #[must_use]
+    pub fn from_settings(settings: &FormSettings) -> Self {
+        Self {
+            success: Url::parse(&settings.success_url).expect("Invalid form success URL"),
+            error: Url::parse(&settings.error_url).expect("Invalid form error URL"),
+        }
+    }
+
+    fn success(&self) -> Response {
+        Redirect::to(self.success.as_str()).into_response()
+    }
+
+    fn error(&self, reason: &str) -> Response {
+        let mut url = self.error.clone();
+        url.query_pairs_mut().append_pair("error", reason);
+        Redirect::to(url.as_str()).into_response()
+    }
+}
+
+/// The hosted signup page, which shows its own errors
+const HOSTED_SUBSCRIBE_PATH: &str = "/subscribe";
+/// The API route taking plain HTML form posts, versioned and not
+const SUBSCRIBE_API_PATHS: [&str; 2] = ["/api/v1/subscriber", "/api/subscriber"];
+
+/// Turns the 408, 413 and 429 refusing a subscription form before its handler
+/// runs into the redirect a browser expects: the configured error page for
+/// the API, the hosted page itself for `/subscribe`
+pub async fn redirect_form_errors(
+    State(redirects): State<FormRedirects>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let path = request.uri().path();
+    let hosted = path == HOSTED_SUBSCRIBE_PATH;
+    let is_form = request.method() == Method::POST
+        && (hosted || SUBSCRIBE_API_PATHS.contains(&path))
+        && request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    let response = next.run(request).await;
+    if !is_form {
+        return response;
+    }
+    let reason = match response.status() {
+        StatusCode::REQUEST_TIMEOUT => "timed_out",
+        StatusCode::PAYLOAD_TOO_LARGE => "too_large",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        _ => return response,
+    };
+    if hosted {
+        Redirect::to(&format!("{HOSTED_SUBSCRIBE_PATH}?error={reason}")).into_response()
+    } else {
+        redirects.error(reason)
+    }
+}
+
+/// Why a subscription was refused, `reason` is what form posts are
+/// redirected with
+pub struct Refusal {
+    pub status: StatusCode,
+    pub reason: &'static str,
+    pub message: String,
+}
+
+impl Refusal {
+    fn new(status: StatusCode, reason: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriber",
+    tag = "subscribers",
+    request_body(content(
+        (CreateSubscriber = "application/json"),
+        (CreateSubscriber = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = 201, description = "Subscriber created, pending until the address is confirmed by link", body = Subscriber),
+        (status = 303, description = "Form submission handled, redirecting to the configured success or error page"),
+        (status = 400, description = "Invalid, duplicate or automated submission"),
+        (status = 422, description = "Missing or malformed fields"),
+        (status = 429, description = "Too many attempts, see the `Retry-After` header"),
+        (status = 500, description = "The subscriber could not be saved or the confirmation email could not be sent"),
+    )
+)]
+pub async fn create_subscriber(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    submission: Result<Submission<CreateSubscriber>, SubmissionRejection>,
+) -> Response {
+    let remote_ip = connect_info.map(|ConnectInfo(address)| address.ip());
+    // Only the admin API, trusting its key, adds confirmed subscribers
+    match submission {
+        Ok(Submission::Json(new_subscriber)) => {
+            match subscribe(
+                &state,
+                remote_ip,
+                &new_subscriber,
+                SubscriberStatus::Pending,
+            )
+            .await
+            {
+                Ok(subscriber) => (StatusCode::CREATED, Json(subscriber)).into_response(),
+                Err(refusal) => (refusal.status, refusal.message).into_response(),
+            }
+        }
+        Ok(Submission::Form(new_subscriber)) => {
+            match subscribe(
+                &state,
+                remote_ip,
+                &new_subscriber,
+                SubscriberStatus::Pending,
+            )
+            .await
+            {
+                Ok(_) => state.form_redirects.success(),
+                Err(refusal) => state.form_redirects.error(refusal.reason),
+            }
+        }
+        Err(SubmissionRejection::Form(rejection)) => {
+            tracing::warn!("Rejected malformed form submission: {rejection}");
+            record_validation_failure("malformed");
+            state.form_redirects.error("malformed")
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Checks `new_subscriber` for bots before adding it
+///
+/// # Errors
+///
+/// Automated or invalid submissions, addresses already subscribed and
+/// database errors
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(new_subscriber, state),
+    skip(new_subscriber, state, remote_ip, status),
     fields(
         subscriber_email = %new_subscriber.email,
         subscriber_name = %new_subscriber.name
     )
 )]
-pub async fn create_subscriber(
-    State(state): State<AppState>,
-    Json(new_subscriber): Json<CreateSubscriber>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
+pub async fn subscribe(
+    state: &AppState,
+    remote_ip: Option<IpAddr>,
+    new_subscriber: &CreateSubscriber,
+    status: SubscriberStatus,
+) -> Result<Subscriber, Refusal> {
+    if let Err(rejection) = state.bot_protection.check(new_subscriber, remote_ip).await {
+        tracing::warn!("Rejected automated signup: {:?}", rejection);
+        record_validation_failure(rejection.as_str());
+        return Err(Refusal::new(
+            StatusCode::BAD_REQUEST,
+            "rejected",
+            "Submission rejected",
+        ));
+    }
+    add_subscriber(state, new_subscriber, status).await
+}
+
+/// Validates and stores `new_subscriber` without checking for bots, for
+/// `subscribe` and trusted API clients. Pending subscribers are emailed
+/// their confirmation link.
+///
+/// # Errors
+///
+/// Invalid names or emails, addresses already subscribed, database errors
+/// and confirmation emails failing to send
+pub async fn add_subscriber(
+    state: &AppState,
+    new_subscriber: &CreateSubscriber,
+    status: SubscriberStatus,
+) -> Result<Subscriber, Refusal> {
     if let Err(_e) = SubscriberName::parse(new_subscriber.name.as_ref().to_string()) {
-        return Err((StatusCode::BAD_REQUEST, "Validation Error".to_string()));
+        record_validation_failure("invalid_name");
+        return Err(Refusal::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_name",
+            "Validation Error",
+        ));
     }
     if let Err(_e) = SubscriberEmail::parse(new_subscriber.email.as_ref().to_string()) {
-        return Err((StatusCode::BAD_REQUEST, "Validation Error".to_string()));
+        record_validation_failure("invalid_email");
+        return Err(Refusal::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_email",
+            "Validation Error",
+        ));
     }
-    match insert_subscriber(&new_subscriber, &state.pool).await {
-        Ok(subscriber) => Ok((StatusCode::CREATED, Json(subscriber))),
-        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    match insert_subscriber(new_subscriber, status, &state.pool).await {
+        Ok(None) => {
+            record_validation_failure("duplicate_email");
+            Err(Refusal::new(
+                StatusCode::BAD_REQUEST,
+                "duplicate_email",
+                "Already subscribed",
+            ))
+        }
+        Ok(Some(subscriber)) => {
+            metrics::counter!("subscriptions_created_total").increment(1);
+            if status == SubscriberStatus::Pending
+                && let Err(e) = send_confirmation(state, &subscriber).await
+            {
+                // Signing up again takes over the pending row and retries
+                tracing::error!("Failed to send the confirmation email: {e}");
+                return Err(Refusal::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "unavailable",
+                    "Failed to send the confirmation email",
+                ));
+            }
+            let payload = state.links.attach(
+                &subscriber,
+                subscriber.id,
+                status == SubscriberStatus::Pending,
+            );
+            state
+                .webhooks
+                .dispatch(SubscriberEvent::Created, &payload)
+                .await;
+            Ok(subscriber)
+        }
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(sqlx::error::DatabaseError::is_unique_violation) =>
+        {
+            record_validation_failure("duplicate_email");
+            Err(Refusal::new(
+                StatusCode::BAD_REQUEST,
+                "duplicate_email",
+                "Already subscribed",
+            ))
+        }
+        Err(e) => {
+            tracing::error!("Failed to insert the subscriber: {:?}", e);
+            Err(Refusal::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "unavailable",
+                "Failed to save the subscription",
+            ))
+        }
     }
 }
 
+async fn send_confirmation(
+    state: &AppState,
+    subscriber: &Subscriber,
+) -> Result<(), reqwest::Error> {
+    let email = state.pages.confirmation_email(
+        &subscriber.name,
+        &state.links.url(LinkAction::Confirm, subscriber.id),
+    );
+    state
+        .email_client
+        .send_email(
+            &subscriber.email,
+            &email.subject,
+            &email.html,
+            &email.text,
+            &[],
+        )
+        .await
+}
+
+fn record_validation_failure(reason: &'static str) {
+    metrics::counter!("subscription_validation_failures_total", "reason" => reason).increment(1);
+}
+
+/// Pending signups take over the row of an address still pending, so a new
+/// confirmation link is sent, or unsubscribed, so it can subscribe again.
+/// Returns `None` when the address is already subscribed.
 #[tracing::instrument(
     name = "saving new subscriber details in the database",
     skip(pool, subscriber)
 )]
 async fn insert_subscriber(
     subscriber: &CreateSubscriber,
+    status: SubscriberStatus,
     pool: &Pool<Postgres>,
-) -> Result<Subscriber, sqlx::Error> {
+) -> Result<Option<Subscriber>, sqlx::Error> {
     let result = sqlx::query_as!(
         Subscriber,
-        "insert into subscriber (name, email) values ($1, $2) returning id, name, email",
+        r#"
+        insert into subscriber (name, email, status) values ($1, $2, $3)
+        on conflict (email) do update
+            set name = excluded.name,
+                status = excluded.status,
+                status_changed_at = now(),
+                paused_until = null
+            where excluded.status = 'pending'
+                and subscriber.status in ('pending', 'unsubscribed')
+        returning id, name, email
+        "#,
         subscriber.name.as_ref(),
         subscriber.email.as_ref(),
+        status.as_str(),
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await
     .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
+        // The error detail repeats the email in clear text, only log its code
+        if let Some(db) = e.as_database_error() {
+            tracing::error!(
+                sqlstate = db.code().as_deref().unwrap_or("unknown"),
+                subscriber_email = %subscriber.email,
+                "Failed to insert the subscriber"
+            );
+        } else {
+            tracing::error!(
+                subscriber_email = %subscriber.email,
+                "Failed to insert the subscriber: {e}"
+            );
+        }
         e
     })?;
     Ok(result)
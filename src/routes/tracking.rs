@@ -0,0 +1,130 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Redirect},
+};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres, types::Uuid};
+use utoipa::IntoParams;
+
+use crate::startup::AppState;
+
+/// A transparent 1x1 GIF
+const PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OpenParameters {
+    sig: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClickParameters {
+    url: String,
+    sig: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/t/open/{issue_id}/{subscriber_id}",
+    tag = "tracking",
+    params(
+        ("issue_id" = Uuid, Path, description = "Newsletter issue"),
+        ("subscriber_id" = Uuid, Path, description = "Recipient"),
+        OpenParameters,
+    ),
+    responses((status = 200, description = "Transparent tracking pixel", content_type = "image/gif"))
+)]
+#[tracing::instrument(name = "Recording an open", skip(state, parameters))]
+pub async fn track_open(
+    State(state): State<AppState>,
+    Path((issue_id, subscriber_id)): Path<(Uuid, Uuid)>,
+    Query(parameters): Query<OpenParameters>,
+) -> impl IntoResponse {
+    if state.tracker.is_enabled()
+        && state
+            .tracker
+            .verify_open(issue_id, subscriber_id, &parameters.sig)
+    {
+        // Failing to record an open must never break the email being rendered
+        let _ = insert_event(&state.pool, issue_id, subscriber_id, "open", None).await;
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "image/gif"),
+            (header::CACHE_CONTROL, "no-store, max-age=0"),
+        ],
+        PIXEL,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/t/click/{issue_id}/{subscriber_id}",
+    tag = "tracking",
+    params(
+        ("issue_id" = Uuid, Path, description = "Newsletter issue"),
+        ("subscriber_id" = Uuid, Path, description = "Recipient"),
+        ClickParameters,
+    ),
+    responses(
+        (status = 303, description = "Redirect to the original link"),
+        (status = 400, description = "Invalid signature"),
+    )
+)]
+/// # Errors
+///
+/// 400 when the signature does not match the link
+#[tracing::instrument(name = "Recording a click", skip(state, parameters))]
+pub async fn track_click(
+    State(state): State<AppState>,
+    Path((issue_id, subscriber_id)): Path<(Uuid, Uuid)>,
+    Query(parameters): Query<ClickParameters>,
+) -> Result<Redirect, StatusCode> {
+    if !state
+        .tracker
+        .verify_click(issue_id, subscriber_id, &parameters.url, &parameters.sig)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if state.tracker.is_enabled() {
+        let _ = insert_event(
+            &state.pool,
+            issue_id,
+            subscriber_id,
+            "click",
+            Some(&parameters.url),
+        )
+        .await;
+    }
+    Ok(Redirect::to(&parameters.url))
+}
+
+#[tracing::instrument(name = "saving engagement event in the database", skip(pool))]
+async fn insert_event(
+    pool: &Pool<Postgres>,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: &str,
+    url: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "insert into engagement_event (issue_id, subscriber_id, kind, url) values ($1, $2, $3, $4)",
+        issue_id,
+        subscriber_id,
+        kind,
+        url,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
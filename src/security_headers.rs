@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use axum::{
+    Router,
+    http::{HeaderName, HeaderValue, Method, header},
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::configuration::{CorsSettings, SecurityHeaderSettings};
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// Answers preflight requests and adds the CORS headers for the configured
+/// origins. Values that do not parse are skipped, the configuration
+/// validation reports them.
+pub fn cors(settings: &CorsSettings) -> CorsLayer {
+    let origins = if settings.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            settings
+                .allowed_origins
+                .iter()
+                .filter_map(|origin| origin.parse::<HeaderValue>().ok()),
+        )
+    };
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(
+            settings
+                .allowed_methods
+                .iter()
+                .filter_map(|method| method.parse::<Method>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .allow_headers(
+            settings
+                .allowed_headers
+                .iter()
+                .filter_map(|name| name.parse::<HeaderName>().ok())
+                .collect::<Vec<_>>(),
+        )
+        .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+        .max_age(Duration::from_secs(settings.max_age_seconds))
+}
+
+/// Adds the headers hardening how browsers handle every response, without
+/// replacing those set by a handler. Values that do not parse are skipped,
+/// the configuration validation reports them.
+pub fn security_headers<S>(router: Router<S>, settings: &SecurityHeaderSettings) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let hsts = settings
+        .strict_transport_security()
+        .and_then(|value| HeaderValue::from_str(&value).ok());
+    let csp = HeaderValue::from_str(&settings.content_security_policy).ok();
+    router
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::REFERRER_POLICY,
+            HeaderValue::from_str(&settings.referrer_policy).ok(),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::STRICT_TRANSPORT_SECURITY,
+            hsts,
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CONTENT_SECURITY_POLICY,
+            move |response: &Response| is_html(response).then(|| csp.clone()).flatten(),
+        ))
+}
+
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{HeaderMap, HeaderValue, Method, Request, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, types::Uuid};
+
+use crate::configuration::Settings;
+use crate::roles::{Permission, Role};
+use crate::startup::AppState;
+
+const COOKIE_NAME: &str = "admin_session";
+/// Holds the nonce the login form CSRF token is derived from, so no session
+/// is stored for anonymous visitors
+const LOGIN_COOKIE_NAME: &str = "admin_login";
+
+/// Where visitors without a logged in session are sent
+pub const LOGIN_PATH: &str = "/admin/login";
+
+/// Dashboard sessions, stored in Postgres and identified by a random token
+/// in a cookie scoped to `/admin`. They only exist once logged in.
+#[derive(Clone)]
+pub struct Sessions {
+    pool: PgPool,
+    ttl_minutes: i32,
+    /// Only send the cookie over HTTPS when the service is served over it
+    secure: bool,
+    /// Signs the login form CSRF tokens
+    login_key: Arc<SecretBox<String>>,
+}
+
+/// A logged in dashboard session
+pub struct Session {
+    token_hash: String,
+    pub admin_user_id: Uuid,
+    pub username: String,
+    role: String,
+    /// Expected in the `csrf_token` field of every form posted with this
+    /// session
+    pub csrf_token: String,
+    /// Set by the previous request, only returned once
+    pub flash: Option<String>,
+}
+
+impl Session {
+    /// The role of the logged in user, none when it is no longer known
+    #[must_use]
+    pub fn role(&self) -> Option<Role> {
+        self.role.parse().ok()
+    }
+
+    #[must_use]
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role().is_some_and(|role| role.can(permission))
+    }
+}
+
+impl Sessions {
+    #[must_use]
+    pub fn from_settings(settings: &Settings, pool: PgPool) -> Self {
+        Self {
+            pool,
+            ttl_minutes: i32::try_from(settings.admin.session_ttl_minutes).unwrap_or(i32::MAX),
+            secure: settings.application.base_url.starts_with("https://"),
+            login_key: Arc::new(settings.application.signing_key("admin-login")),
+        }
+    }
+
+    /// Starts a session for `admin_user_id`, returning the `Set-Cookie`
+    /// header handing it to the browser. `flash` is shown by the next
+    /// request, not taken from the returned session.
+    ///
+    /// # Errors
+    ///
+    /// Database error
+    pub async fn start(
+        &self,
+        admin_user_id: Uuid,
+        flash: Option<&str>,
+    ) -> Result<(HeaderValue, Session), sqlx::Error> {
+        let token = random_token();
+        let mut session = sqlx::query_as!(
+            Session,
+            r#"
+            insert into admin_session (token_hash, admin_user_id, csrf_token, flash, expires_at)
+            values ($1, $2, $3, $4, now() + make_interval(mins => $5))
+            returning
+                token_hash,
+                admin_user_id,
+                (select username from admin_user where id = admin_user_id) as "username!",
+                (select role from admin_user where id = admin_user_id) as "role!",
+                csrf_token,
+                flash
+            "#,
+            hash(&token),
+            admin_user_id,
+            random_token(),
+            flash,
+            self.ttl_minutes,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        session.flash = None;
+        Ok((self.cookie(COOKIE_NAME, "/admin", &token), session))
+    }
+
+    /// Loads the session of the request, taking its flash message and
+    /// extending its lifetime
+    ///
+    /// # Errors
+    ///
+    /// Database error
+    pub async fn load(&self, headers: &HeaderMap) -> Result<Option<Session>, sqlx::Error> {
+        let Some(token) = cookie(headers, COOKIE_NAME) else {
+            return Ok(None);
+        };
+        sqlx::query_as!(
+            Session,
+            r#"
+            update admin_session s
+            set flash = null, expires_at = now() + make_interval(mins => $2)
+            from (
+                select token_hash, flash from admin_session
+                where token_hash = $1 and expires_at > now()
+                for update
+            ) previous
+            where s.token_hash = previous.token_hash
+            returning
+                s.token_hash,
+                s.admin_user_id,
+                (select username from admin_user where id = s.admin_user_id) as "username!",
+                (select role from admin_user where id = s.admin_user_id) as "role!",
+                s.csrf_token,
+                previous.flash
+            "#,
+            hash(token),
+            self.ttl_minutes,
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Shows `message` on the next page loaded with `session`
+    ///
+    /// # Errors
+    ///
+    /// Database error
+    pub async fn flash(&self, session: &Session, message: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update admin_session set flash = $2 where token_hash = $1",
+            session.token_hash,
+            message,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Database error
+    pub async fn end(&self, session: &Session) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from admin_session where token_hash = $1",
+            session.token_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The CSRF token of the login form, with the `Set-Cookie` header to send
+    /// when the visitor has no login cookie yet
+    #[must_use]
+    pub fn login_csrf_token(&self, headers: &HeaderMap) -> (String, Option<HeaderValue>) {
+        if let Some(nonce) = cookie(headers, LOGIN_COOKIE_NAME) {
+            return (self.sign(nonce), None);
+        }
+        let nonce = random_token();
+        let cookie = self.cookie(LOGIN_COOKIE_NAME, LOGIN_PATH, &nonce);
+        (self.sign(&nonce), Some(cookie))
+    }
+
+    /// The tokens a form may carry: the one of the session, and the one of
+    /// the login form, whose cookie is only sent to the login page
+    async fn csrf_tokens(&self, headers: &HeaderMap) -> Result<Vec<String>, sqlx::Error> {
+        let mut tokens = Vec::new();
+        if let Some(token) = cookie(headers, COOKIE_NAME) {
+            tokens.extend(
+                sqlx::query_scalar!(
+                    "select csrf_token from admin_session where token_hash = $1 and expires_at > now()",
+                    hash(token)
+                )
+                .fetch_optional(&self.pool)
+                .await?,
+            );
+        }
+        tokens.extend(cookie(headers, LOGIN_COOKIE_NAME).map(|nonce| self.sign(nonce)));
+        Ok(tokens)
+    }
+
+    fn sign(&self, nonce: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.login_key.expose_secret().as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(b"admin-login:");
+        mac.update(nonce.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn cookie(&self, name: &str, path: &str, value: &str) -> HeaderValue {
+        let secure = if self.secure { "; Secure" } else { "" };
+        HeaderValue::from_str(&format!(
+            "{name}={value}; Path={path}; HttpOnly; SameSite=Lax{secure}"
+        ))
+        .expect("The token is hex encoded")
+    }
+}
+
+/// Deletes the expired sessions, returning how many were deleted
+///
+/// # Errors
+///
+/// Database error
+pub async fn delete_expired_sessions(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query!("delete from admin_session where expires_at < now()")
+        .execute(pool)
+        .await?;
+    Ok(deleted.rows_affected())
+}
+
+/// A logged in session, visitors without one are redirected to the login
+/// page
+pub struct AdminSession(pub Session);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminSession {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Response> {
+        match state.sessions.load(&parts.headers).await {
+            Ok(Some(session)) => Ok(Self(session)),
+            Ok(None) => Err(Redirect::to(LOGIN_PATH).into_response()),
+            Err(e) => {
+                tracing::error!("Failed to load the session: {:?}", e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+            }
+        }
+    }
+}
+
+/// Refuses forms posted without the CSRF token of their session
+pub async fn verify_csrf(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    // Already bounded by `request_limits`
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let submitted = url::form_urlencoded::parse(&bytes)
+        .find(|(name, _)| name == "csrf_token")
+        .map(|(_, value)| value.into_owned());
+    let expected = match state.sessions.csrf_tokens(&parts.headers).await {
+        Ok(expected) => expected,
+        Err(e) => {
+            tracing::error!("Failed to load the session: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match submitted {
+        Some(submitted)
+            if expected
+                .iter()
+                .any(|expected| constant_time_eq(&submitted, expected)) =>
+        {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        _ => {
+            tracing::warn!("Refused a form without a valid CSRF token");
+            (StatusCode::FORBIDDEN, "Invalid or missing CSRF token").into_response()
+        }
+    }
+}
+
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (pair_name, value) = pair.trim().split_once('=')?;
+            (pair_name == name && !value.is_empty()).then_some(value)
+        })
+}
+
+fn random_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Only hashes of the tokens are stored
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, header};
+
+    use super::{COOKIE_NAME, LOGIN_COOKIE_NAME, constant_time_eq, cookie};
+
+    #[test]
+    fn session_cookie_is_found_among_others() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; admin_session=abc123; other=1"),
+        );
+        assert_eq!(cookie(&headers, COOKIE_NAME), Some("abc123"));
+        assert_eq!(cookie(&headers, LOGIN_COOKIE_NAME), None);
+
+        headers.insert(header::COOKIE, HeaderValue::from_static("admin_session="));
+        assert_eq!(cookie(&headers, COOKIE_NAME), None);
+    }
+
+    #[test]
+    fn tokens_are_compared_in_full() {
+        assert!(constant_time_eq("abc", "abc"));
+        assert!(!constant_time_eq("abc", "abd"));
+        assert!(!constant_time_eq("abc", "abcd"));
+    }
+}
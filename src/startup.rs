@@ -1,33 +1,279 @@
-use axum::{Router, body::Body, routing::get};
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{HeaderName, HeaderValue, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{MethodRouter, delete, get, post},
+};
 use hyper::Request;
+use metrics_exporter_prometheus::PrometheusHandle;
 use sqlx::PgPool;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
 use tower_http::trace::TraceLayer;
 use tracing::Level;
 
-use crate::routes::{create_subscriber, health_check};
+use crate::bot_protection::BotProtection;
+use crate::configuration::Settings;
+use crate::email_client::EmailClient;
+use crate::pages::Pages;
+use crate::prometheus::{self, track_requests};
+use crate::rate_limit::{LoginLimiter, RateLimiter, limit_subscriptions};
+use crate::request_id::{REQUEST_ID_HEADER, drop_invalid_request_id, include_request_id_in_errors};
+use crate::request_limits::{compression, request_limits};
+use crate::routes::{
+    FormRedirects, add_confirmed_subscriber, api_docs, check_inbox_page, compose_issue_page,
+    confirm_subscription, create_issue, create_key, create_subscriber, dashboard, docs_page,
+    health_check, issue_delivery_report, issue_engagement, issues_page, list_keys,
+    list_subscribers, login, login_page, logout, metrics, openapi_json, preferences_page,
+    publish_issue, redirect_form_errors, revoke_key, submit_subscribe_page, subscribe_page,
+    subscribers_page, track_click, track_open, unsubscribe, unsubscribe_page, update_preferences,
+    update_role, users_page,
+};
+use crate::security_headers::{cors, security_headers};
+use crate::sessions::{Sessions, verify_csrf};
+use crate::subscription_links::SubscriptionLinks;
+use crate::telemetry::set_remote_parent;
+use crate::tracking::Tracker;
+use crate::webhooks::Webhooks;
+
+/// When the unversioned `/api` paths were deprecated, as an RFC 9745 date
+const UNVERSIONED_API_DEPRECATION: &str = "@1792368000";
+/// When the unversioned `/api` paths stop being served
+const UNVERSIONED_API_SUNSET: &str = "Mon, 19 Apr 2027 00:00:00 GMT";
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
+    pub tracker: Tracker,
+    pub webhooks: Webhooks,
+    pub bot_protection: BotProtection,
+    pub metrics: PrometheusHandle,
+    pub form_redirects: FormRedirects,
+    pub links: SubscriptionLinks,
+    pub pages: Pages,
+    /// Sends the confirmation emails of new subscribers
+    pub email_client: EmailClient,
+    pub sessions: Sessions,
+    pub login_limiter: LoginLimiter,
+    /// Subscribers listed per page of the dashboard
+    pub admin_page_size: u32,
+    /// The page of `/api/docs`, rendered once
+    pub api_docs: Arc<str>,
 }
 
-pub fn generate_routes(pool: PgPool) -> Router {
-    let state = AppState { pool };
-    Router::new()
-        .route("/healthcheck", get(health_check))
-        .route("/api/subscriber", axum::routing::post(create_subscriber))
+/// A router that remembers the paths registered on it, so they can be
+/// compared with the `OpenAPI` document.
+struct Routes {
+    router: Router<AppState>,
+    paths: Vec<String>,
+}
+
+impl Routes {
+    fn new() -> Self {
+        Self {
+            router: Router::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    fn route(mut self, path: &str, method_router: MethodRouter<AppState>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self.paths.push(path.to_string());
+        self
+    }
+
+    /// HTML pages are not part of the documented API, so they are not recorded
+    fn page(mut self, path: &str, method_router: MethodRouter<AppState>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    /// Merges a router of HTML pages, its paths are not recorded
+    fn pages(mut self, router: Router<AppState>) -> Self {
+        self.router = self.router.merge(router);
+        self
+    }
+
+    fn nest(mut self, prefix: &str, routes: Self) -> Self {
+        self.router = self.router.nest(prefix, routes.router);
+        self.paths.extend(
+            routes
+                .paths
+                .into_iter()
+                .map(|path| format!("{prefix}{path}")),
+        );
+        self
+    }
+
+    /// Serves `routes` under `prefix` as deprecated aliases. Aliases are not
+    /// recorded since they are not part of the documented API.
+    fn nest_deprecated(mut self, prefix: &str, routes: Self) -> Self {
+        self.router = self.router.nest(
+            prefix,
+            routes
+                .router
+                .layer(middleware::from_fn(deprecate_unversioned_api)),
+        );
+        self
+    }
+}
+
+pub fn generate_routes(pool: &PgPool, configuration: &Settings) -> Router {
+    let (Routes { router, .. }, state) = build_routes(pool, configuration);
+    let router = router.route_layer(middleware::from_fn(track_requests));
+    let router = request_limits(router, configuration.application.limits)
+        .layer(middleware::from_fn_with_state(
+            state.form_redirects.clone(),
+            redirect_form_errors,
+        ))
+        .layer(middleware::from_fn(include_request_id_in_errors))
+        // Preflight requests are answered before reaching the rate limiter
+        .layer(cors(&configuration.application.cors));
+    security_headers(router, &configuration.application.security_headers)
+        .layer(compression(&configuration.application.compression))
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &Request<Body>| {
-                let request_id = uuid::Uuid::new_v4();
-                tracing::span!(
-                    Level::DEBUG,
+                let request_id = request
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default();
+                let span = tracing::span!(
+                    Level::INFO,
                     "request",
                     method = tracing::field::display(request.method()),
                     uri = tracing::field::display(request.uri()),
                     version = tracing::field::debug(request.version()),
                     request_id = tracing::field::display(request_id)
-                )
+                );
+                set_remote_parent(&span, request.headers());
+                span
             }),
         )
+        // Honours a valid id sent by the client, or generates one, before
+        // the span is created
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(middleware::from_fn(drop_invalid_request_id))
         .with_state(state)
 }
+
+/// Paths served by `generate_routes`, using axum's `:param` syntax
+#[must_use]
+pub fn registered_paths(pool: &PgPool, configuration: &Settings) -> Vec<String> {
+    build_routes(pool, configuration).0.paths
+}
+
+fn build_routes(pool: &PgPool, configuration: &Settings) -> (Routes, AppState) {
+    let rate_limiter =
+        RateLimiter::from_settings(&configuration.application.rate_limit, pool.clone());
+    let state = AppState {
+        webhooks: Webhooks::from_settings(&configuration.webhooks, pool.clone()),
+        sessions: Sessions::from_settings(configuration, pool.clone()),
+        login_limiter: LoginLimiter::new(
+            rate_limiter.clone(),
+            &configuration.admin.login_rate_limit,
+        ),
+        pool: pool.clone(),
+        tracker: Tracker::from_settings(configuration),
+        bot_protection: BotProtection::from_settings(configuration),
+        metrics: prometheus::recorder(),
+        form_redirects: FormRedirects::from_settings(&configuration.application.forms),
+        links: SubscriptionLinks::from_settings(configuration),
+        pages: Pages::from_settings(configuration),
+        email_client: EmailClient::from_settings(&configuration.email_client),
+        admin_page_size: configuration.admin.page_size,
+        api_docs: docs_page(&configuration.application.api_docs).into(),
+    };
+    let routes = Routes::new()
+        .route("/healthcheck", get(health_check))
+        .route("/metrics", get(metrics))
+        .route("/api/openapi.json", get(openapi_json))
+        .route("/api/docs", get(api_docs))
+        .nest("/api/v1", api_v1(&rate_limiter))
+        .nest_deprecated("/api", api_v1(&rate_limiter))
+        .route("/t/open/:issue_id/:subscriber_id", get(track_open))
+        .route("/t/click/:issue_id/:subscriber_id", get(track_click))
+        .page(
+            "/subscribe",
+            get(subscribe_page).merge(post(submit_subscribe_page).route_layer(
+                middleware::from_fn_with_state(rate_limiter.clone(), limit_subscriptions),
+            )),
+        )
+        .page("/subscribe/check-inbox", get(check_inbox_page))
+        .page("/subscribe/confirm", get(confirm_subscription))
+        .page("/unsubscribe", get(unsubscribe_page).post(unsubscribe))
+        .page(
+            "/preferences",
+            get(preferences_page).post(update_preferences),
+        )
+        .pages(admin_dashboard(&state));
+    (routes, state)
+}
+
+/// The admin dashboard, for editors using a browser. Every form posted to it
+/// carries the CSRF token of the session, and pages check the role of its
+/// user with `Authorized`.
+fn admin_dashboard(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin", get(dashboard))
+        .route("/admin/login", get(login_page).post(login))
+        .route("/admin/logout", post(logout))
+        .route("/admin/subscribers", get(subscribers_page))
+        .route("/admin/issues", get(issues_page).post(create_issue))
+        .route("/admin/issues/new", get(compose_issue_page))
+        .route("/admin/users", get(users_page))
+        .route("/admin/users/:user_id/role", post(update_role))
+        .route_layer(middleware::from_fn_with_state(state.clone(), verify_csrf))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ))
+}
+
+/// Version 1 of the API. Breaking payload changes go in an `api_v2` next to
+/// this one, nested under `/api/v2` in `build_routes`, reusing the handlers
+/// that did not change.
+fn api_v1(rate_limiter: &RateLimiter) -> Routes {
+    Routes::new()
+        .route(
+            "/subscriber",
+            post(create_subscriber).route_layer(middleware::from_fn_with_state(
+                rate_limiter.clone(),
+                limit_subscriptions,
+            )),
+        )
+        .route(
+            "/admin/subscribers",
+            get(list_subscribers).post(add_confirmed_subscriber),
+        )
+        .route("/admin/issues", post(publish_issue))
+        .route("/admin/issues/:issue_id/engagement", get(issue_engagement))
+        .route("/admin/issues/:issue_id/report", get(issue_delivery_report))
+        .route("/admin/api-keys", get(list_keys).post(create_key))
+        .route("/admin/api-keys/:key_id", delete(revoke_key))
+}
+
+/// Flags responses served from the unversioned `/api` paths as deprecated
+/// in favour of `/api/v1`.
+async fn deprecate_unversioned_api(request: Request<Body>, next: Next<Body>) -> Response {
+    let successor = format!("/api/v1{}", request.uri().path());
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static("deprecation"),
+        HeaderValue::from_static(UNVERSIONED_API_DEPRECATION),
+    );
+    headers.insert(
+        HeaderName::from_static("sunset"),
+        HeaderValue::from_static(UNVERSIONED_API_SUNSET),
+    );
+    if let Ok(link) = HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\"")) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
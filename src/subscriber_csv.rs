@@ -0,0 +1,109 @@
+use std::io::{Read, Write};
+
+use miette::{IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, types::Uuid};
+
+use crate::domain::{SubscriberEmail, SubscriberName};
+
+#[derive(Deserialize)]
+struct ImportRow {
+    email: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct ExportRow {
+    id: Uuid,
+    email: String,
+    name: String,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub imported: u64,
+    /// Rows whose email is already subscribed
+    pub existing: u64,
+    /// Line numbers of the rows that were skipped, with the reason
+    pub invalid: Vec<(u64, String)>,
+}
+
+/// Adds the subscribers listed in a CSV file with `email` and `name`
+/// columns, in a single transaction. Invalid rows are skipped and reported.
+///
+/// # Errors
+///
+/// - The file is not valid CSV
+/// - The database cannot be written to
+#[tracing::instrument(name = "Importing subscribers", skip_all)]
+pub async fn import_subscribers(pool: &PgPool, reader: impl Read) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut transaction = pool.begin().await.into_diagnostic()?;
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().into_diagnostic()?.clone();
+    for record in reader.records() {
+        let record = record.into_diagnostic()?;
+        let line = record.position().map_or(0, csv::Position::line);
+        let row = match record.deserialize::<ImportRow>(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                summary.invalid.push((line, e.to_string()));
+                continue;
+            }
+        };
+        let (email, name) = match (
+            SubscriberEmail::parse(row.email.trim().to_string()),
+            SubscriberName::parse(row.name.trim().to_string()),
+        ) {
+            (Ok(email), Ok(name)) => (email, name),
+            (Err(_), _) => {
+                summary.invalid.push((line, "invalid email".to_string()));
+                continue;
+            }
+            (_, Err(_)) => {
+                summary.invalid.push((line, "invalid name".to_string()));
+                continue;
+            }
+        };
+        let inserted = sqlx::query!(
+            "insert into subscriber (name, email) values ($1, $2) on conflict (email) do nothing",
+            name.as_ref(),
+            email.as_ref(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .into_diagnostic()?
+        .rows_affected();
+        if inserted == 0 {
+            summary.existing += 1;
+        } else {
+            summary.imported += 1;
+        }
+    }
+    transaction.commit().await.into_diagnostic()?;
+    Ok(summary)
+}
+
+/// Writes every subscriber as CSV with `id`, `email` and `name` columns,
+/// which `import_subscribers` accepts back. Returns the number of rows.
+///
+/// # Errors
+///
+/// - The database cannot be read
+/// - `writer` fails
+#[tracing::instrument(name = "Exporting subscribers", skip_all)]
+pub async fn export_subscribers(pool: &PgPool, writer: impl Write) -> Result<usize> {
+    let subscribers = sqlx::query_as!(
+        ExportRow,
+        "select id, email, name from subscriber order by email"
+    )
+    .fetch_all(pool)
+    .await
+    .into_diagnostic()?;
+    let mut writer = csv::Writer::from_writer(writer);
+    for subscriber in &subscribers {
+        writer.serialize(subscriber).into_diagnostic()?;
+    }
+    writer.flush().into_diagnostic()?;
+    Ok(subscribers.len())
+}
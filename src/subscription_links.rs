@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+
+/// What a signed subscriber link lets its holder do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkAction {
+    Confirm,
+    Unsubscribe,
+    /// Open the preference centre
+    ManagePreferences,
+}
+
+impl LinkAction {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Confirm => "confirm",
+            Self::Unsubscribe => "unsubscribe",
+            Self::ManagePreferences => "preferences",
+        }
+    }
+
+    const fn path(self) -> &'static str {
+        match self {
+            Self::Confirm => "/subscribe/confirm",
+            Self::Unsubscribe => "/unsubscribe",
+            Self::ManagePreferences => "/preferences",
+        }
+    }
+}
+
+/// Builds and verifies the signed links handed out to subscribers, so they
+/// can manage their subscription without an account.
+#[derive(Clone)]
+pub struct SubscriptionLinks {
+    base_url: String,
+    hmac_secret: Arc<SecretBox<String>>,
+}
+
+impl SubscriptionLinks {
+    #[must_use]
+    pub fn new(base_url: &str, hmac_secret: SecretBox<String>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            hmac_secret: Arc::new(hmac_secret),
+        }
+    }
+
+    #[must_use]
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            &settings.application.base_url,
+            settings.application.signing_key("subscription-links"),
+        )
+    }
+
+    /// Link letting `subscriber_id` perform `action`
+    #[must_use]
+    pub fn url(&self, action: LinkAction, subscriber_id: Uuid) -> String {
+        format!(
+            "{}{}?subscriber_id={subscriber_id}&sig={}",
+            self.base_url,
+            action.path(),
+            self.sign(action, subscriber_id)
+        )
+    }
+
+    /// Adds the links managing the subscription to a webhook payload
+    #[must_use]
+    pub fn attach<'a, T>(
+        &self,
+        subscriber: &'a T,
+        subscriber_id: Uuid,
+        pending: bool,
+    ) -> WithLinks<'a, T> {
+        WithLinks {
+            subscriber,
+            confirmation_url: pending.then(|| self.url(LinkAction::Confirm, subscriber_id)),
+            preferences_url: self.url(LinkAction::ManagePreferences, subscriber_id),
+            unsubscribe_url: self.url(LinkAction::Unsubscribe, subscriber_id),
+        }
+    }
+
+    #[must_use]
+    pub fn verify(&self, action: LinkAction, subscriber_id: Uuid, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        let mut mac = self.mac();
+        mac.update(message(action, subscriber_id).as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn sign(&self, action: LinkAction, subscriber_id: Uuid) -> String {
+        let mut mac = self.mac();
+        mac.update(message(action, subscriber_id).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+}
+
+/// A subscriber with the links whoever emails them needs
+#[derive(Serialize)]
+pub struct WithLinks<'a, T> {
+    #[serde(flatten)]
+    subscriber: &'a T,
+    /// Only while the address is not confirmed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirmation_url: Option<String>,
+    preferences_url: String,
+    unsubscribe_url: String,
+}
+
+fn message(action: LinkAction, subscriber_id: Uuid) -> String {
+    format!("subscription:{}:{subscriber_id}", action.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use secrecy::SecretBox;
+    use uuid::Uuid;
+
+    use super::{LinkAction, SubscriptionLinks};
+    use crate::configuration::load_configuration;
+
+    fn links(secret: &str) -> SubscriptionLinks {
+        SubscriptionLinks::new(
+            "https://news.example.com/",
+            SecretBox::new(Box::new(secret.to_string())),
+        )
+    }
+
+    fn signature(url: &str) -> &str {
+        url.rsplit_once("sig=").unwrap().1
+    }
+
+    #[test]
+    fn links_are_only_valid_for_their_action_and_subscriber() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let url = links.url(LinkAction::Confirm, subscriber_id);
+        assert!(url.starts_with(&format!(
+            "https://news.example.com/subscribe/confirm?subscriber_id={subscriber_id}&sig="
+        )));
+
+        let sig = signature(&url);
+        assert!(links.verify(LinkAction::Confirm, subscriber_id, sig));
+        assert!(!links.verify(LinkAction::Unsubscribe, subscriber_id, sig));
+        assert!(!links.verify(LinkAction::Confirm, Uuid::new_v4(), sig));
+        assert!(!links.verify(LinkAction::Confirm, subscriber_id, "not-hex"));
+    }
+
+    #[test]
+    fn links_depend_on_the_secret() {
+        let subscriber_id = Uuid::new_v4();
+        let url = links("secret").url(LinkAction::Unsubscribe, subscriber_id);
+        assert!(!links("another-secret").verify(
+            LinkAction::Unsubscribe,
+            subscriber_id,
+            signature(&url)
+        ));
+    }
+
+    #[test]
+    fn links_signed_with_the_local_key_are_refused_in_production() {
+        let load = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect();
+            SubscriptionLinks::from_settings(
+                &load_configuration(Path::new("configuration"), vars).unwrap(),
+            )
+        };
+        let local = load(&[]);
+        let production = load(&[
+            ("APP_ENVIRONMENT", "production"),
+            ("APP_BOT_PROTECTION__CHALLENGE__SECRET_KEY", "challenge"),
+            ("APP_TELEMETRY__REDACTION__KEY", "redaction"),
+            ("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN", "token"),
+            ("APP_APPLICATION__API_DOCS__REDOC_INTEGRITY", "sha384-abc"),
+            (
+                "APP_APPLICATION__HMAC_SECRET",
+                "a-production-key-of-at-least-32-characters",
+            ),
+        ]);
+
+        let subscriber_id = Uuid::new_v4();
+        for action in [LinkAction::Unsubscribe, LinkAction::ManagePreferences] {
+            let url = local.url(action, subscriber_id);
+            assert!(local.verify(action, subscriber_id, signature(&url)));
+            assert!(!production.verify(action, subscriber_id, signature(&url)));
+        }
+    }
+}
@@ -1,14 +1,54 @@
 use std::str::FromStr;
+use std::time::Duration;
 
-use miette::{Error, Result, miette};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use miette::{Error, IntoDiagnostic, Result, miette};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider};
 use tracing::level_filters::LevelFilter;
+use tracing::{Level, Subscriber};
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt};
 
+use crate::configuration::{LogFormat, OtlpProtocol, TelemetrySettings};
+use crate::prometheus::PoolAcquireLayer;
+use crate::redaction;
+
+/// Flushes the spans still buffered for export when dropped
+pub struct TracingGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Installs the global subscriber, logging to stdout at `log_level` in the
+/// configured format with personal data redacted, and exporting traces over
+/// OTLP when an endpoint is configured.
+///
 /// # Errors
 ///
 /// - Invalid log-level value supplied
-pub fn init_tracing_subscriber(log_level: &str) -> Result<(), Error> {
+/// - Invalid OTLP exporter settings
+/// - Called more than once
+pub fn init_tracing_subscriber(
+    log_level: &str,
+    settings: &TelemetrySettings,
+) -> Result<TracingGuard, Error> {
     let level_filter =
         LevelFilter::from_str(log_level).map_err(|x| miette!("Invalid Log level: {:?}", x))?;
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
@@ -19,9 +59,183 @@ pub fn init_tracing_subscriber(log_level: &str) -> Result<(), Error> {
         )
         .into()
     });
+    let provider = settings
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| tracer_provider(endpoint, settings))
+        .transpose()?;
+    // Exported traces do not depend on how verbose the logs are
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(env!("CARGO_CRATE_NAME")))
+            .with_filter(
+                Targets::new()
+                    .with_target(env!("CARGO_CRATE_NAME"), Level::INFO)
+                    .with_target("tower_http", Level::INFO),
+            )
+    });
+    redaction::init(&settings.redaction)?;
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(env_filter)
+        .with(
+            log_layer(settings.log_format, &settings.service_name, std::io::stdout)
+                .with_filter(env_filter),
+        )
+        .with(otel_layer)
+        .with(
+            PoolAcquireLayer
+                .with_filter(Targets::new().with_target(PoolAcquireLayer::TARGET, Level::TRACE)),
+        )
         .init();
-    Ok(())
+    Ok(TracingGuard { provider })
+}
+
+/// Formats log lines written to `make_writer`
+fn log_layer<S, W>(format: LogFormat, name: &str, make_writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .with_writer(make_writer)
+            .boxed(),
+        LogFormat::Json => JsonStorageLayer
+            .and_then(BunyanFormattingLayer::new(name.to_string(), make_writer))
+            .boxed(),
+    }
+}
+
+fn tracer_provider(endpoint: &str, settings: &TelemetrySettings) -> Result<SdkTracerProvider> {
+    let protocol = match settings.otlp_protocol {
+        OtlpProtocol::HttpProtobuf => Protocol::HttpBinary,
+        OtlpProtocol::HttpJson => Protocol::HttpJson,
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .with_protocol(protocol)
+        .build()
+        .into_diagnostic()?;
+    let processor = BatchSpanProcessor::builder(exporter)
+        .with_batch_config(
+            BatchConfigBuilder::default()
+                .with_scheduled_delay(Duration::from_millis(settings.export_delay_milliseconds))
+                .build(),
+        )
+        .build();
+    Ok(SdkTracerProvider::builder()
+        .with_span_processor(processor)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// Continues the trace described by the W3C `traceparent` header of an
+/// incoming request, if any, in `span`.
+pub fn set_remote_parent(span: &tracing::Span, headers: &HeaderMap) {
+    let context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    let _ = span.set_parent(context);
+}
+
+/// W3C trace context headers for an outbound request made from the current span
+#[must_use]
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers));
+    });
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_str(key), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::fmt::MakeWriter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::log_layer;
+    use crate::configuration::LogFormat;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MakeWriter<'_> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn json_lines_include_the_enclosing_span_fields() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(log_layer(
+            LogFormat::Json,
+            "z2p-axum",
+            buffer.clone(),
+        ));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = "abc", subscriber_name = "Joe");
+            let _entered = span.enter();
+            tracing::info!("Adding a new subscriber");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| {
+                line["msg"]
+                    .as_str()
+                    .is_some_and(|msg| msg.ends_with("Adding a new subscriber"))
+            })
+            .expect("the event was not logged");
+        assert_eq!(line["name"], "z2p-axum");
+        assert_eq!(line["request_id"], "abc");
+        assert_eq!(line["subscriber_name"], "Joe");
+        assert!(line["time"].is_string());
+    }
 }
@@ -0,0 +1,67 @@
+use std::net::{SocketAddr, TcpListener};
+use std::{path::Path, time::Duration, time::SystemTime};
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use miette::{IntoDiagnostic, Result, WrapErr};
+
+use crate::configuration::TlsSettings;
+
+/// Serves `router` over HTTPS on `listener`. HTTP/2 and HTTP/1.1 are both
+/// offered over ALPN, and the certificate is reloaded when its files change.
+///
+/// # Errors
+///
+/// - The certificate or key cannot be read
+/// - The listener cannot be used by tokio
+pub async fn serve_tls(
+    listener: TcpListener,
+    router: Router,
+    settings: &TlsSettings,
+) -> Result<()> {
+    let config = RustlsConfig::from_pem_file(&settings.cert_path, &settings.key_path)
+        .await
+        .into_diagnostic()
+        .wrap_err("Failed to load the TLS certificate")?;
+    tokio::spawn(watch(config.clone(), settings.clone()));
+    listener.set_nonblocking(true).into_diagnostic()?;
+    axum_server::from_tcp_rustls(listener, config)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .into_diagnostic()
+}
+
+/// Polls the modification times of the certificate and key, and reloads
+/// them when either changes. A pair that fails to load is logged and the
+/// previous certificate is kept.
+async fn watch(config: RustlsConfig, settings: TlsSettings) {
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(settings.reload_interval_milliseconds));
+    let mut loaded = modified(&settings);
+    loop {
+        interval.tick().await;
+        let current = modified(&settings);
+        if current == loaded {
+            continue;
+        }
+        match config
+            .reload_from_pem_file(&settings.cert_path, &settings.key_path)
+            .await
+        {
+            Ok(()) => {
+                tracing::info!(cert_path = %settings.cert_path.display(), "Reloaded the TLS certificate");
+                loaded = current;
+            }
+            Err(e) => tracing::error!(
+                error = %e,
+                cert_path = %settings.cert_path.display(),
+                "Failed to reload the TLS certificate, keeping the previous one"
+            ),
+        }
+    }
+}
+
+fn modified(settings: &TlsSettings) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (mtime(&settings.cert_path), mtime(&settings.key_path))
+}
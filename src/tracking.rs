@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use sha2::Sha256;
+use url::form_urlencoded::byte_serialize;
+use uuid::Uuid;
+
+use crate::configuration::Settings;
+
+/// Builds and verifies the signed URLs used to record opens and clicks on
+/// newsletter issues.
+#[derive(Clone)]
+pub struct Tracker {
+    base_url: String,
+    hmac_secret: Arc<SecretBox<String>>,
+    enabled: bool,
+}
+
+impl Tracker {
+    #[must_use]
+    pub fn new(base_url: &str, hmac_secret: SecretBox<String>, enabled: bool) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            hmac_secret: Arc::new(hmac_secret),
+            enabled,
+        }
+    }
+
+    #[must_use]
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self::new(
+            &settings.application.base_url,
+            settings.application.signing_key("tracking"),
+            settings.tracking.enabled,
+        )
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// URL of the tracking pixel for a single recipient of an issue.
+    #[must_use]
+    pub fn open_url(&self, issue_id: Uuid, subscriber_id: Uuid) -> String {
+        let signature = self.sign(&open_message(issue_id, subscriber_id));
+        format!(
+            "{}/t/open/{issue_id}/{subscriber_id}?sig={signature}",
+            self.base_url
+        )
+    }
+
+    /// Redirect URL that records a click before sending the recipient on to `target`.
+    #[must_use]
+    pub fn click_url(&self, issue_id: Uuid, subscriber_id: Uuid, target: &str) -> String {
+        let signature = self.sign(&click_message(issue_id, subscriber_id, target));
+        let target: String = byte_serialize(target.as_bytes()).collect();
+        format!(
+            "{}/t/click/{issue_id}/{subscriber_id}?url={target}&sig={signature}",
+            self.base_url
+        )
+    }
+
+    #[must_use]
+    pub fn verify_open(&self, issue_id: Uuid, subscriber_id: Uuid, signature: &str) -> bool {
+        self.verify(&open_message(issue_id, subscriber_id), signature)
+    }
+
+    #[must_use]
+    pub fn verify_click(
+        &self,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+        target: &str,
+        signature: &str,
+    ) -> bool {
+        self.verify(&click_message(issue_id, subscriber_id, target), signature)
+    }
+
+    /// Rewrites every `http(s)` link in `html` into a signed click-tracking
+    /// redirect and appends the open-tracking pixel.
+    ///
+    /// The HTML is returned untouched when tracking is disabled.
+    #[must_use]
+    pub fn instrument_html(&self, html: &str, issue_id: Uuid, subscriber_id: Uuid) -> String {
+        if !self.enabled {
+            return html.to_string();
+        }
+        let lowercase = html.to_ascii_lowercase();
+        let mut output = String::with_capacity(html.len());
+        let mut cursor = 0;
+        while let Some(offset) = lowercase[cursor..].find("href=") {
+            let attribute_start = cursor + offset;
+            let value_start = attribute_start + "href=".len();
+            let preceded_by_whitespace = html[..attribute_start]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_whitespace);
+            let quote = html[value_start..].chars().next();
+            let (true, Some(quote @ ('"' | '\''))) = (preceded_by_whitespace, quote) else {
+                output.push_str(&html[cursor..value_start]);
+                cursor = value_start;
+                continue;
+            };
+            let Some(value_length) = html[value_start + 1..].find(quote) else {
+                break;
+            };
+            let value_end = value_start + 1 + value_length;
+            let raw_link = &html[value_start + 1..value_end];
+            let link = raw_link.replace("&amp;", "&");
+
+            output.push_str(&html[cursor..=value_start]);
+            if let Some(target) = trackable_url(&link) {
+                output.push_str(
+                    &self
+                        .click_url(issue_id, subscriber_id, target.as_str())
+                        .replace('&', "&amp;"),
+                );
+            } else {
+                output.push_str(raw_link);
+            }
+            output.push(quote);
+            cursor = value_end + 1;
+        }
+        output.push_str(&html[cursor..]);
+
+        let pixel = format!(
+            r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+            self.open_url(issue_id, subscriber_id).replace('&', "&amp;")
+        );
+        match output.to_ascii_lowercase().rfind("</body>") {
+            Some(index) => output.insert_str(index, &pixel),
+            None => output.push_str(&pixel),
+        }
+        output
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+
+    fn sign(&self, message: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn verify(&self, message: &str, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+fn open_message(issue_id: Uuid, subscriber_id: Uuid) -> String {
+    format!("open:{issue_id}:{subscriber_id}")
+}
+
+fn click_message(issue_id: Uuid, subscriber_id: Uuid, target: &str) -> String {
+    format!("click:{issue_id}:{subscriber_id}:{target}")
+}
+
+/// Only absolute `http(s)` links are tracked. Parsing also percent-encodes
+/// the target so it is always safe to send back in a `Location` header.
+fn trackable_url(link: &str) -> Option<url::Url> {
+    url::Url::parse(link)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretBox;
+    use uuid::Uuid;
+
+    use super::Tracker;
+
+    fn tracker(enabled: bool) -> Tracker {
+        Tracker::new(
+            "https://news.example.com/",
+            SecretBox::new(Box::new("secret".to_string())),
+            enabled,
+        )
+    }
+
+    #[test]
+    fn html_is_untouched_when_tracking_is_disabled() {
+        let html = r#"<a href="https://example.com">link</a>"#;
+        let output = tracker(false).instrument_html(html, Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(html, output);
+    }
+
+    #[test]
+    fn http_links_are_rewritten_to_signed_redirects() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let tracker = tracker(true);
+        let html = r#"<p><a class="x" href="https://example.com/?a=1&amp;b=2">link</a></p>"#;
+
+        let output = tracker.instrument_html(html, issue_id, subscriber_id);
+
+        let expected = tracker
+            .click_url(issue_id, subscriber_id, "https://example.com/?a=1&b=2")
+            .replace('&', "&amp;");
+        assert!(output.contains(&format!(r#"href="{expected}""#)));
+        assert!(!output.contains(r#"href="https://example.com"#));
+    }
+
+    #[test]
+    fn non_http_links_are_left_alone() {
+        let html = r#"<a href="mailto:a@b.com">a</a><a href='#top'>b</a><a data-href="https://x.com">c</a>"#;
+        let output = tracker(true).instrument_html(html, Uuid::new_v4(), Uuid::new_v4());
+        assert!(output.starts_with(html));
+    }
+
+    #[test]
+    fn pixel_is_inserted_before_closing_body() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let tracker = tracker(true);
+        let output = tracker.instrument_html("<body><p>hi</p></BODY>", issue_id, subscriber_id);
+        let pixel_url = tracker.open_url(issue_id, subscriber_id);
+        assert!(output.contains(&pixel_url));
+        assert!(output.ends_with("</BODY>"));
+    }
+
+    #[test]
+    fn signatures_are_bound_to_their_inputs() {
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let tracker = tracker(true);
+        let url = tracker.click_url(issue_id, subscriber_id, "https://example.com");
+        let signature = url.rsplit_once("sig=").unwrap().1;
+
+        assert!(tracker.verify_click(issue_id, subscriber_id, "https://example.com", signature));
+        assert!(!tracker.verify_click(issue_id, subscriber_id, "https://evil.com", signature));
+        assert!(!tracker.verify_click(issue_id, Uuid::new_v4(), "https://example.com", signature));
+        assert!(!tracker.verify_open(issue_id, subscriber_id, signature));
+        assert!(!tracker.verify_open(issue_id, subscriber_id, "not-hex"));
+    }
+}
@@ -0,0 +1,326 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretBox};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{PgExecutor, PgPool, types::Uuid};
+use tracing::Instrument;
+
+use crate::configuration::WebhookSettings;
+use crate::delivery::ExecutionOutcome;
+use crate::telemetry::{set_remote_parent, trace_headers};
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+
+/// How long the worker waits after a database error
+const ERROR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Subscriber lifecycle events that can be delivered to webhook endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscriberEvent {
+    #[serde(rename = "subscriber.created")]
+    Created,
+    #[serde(rename = "subscriber.confirmed")]
+    Confirmed,
+    #[serde(rename = "subscriber.unsubscribed")]
+    Unsubscribed,
+}
+
+impl SubscriberEvent {
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "subscriber.created",
+            Self::Confirmed => "subscriber.confirmed",
+            Self::Unsubscribed => "subscriber.unsubscribed",
+        }
+    }
+}
+
+struct Endpoint {
+    url: String,
+    secret: SecretBox<String>,
+    events: Vec<SubscriberEvent>,
+}
+
+impl Endpoint {
+    fn accepts(&self, event: SubscriberEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// Delivers lifecycle events to the configured webhook endpoints. Events are
+/// queued in `webhook_delivery` and sent by `spawn_worker`, which retries
+/// failed attempts from the table so that none is lost on restart.
+#[derive(Clone)]
+pub struct Webhooks {
+    client: reqwest::Client,
+    pool: PgPool,
+    endpoints: Vec<Arc<Endpoint>>,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    poll_interval: Duration,
+}
+
+impl Webhooks {
+    /// # Panics
+    ///
+    /// If the HTTP client cannot be built
+    #[must_use]
+    pub fn from_settings(settings: &WebhookSettings, pool: PgPool) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        let endpoints = settings
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                Arc::new(Endpoint {
+                    url: endpoint.url.clone(),
+                    secret: SecretBox::new(Box::new(endpoint.secret.expose_secret().clone())),
+                    events: endpoint.events.clone(),
+                })
+            })
+            .collect();
+        Self {
+            client,
+            pool,
+            endpoints,
+            max_attempts: settings.max_attempts.max(1),
+            retry_base_delay: Duration::from_millis(settings.retry_base_delay_milliseconds),
+            poll_interval: Duration::from_millis(settings.poll_interval_milliseconds),
+        }
+    }
+
+    /// Queues `event` for delivery to every endpoint subscribed to it, along
+    /// with the trace of the current span. Callers never wait on endpoints,
+    /// and failing to queue is logged rather than failing the caller.
+    pub async fn dispatch(&self, event: SubscriberEvent, data: &impl Serialize) {
+        let event_id = Uuid::new_v4();
+        let payload = serde_json::json!({
+            "id": event_id,
+            "type": event,
+            "occurred_at": Utc::now(),
+            "data": data,
+        });
+        let headers = trace_headers();
+        let traceparent = headers
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok());
+        for endpoint in self.endpoints.iter().filter(|e| e.accepts(event)) {
+            if let Err(e) =
+                insert_delivery(&self.pool, endpoint, event, event_id, &payload, traceparent).await
+            {
+                tracing::error!("Failed to queue webhook delivery: {:?}", e);
+            }
+        }
+    }
+
+    /// Sends the pending delivery due first and records the attempt, due
+    /// again after a delay doubled on every failure until `max_attempts`.
+    /// Concurrent workers skip the deliveries locked by each other.
+    ///
+    /// # Errors
+    ///
+    /// Database error, failures to deliver are recorded on the delivery
+    pub async fn deliver_next(&self) -> Result<ExecutionOutcome, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let Some(delivery) = sqlx::query!(
+            r#"
+            select id, event_type, endpoint_url, payload, traceparent, attempts
+            from webhook_delivery
+            where status = 'pending' and next_attempt_at <= now()
+            order by next_attempt_at
+            limit 1
+            for update skip locked
+            "#
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Ok(ExecutionOutcome::EmptyQueue);
+        };
+        let span = tracing::info_span!(
+            "Delivering webhook",
+            delivery_id = %delivery.id,
+            url = %delivery.endpoint_url
+        );
+        // Continues the trace of the request that raised the event
+        if let Some(value) = delivery
+            .traceparent
+            .as_deref()
+            .and_then(|traceparent| HeaderValue::from_str(traceparent).ok())
+        {
+            let mut headers = HeaderMap::new();
+            headers.insert("traceparent", value);
+            set_remote_parent(&span, &headers);
+        }
+
+        let attempt = u32::try_from(delivery.attempts).unwrap_or(0) + 1;
+        let result = match self
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.url == delivery.endpoint_url)
+        {
+            Some(endpoint) => {
+                self.send(endpoint, &delivery.event_type, delivery.payload.to_string())
+                    .instrument(span.clone())
+                    .await
+            }
+            None => Err((None, "Endpoint is no longer configured".to_string())),
+        };
+        match result {
+            Ok(status_code) => {
+                update_delivery(
+                    &mut *transaction,
+                    delivery.id,
+                    attempt,
+                    "delivered",
+                    Some(status_code),
+                    None,
+                    Utc::now(),
+                )
+                .await?;
+            }
+            Err((status_code, error)) => {
+                span.in_scope(|| {
+                    tracing::warn!("Webhook delivery attempt {attempt} failed: {error}");
+                });
+                let status = if attempt >= self.max_attempts {
+                    "failed"
+                } else {
+                    "pending"
+                };
+                let backoff = self.retry_base_delay * 2u32.saturating_pow(attempt - 1);
+                update_delivery(
+                    &mut *transaction,
+                    delivery.id,
+                    attempt,
+                    status,
+                    status_code,
+                    Some(&error),
+                    Utc::now() + backoff,
+                )
+                .await?;
+            }
+        }
+        transaction.commit().await?;
+        Ok(ExecutionOutcome::Delivered)
+    }
+
+    /// Posts the signed `body` to `endpoint`, returning the status code of
+    /// a successful response, or the status code if any and the error
+    async fn send(
+        &self,
+        endpoint: &Endpoint,
+        event_type: &str,
+        body: String,
+    ) -> Result<u16, (Option<u16>, String)> {
+        let signature = format!("sha256={}", sign(&endpoint.secret, body.as_bytes()));
+        match self
+            .client
+            .post(&endpoint.url)
+            .headers(trace_headers())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event_type)
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => Ok(response.status().as_u16()),
+            Ok(response) => Err((
+                Some(response.status().as_u16()),
+                format!("Endpoint responded with {}", response.status()),
+            )),
+            Err(e) => Err((None, e.to_string())),
+        }
+    }
+}
+
+/// Delivers the queued webhook events in the background for as long as the
+/// process runs
+pub fn spawn_worker(webhooks: Webhooks) {
+    tokio::spawn(async move {
+        loop {
+            match webhooks.deliver_next().await {
+                Ok(ExecutionOutcome::Delivered) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    tokio::time::sleep(webhooks.poll_interval).await;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to deliver the queued webhooks: {:?}", e);
+                    tokio::time::sleep(ERROR_INTERVAL).await;
+                }
+            }
+        }
+    });
+}
+
+fn sign(secret: &SecretBox<String>, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+async fn insert_delivery(
+    pool: &PgPool,
+    endpoint: &Endpoint,
+    event: SubscriberEvent,
+    event_id: Uuid,
+    payload: &serde_json::Value,
+    traceparent: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        insert into webhook_delivery (event_id, event_type, endpoint_url, payload, traceparent)
+        values ($1, $2, $3, $4, $5)
+        "#,
+        event_id,
+        event.as_str(),
+        endpoint.url,
+        payload,
+        traceparent,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn update_delivery(
+    executor: impl PgExecutor<'_>,
+    delivery_id: Uuid,
+    attempts: u32,
+    status: &str,
+    status_code: Option<u16>,
+    error: Option<&str>,
+    next_attempt_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        update webhook_delivery
+        set attempts = $2, status = $3, last_status_code = $4, last_error = $5,
+            next_attempt_at = $6, updated_at = now()
+        where id = $1
+        "#,
+        delivery_id,
+        i32::try_from(attempts).unwrap_or(i32::MAX),
+        status,
+        status_code.map(i32::from),
+        error,
+        next_attempt_at,
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use hyper::StatusCode;
+use secrecy::SecretBox;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::admin::create_admin;
+use z2p_axum::roles::Role;
+
+mod common;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+/// Keeps the cookies between requests, sending each under its path, like a
+/// browser
+struct Browser {
+    client: reqwest::Client,
+    address: String,
+    /// Value and path of each cookie, by name
+    cookies: HashMap<String, (String, String)>,
+}
+
+impl Browser {
+    fn new(test_app: &common::TestApp) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap(),
+            address: test_app.address.clone(),
+            cookies: HashMap::new(),
+        }
+    }
+
+    async fn get(&mut self, path: &str) -> reqwest::Response {
+        let request = self.client.get(format!("{}{path}", self.address));
+        self.send(path, request).await
+    }
+
+    async fn post(&mut self, path: &str, form: &[(&str, &str)]) -> reqwest::Response {
+        let request = self
+            .client
+            .post(format!("{}{path}", self.address))
+            .form(form);
+        self.send(path, request).await
+    }
+
+    async fn send(
+        &mut self,
+        path: &str,
+        mut request: reqwest::RequestBuilder,
+    ) -> reqwest::Response {
+        let cookies: Vec<String> = self
+            .cookies
+            .iter()
+            .filter(|(_, (_, cookie_path))| path.starts_with(cookie_path.as_str()))
+            .map(|(name, (value, _))| format!("{name}={value}"))
+            .collect();
+        if !cookies.is_empty() {
+            request = request.header("Cookie", cookies.join("; "));
+        }
+        let response = request.send().await.expect("Failed to execute request.");
+        for set_cookie in response.headers().get_all("set-cookie") {
+            let mut attributes = set_cookie.to_str().unwrap().split(';');
+            let (name, value) = attributes.next().unwrap().split_once('=').unwrap();
+            let cookie_path = attributes
+                .find_map(|attribute| attribute.trim().strip_prefix("Path="))
+                .unwrap_or("/");
+            self.cookies.insert(
+                name.to_string(),
+                (value.to_string(), cookie_path.to_string()),
+            );
+        }
+        response
+    }
+
+    fn session_cookie(&self) -> Option<String> {
+        self.cookies
+            .get("admin_session")
+            .map(|(value, _)| format!("admin_session={value}"))
+    }
+
+    /// The CSRF token of the form on `path`
+    async fn csrf_token(&mut self, path: &str) -> String {
+        let html = self.get(path).await.text().await.unwrap();
+        let needle = r#"name="csrf_token" value=""#;
+        let start = html.find(needle).unwrap() + needle.len();
+        let end = start + html[start..].find('"').unwrap();
+        html[start..end].to_string()
+    }
+
+    async fn log_in(&mut self, username: &str, password: &str) -> reqwest::Response {
+        let csrf_token = self.csrf_token("/admin/login").await;
+        self.post(
+            "/admin/login",
+            &[
+                ("csrf_token", &csrf_token),
+                ("username", username),
+                ("password", password),
+            ],
+        )
+        .await
+    }
+}
+
+async fn logged_in(test_app: &common::TestApp) -> Browser {
+    logged_in_as(test_app, "editor", Role::Editor).await
+}
+
+async fn logged_in_as(test_app: &common::TestApp, username: &str, role: Role) -> Browser {
+    create_admin(
+        &test_app.db_pool,
+        username,
+        &SecretBox::new(Box::new(PASSWORD.to_string())),
+        role,
+    )
+    .await
+    .unwrap();
+    let mut browser = Browser::new(test_app);
+    let response = browser.log_in(username, PASSWORD).await;
+    assert_eq!(response.headers()["location"], "/admin");
+    browser
+}
+
+#[sqlx::test]
+async fn dashboard_requires_login(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut browser = Browser::new(&test_app);
+
+    for path in ["/admin", "/admin/subscribers", "/admin/issues/new"] {
+        let response = browser.get(path).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER, "{path}");
+        assert_eq!(response.headers()["location"], "/admin/login");
+    }
+}
+
+#[sqlx::test]
+async fn sessions_start_only_on_login(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    create_admin(
+        &test_app.db_pool,
+        "editor",
+        &SecretBox::new(Box::new(PASSWORD.to_string())),
+        Role::Editor,
+    )
+    .await
+    .unwrap();
+    let mut browser = Browser::new(&test_app);
+
+    let response = browser.get("/admin/login").await;
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.starts_with("admin_login="));
+    assert!(cookie.contains("Path=/admin/login"));
+    let response = browser.log_in("editor", "not the password").await;
+    assert_eq!(
+        response.headers()["location"],
+        "/admin/login?notice=invalid_credentials"
+    );
+    let html = browser
+        .get("/admin/login?notice=invalid_credentials")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Invalid username or password"));
+    assert_eq!(browser.get("/admin").await.status(), StatusCode::SEE_OTHER);
+    let sessions: i64 = sqlx::query_scalar("select count(*) from admin_session")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions, 0);
+
+    let login_token = browser.csrf_token("/admin/login").await;
+    let response = browser.log_in("editor", PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "/admin");
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    assert!(cookie.starts_with("admin_session="));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("SameSite=Lax"));
+
+    let response = browser.get("/admin").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Welcome back, editor")
+    );
+    // Flash messages are shown once
+    let html = browser.get("/admin").await.text().await.unwrap();
+    assert!(!html.contains("Welcome back"));
+
+    // Logging in again replaces the session
+    let previous = browser.session_cookie();
+    browser
+        .post(
+            "/admin/login",
+            &[
+                ("csrf_token", &login_token),
+                ("username", "editor"),
+                ("password", PASSWORD),
+            ],
+        )
+        .await;
+    assert_ne!(browser.session_cookie(), previous);
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}/admin", &test_app.address))
+        .header("Cookie", previous.unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+#[sqlx::test]
+async fn login_attempts_are_limited(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.admin.login_rate_limit.per_username.requests = 2;
+    })
+    .await;
+    create_admin(
+        &test_app.db_pool,
+        "editor",
+        &SecretBox::new(Box::new(PASSWORD.to_string())),
+        Role::Editor,
+    )
+    .await
+    .unwrap();
+    let mut browser = Browser::new(&test_app);
+
+    for _ in 0..2 {
+        let response = browser.log_in("Editor", "not the password").await;
+        assert_eq!(
+            response.headers()["location"],
+            "/admin/login?notice=invalid_credentials"
+        );
+    }
+    // Even the right password is not checked once limited
+    let response = browser.log_in("editor", PASSWORD).await;
+    assert_eq!(
+        response.headers()["location"],
+        "/admin/login?notice=rate_limited"
+    );
+    let html = browser
+        .get("/admin/login?notice=rate_limited")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Too many login attempts"));
+    assert!(browser.session_cookie().is_none());
+
+    let response = logged_in_as(&test_app, "viewer", Role::Viewer)
+        .await
+        .get("/admin")
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn logout_ends_the_session(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut browser = logged_in(&test_app).await;
+    let logged_in_cookie = browser.cookies.clone();
+
+    let csrf_token = browser.csrf_token("/admin").await;
+    let response = browser
+        .post("/admin/logout", &[("csrf_token", &csrf_token)])
+        .await;
+    assert_eq!(
+        response.headers()["location"],
+        "/admin/login?notice=logged_out"
+    );
+    let html = browser
+        .get("/admin/login?notice=logged_out")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("You are logged out"));
+
+    browser.cookies = logged_in_cookie;
+    assert_eq!(browser.get("/admin").await.status(), StatusCode::SEE_OTHER);
+}
+
+#[sqlx::test]
+async fn forms_without_the_session_csrf_token_are_refused(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut browser = logged_in(&test_app).await;
+    let issue = [
+        ("title", "Issue #1"),
+        ("text_content", "Hello"),
+        ("html_content", "<p>Hello</p>"),
+    ];
+
+    let response = browser.post("/admin/issues", &issue).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let mut forged = issue.to_vec();
+    forged.push(("csrf_token", "00"));
+    let response = browser.post("/admin/issues", &forged).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Tokens from another session do not work either
+    let mut other = Browser::new(&test_app);
+    let other_token = other.csrf_token("/admin/login").await;
+    let mut stolen = issue.to_vec();
+    stolen.push(("csrf_token", &other_token));
+    let response = browser.post("/admin/issues", &stolen).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = Browser::new(&test_app)
+        .post(
+            "/admin/login",
+            &[("username", "editor"), ("password", PASSWORD)],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let issues: i64 = sqlx::query_scalar("select count(*) from newsletter_issue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues, 0);
+}
+
+#[sqlx::test]
+async fn issues_are_composed_in_the_dashboard(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut browser = logged_in(&test_app).await;
+    let csrf_token = browser.csrf_token("/admin/issues/new").await;
+
+    let response = browser
+        .post(
+            "/admin/issues",
+            &[
+                ("csrf_token", &csrf_token),
+                ("title", "<Issue #1>"),
+                ("text_content", "  "),
+                ("html_content", "<p>Hello</p>"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"id="text_content-error""#));
+    assert!(!html.contains(r#"id="title-error""#));
+    assert!(html.contains(r#"value="&lt;Issue #1&gt;""#));
+    assert!(html.contains("&lt;p&gt;Hello&lt;/p&gt;</textarea>"));
+
+    let response = browser
+        .post(
+            "/admin/issues",
+            &[
+                ("csrf_token", &csrf_token),
+                ("title", "<Issue #1>"),
+                ("text_content", "Hello"),
+                ("html_content", "<p>Hello</p>"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "/admin/issues");
+
+    let html = browser.get("/admin/issues").await.text().await.unwrap();
+    assert!(html.contains("Saved “&lt;Issue #1&gt;”"));
+    assert!(html.contains("<td>&lt;Issue #1&gt;</td><td>editor</td>"));
+    let author: String = sqlx::query_scalar(
+        "select u.username from newsletter_issue i join admin_user u on u.id = i.created_by",
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(author, "editor");
+}
+
+#[sqlx::test]
+async fn subscribers_are_browsed_by_page_and_filter(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.admin.page_size = 2).await;
+    for email in ["ursula@example.com", "ged@example.com", "tenar@example.com"] {
+        test_app.create_subscriber(email).await;
+    }
+    sqlx::query("update subscriber set status = 'unsubscribed' where email = 'ged@example.com'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let mut browser = logged_in(&test_app).await;
+
+    let html = browser
+        .get("/admin/subscribers")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html.matches("@example.com").count(), 2);
+    assert!(html.contains(r#"href="/admin/subscribers?page=2" rel="next""#));
+    let html = browser
+        .get("/admin/subscribers?page=2")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html.matches("@example.com").count(), 1);
+    assert!(html.contains(r#"rel="prev""#));
+    assert!(!html.contains(r#"rel="next""#));
+
+    let html = browser
+        .get("/admin/subscribers?status=unsubscribed")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("ged@example.com"));
+    assert_eq!(html.matches("@example.com").count(), 1);
+
+    let html = browser
+        .get("/admin/subscribers?search=TENAR")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("tenar@example.com"));
+    assert_eq!(html.matches("@example.com").count(), 1);
+}
+
+#[sqlx::test]
+async fn viewers_read_but_cannot_write(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut browser = logged_in_as(&test_app, "viewer", Role::Viewer).await;
+
+    assert_eq!(
+        browser.get("/admin/subscribers").await.status(),
+        StatusCode::OK
+    );
+    let response = browser.get("/admin/issues").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().await.unwrap();
+    assert!(!html.contains("Compose an issue"));
+    assert!(!html.contains(r#"href="/admin/users""#));
+
+    let response = browser.get("/admin/issues/new").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 403);
+    assert_eq!(problem["title"], "Forbidden");
+    assert_eq!(
+        problem["detail"],
+        "The `viewer` role lacks the `issues:write` permission"
+    );
+    assert!(problem["request_id"].is_string());
+
+    let csrf_token = browser.csrf_token("/admin").await;
+    let response = browser
+        .post(
+            "/admin/issues",
+            &[
+                ("csrf_token", &csrf_token),
+                ("title", "Issue #1"),
+                ("text_content", "Hello"),
+                ("html_content", "<p>Hello</p>"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        browser.get("/admin/users").await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    let issues: i64 = sqlx::query_scalar("select count(*) from newsletter_issue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues, 0);
+}
+
+#[sqlx::test]
+async fn admins_change_the_role_of_other_users(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let mut editor = logged_in(&test_app).await;
+    let mut admin = logged_in_as(&test_app, "root", Role::Admin).await;
+    let user_id = |username: &'static str| {
+        sqlx::query_scalar::<_, sqlx::types::Uuid>("select id from admin_user where username = $1")
+            .bind(username)
+            .fetch_one(&test_app.db_pool)
+    };
+    let editor_id = user_id("editor").await.unwrap();
+    let admin_id = user_id("root").await.unwrap();
+
+    assert_eq!(
+        editor.get("/admin/users").await.status(),
+        StatusCode::FORBIDDEN
+    );
+    let html = admin.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains(&format!(r#"action="/admin/users/{editor_id}/role""#)));
+    assert!(!html.contains(&format!(r#"action="/admin/users/{admin_id}/role""#)));
+
+    let csrf_token = admin.csrf_token("/admin/users").await;
+    let response = admin
+        .post(
+            &format!("/admin/users/{editor_id}/role"),
+            &[("csrf_token", &csrf_token), ("role", "viewer")],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let html = admin.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains("editor is now viewer"));
+    // The new role applies to the sessions already logged in
+    assert_eq!(
+        editor.get("/admin/issues/new").await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    admin
+        .post(
+            &format!("/admin/users/{admin_id}/role"),
+            &[("csrf_token", &csrf_token), ("role", "viewer")],
+        )
+        .await;
+    let html = admin.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains("You cannot change your own role"));
+    let response = admin
+        .post(
+            &format!("/admin/users/{editor_id}/role"),
+            &[("csrf_token", &csrf_token), ("role", "owner")],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let response = admin
+        .post(
+            &format!("/admin/users/{}/role", Uuid::new_v4()),
+            &[("csrf_token", &csrf_token), ("role", "viewer")],
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["detail"], "No such user");
+
+    let roles: Vec<String> = sqlx::query_scalar("select role from admin_user order by username")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(roles, ["viewer", "admin"]);
+}
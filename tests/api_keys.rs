@@ -0,0 +1,282 @@
+use chrono::{Duration, Utc};
+use hyper::StatusCode;
+use sqlx::PgPool;
+use z2p_axum::api_keys::{Scope, create_api_key};
+
+mod common;
+
+fn client() -> reqwest::Client {
+    reqwest::Client::new()
+}
+
+#[sqlx::test]
+async fn admin_endpoints_refuse_requests_without_a_usable_key(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let url = format!("{}/api/v1/admin/subscribers", &test_app.address);
+
+    let response = client().get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+    let key = test_app.api_key(&[Scope::SubscribersRead]).await;
+    let (kept, last) = key.split_at(key.len() - 1);
+    let forged = format!("{kept}{}", if last == "0" { "1" } else { "0" });
+    let response = client()
+        .get(&url)
+        .bearer_auth(&forged)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()["www-authenticate"],
+        r#"Bearer error="invalid_token""#
+    );
+
+    let expired = create_api_key(
+        &test_app.db_pool,
+        "expired",
+        &[Scope::SubscribersRead],
+        Some(Utc::now() + Duration::seconds(1)),
+    )
+    .await
+    .unwrap();
+    sqlx::query("update api_key set expires_at = now() - interval '1 minute' where id = $1")
+        .bind(expired.id)
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let response = client()
+        .get(&url)
+        .bearer_auth(&expired.key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client().get(&url).bearer_auth(&key).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn keys_without_the_scope_are_forbidden(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let key = test_app.api_key(&[Scope::SubscribersRead]).await;
+
+    let response = client()
+        .post(format!("{}/api/v1/admin/issues", &test_app.address))
+        .bearer_auth(&key)
+        .json(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.headers()["www-authenticate"],
+        r#"Bearer error="insufficient_scope", scope="issues:publish""#
+    );
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 403);
+    assert_eq!(
+        problem["detail"],
+        "The API key lacks the `issues:publish` scope"
+    );
+}
+
+#[sqlx::test]
+async fn keys_are_created_listed_and_revoked(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let admin_key = test_app.api_key(&[Scope::ApiKeysManage]).await;
+    let url = format!("{}/api/v1/admin/api-keys", &test_app.address);
+
+    let response = client()
+        .post(&url)
+        .bearer_auth(&admin_key)
+        .json(&serde_json::json!({"name": "crm", "scopes": []}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = client()
+        .post(&url)
+        .bearer_auth(&admin_key)
+        .json(&serde_json::json!({
+            "name": "crm",
+            "scopes": ["subscribers:read", "subscribers:write"],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: serde_json::Value = response.json().await.unwrap();
+    let key = common::expect_string(&created["key"]).to_string();
+    let prefix = common::expect_string(&created["prefix"]);
+    assert!(prefix.starts_with("nl_"));
+    assert!(key.starts_with(prefix));
+
+    let response = client()
+        .get(format!("{}/api/v1/admin/subscribers", &test_app.address))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let keys: serde_json::Value = client()
+        .get(&url)
+        .bearer_auth(&admin_key)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let listed = keys
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|listed| listed["id"] == created["id"])
+        .unwrap();
+    assert_eq!(
+        listed["scopes"],
+        serde_json::json!(["subscribers:read", "subscribers:write"])
+    );
+    assert!(listed["last_used_at"].is_string());
+    assert!(listed.get("key").is_none());
+
+    let revoke_url = format!("{url}/{}", common::expect_uuid(&created["id"]));
+    let response = client()
+        .delete(&revoke_url)
+        .bearer_auth(&admin_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = client()
+        .delete(&revoke_url)
+        .bearer_auth(&admin_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = client()
+        .get(format!("{}/api/v1/admin/subscribers", &test_app.address))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn subscribers_are_listed_and_added_with_a_key(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    test_app.create_subscriber("ursula@example.com").await;
+    let key = test_app
+        .api_key(&[Scope::SubscribersRead, Scope::SubscribersWrite])
+        .await;
+    let subscriber = serde_json::json!({"name": "Ged", "email": "ged@example.com"});
+
+    let response = client()
+        .post(format!("{}/api/v1/admin/subscribers", &test_app.address))
+        .bearer_auth(&key)
+        .json(&subscriber)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = client()
+        .post(format!("{}/api/v1/admin/subscribers", &test_app.address))
+        .bearer_auth(&key)
+        .json(&subscriber)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let subscribers: serde_json::Value = client()
+        .get(format!(
+            "{}/api/v1/admin/subscribers?status=confirmed",
+            &test_app.address
+        ))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(
+        subscribers
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|subscriber| common::expect_string(&subscriber["email"]) == "ged@example.com")
+    );
+
+    let response = client()
+        .get(format!(
+            "{}/api/v1/admin/subscribers?status=lapsed",
+            &test_app.address
+        ))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn published_issues_are_queued_and_reported(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let key = test_app
+        .api_key(&[Scope::IssuesPublish, Scope::IssuesRead])
+        .await;
+    let response = client()
+        .post(format!("{}/api/v1/admin/subscribers", &test_app.address))
+        .bearer_auth(test_app.api_key(&[Scope::SubscribersWrite]).await)
+        .json(&serde_json::json!({"name": "Ged", "email": "ged@example.com"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = client()
+        .post(format!("{}/api/v1/admin/issues", &test_app.address))
+        .bearer_auth(&key)
+        .json(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let published: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(published["queued"], 1);
+
+    let response = client()
+        .get(format!(
+            "{}/api/v1/admin/issues/{}/report",
+            &test_app.address,
+            common::expect_uuid(&published["issue_id"])
+        ))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["queued"], 1);
+}
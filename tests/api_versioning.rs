@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use hyper::StatusCode;
+use rstest::rstest;
+use sqlx::PgPool;
+
+mod common;
+
+async fn subscribe(test_app: &common::TestApp, path: &str, email: &str) -> reqwest::Response {
+    let mut map = HashMap::new();
+    map.insert("email", email);
+    map.insert("name", "Joe B");
+    reqwest::Client::new()
+        .post(format!("{}{path}", &test_app.address))
+        .json(&map)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[sqlx::test]
+async fn unversioned_paths_are_deprecated_aliases_of_v1(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = subscribe(&test_app, "/api/subscriber", "test@example.com").await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let headers = response.headers();
+    assert!(headers["deprecation"].to_str().unwrap().starts_with('@'));
+    assert!(headers.contains_key("sunset"));
+    assert_eq!(
+        headers["link"],
+        r#"</api/v1/subscriber>; rel="successor-version""#
+    );
+}
+
+#[sqlx::test]
+async fn versioned_paths_are_not_deprecated(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = subscribe(&test_app, "/api/v1/subscriber", "test@example.com").await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(!response.headers().contains_key("deprecation"));
+    assert!(!response.headers().contains_key("sunset"));
+}
+
+#[rstest]
+#[case::unversioned("/api/subscriber")]
+#[case::v1("/api/v1/subscriber")]
+#[sqlx::test]
+async fn both_paths_validate_subscribers_alike(#[case] path: &str, #[ignore] _db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = subscribe(&test_app, path, "not-an-email").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}{path}", &test_app.address))
+        .json(&HashMap::<String, String>::new())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = subscribe(&test_app, path, "test@example.com").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["email"], "test@example.com");
+    test_app.confirm(common::expect_uuid(&created["id"])).await;
+    let response = subscribe(&test_app, path, "test@example.com").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
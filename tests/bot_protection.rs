@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use axum::{Form, Json, Router, routing::post};
+use hyper::StatusCode;
+use sqlx::PgPool;
+use z2p_axum::configuration::ChallengeProvider;
+
+mod common;
+
+/// Starts a `siteverify` stub that only accepts the token `good`
+fn spawn_provider() -> String {
+    async fn siteverify(Form(form): Form<HashMap<String, String>>) -> Json<serde_json::Value> {
+        Json(serde_json::json!({ "success": form["response"] == "good" }))
+    }
+    let router = Router::new().route("/siteverify", post(siteverify));
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let url = format!("http://{}/siteverify", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+async fn subscribe(test_app: &common::TestApp, body: serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[sqlx::test]
+async fn filled_in_honeypot_is_rejected(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = subscribe(
+        &test_app,
+        serde_json::json!({
+            "name": "Joe B",
+            "email": "test@example.com",
+            "website": "https://spam.example.com",
+        }),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn challenge_token_is_verified_with_the_provider(_db: PgPool) {
+    let verify_url = spawn_provider();
+    let test_app = common::spawn_app_with(|c| {
+        c.bot_protection.challenge.provider = ChallengeProvider::Turnstile;
+        c.bot_protection.challenge.verify_url = Some(verify_url);
+    })
+    .await;
+
+    for (token, status) in [
+        (None, StatusCode::BAD_REQUEST),
+        (Some("bad"), StatusCode::BAD_REQUEST),
+        (Some("good"), StatusCode::CREATED),
+    ] {
+        let response = subscribe(
+            &test_app,
+            serde_json::json!({
+                "name": "Joe B",
+                "email": "test@example.com",
+                "challenge_token": token,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), status, "token {token:?}");
+    }
+}
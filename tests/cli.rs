@@ -0,0 +1,111 @@
+use std::process::Command;
+
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use z2p_axum::admin::{create_admin, verify_password};
+use z2p_axum::roles::Role;
+use z2p_axum::subscriber_csv::{ImportSummary, export_subscribers, import_subscribers};
+
+fn secret(value: &str) -> SecretBox<String> {
+    SecretBox::new(Box::new(value.to_string()))
+}
+
+fn z2p_axum(database_name: &str, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_z2p_axum"))
+        .args(args)
+        .env("APP_DATABASE__DATABASE_NAME", database_name)
+        .output()
+        .expect("Failed to run the binary")
+}
+
+#[sqlx::test]
+async fn admin_is_created_with_a_hashed_password(db: PgPool) {
+    create_admin(
+        &db,
+        "ursula",
+        &secret("a-long-enough-password"),
+        Role::Viewer,
+    )
+    .await
+    .unwrap();
+
+    let (password_hash, role): (String, String) =
+        sqlx::query_as("select password_hash, role from admin_user where username = 'ursula'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(role, "viewer");
+    assert!(verify_password(
+        &secret("a-long-enough-password"),
+        &password_hash
+    ));
+    assert!(
+        create_admin(
+            &db,
+            "ursula",
+            &secret("a-long-enough-password"),
+            Role::Admin
+        )
+        .await
+        .is_err()
+    );
+    assert!(
+        create_admin(&db, "le_guin", &secret("short"), Role::Admin)
+            .await
+            .is_err()
+    );
+}
+
+#[sqlx::test]
+async fn exported_subscribers_can_be_imported_back(db: PgPool) {
+    let csv = "email,name\n\
+        ursula@domain.com,Ursula Le Guin\n\
+        not-an-email,Someone\n\
+        ursula@domain.com,Ursula again\n\
+        octavia@domain.com,Octavia Butler\n";
+
+    let summary = import_subscribers(&db, csv.as_bytes()).await.unwrap();
+
+    assert_eq!(
+        summary,
+        ImportSummary {
+            imported: 2,
+            existing: 1,
+            invalid: vec![(3, "invalid email".to_string())],
+        }
+    );
+    let mut exported = Vec::new();
+    assert_eq!(export_subscribers(&db, &mut exported).await.unwrap(), 2);
+    let exported = String::from_utf8(exported).unwrap();
+    assert!(exported.starts_with("id,email,name\n"));
+    assert!(exported.contains("octavia@domain.com,Octavia Butler"));
+
+    let summary = import_subscribers(&db, exported.as_bytes()).await.unwrap();
+    assert_eq!((summary.imported, summary.existing), (0, 2));
+}
+
+#[sqlx::test]
+async fn migrate_dry_run_lists_nothing_on_a_migrated_database(db: PgPool) {
+    let database_name: String = sqlx::query_scalar("select current_database()")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    let output = z2p_axum(&database_name, &["migrate", "--dry-run"]);
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(output.stdout.is_empty(), "{output:?}");
+}
+
+#[test]
+fn check_config_rejects_unknown_environments() {
+    let output = z2p_axum("newsletter", &["check-config"]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_z2p_axum"))
+        .arg("check-config")
+        .env("APP_ENVIRONMENT", "staging")
+        .output()
+        .expect("Failed to run the binary");
+    assert!(!output.status.success());
+}
@@ -1,43 +1,128 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
 
 use axum::Error;
 use axum::Router;
-use axum::routing::IntoMakeService;
+use axum::extract::{Json, State, connect_info::IntoMakeServiceWithConnectInfo};
+use axum::routing::post;
 use hyper::server::conn::AddrIncoming;
 use sqlx::{Connection, Executor, PgConnection, PgPool, types::Uuid};
 use tokio::net::TcpListener;
+use z2p_axum::api_keys::{Scope, create_api_key};
+use z2p_axum::configuration::ChallengeProvider;
 use z2p_axum::configuration::DatabaseSettings;
+use z2p_axum::configuration::Settings;
+use z2p_axum::configuration::TelemetrySettings;
 use z2p_axum::configuration::get_configuration;
-use z2p_axum::telemetry::init_tracing_subscriber;
+use z2p_axum::subscription_links::{LinkAction, SubscriptionLinks};
+use z2p_axum::telemetry::{TracingGuard, init_tracing_subscriber};
+use z2p_axum::tls::serve_tls;
+use z2p_axum::tracking::Tracker;
+use z2p_axum::webhooks::{self, Webhooks};
+
+// Ensure that the `tracing` stack is only initialised once, with the
+// telemetry settings of the first app spawned by the test binary
+static TRACING: OnceLock<TracingGuard> = OnceLock::new();
+
+fn init_tracing(settings: &TelemetrySettings) {
+    TRACING.get_or_init(|| {
+        let log_level = match std::env::var("TEST_LOG_LEVEL") {
+            Ok(l) => l,
+            Err(_) => "off".to_string(),
+        };
+        init_tracing_subscriber(&log_level, settings).unwrap()
+    });
+}
 
-// Ensure that the `tracing` stack is only initialised once using `once_cell`
-static TRACING: std::sync::LazyLock<()> = std::sync::LazyLock::new(|| {
-    let log_level = match std::env::var("TEST_LOG_LEVEL") {
-        Ok(l) => l,
-        Err(_) => "off".to_string(),
-    };
-    init_tracing_subscriber(&log_level).unwrap();
-});
+/// Emails received by the stub email API the app sends through
+pub type Outbox = Arc<Mutex<Vec<serde_json::Value>>>;
+
+async fn receive_email(State(outbox): State<Outbox>, Json(email): Json<serde_json::Value>) {
+    outbox.lock().unwrap().push(email);
+}
+
+/// Starts a stub email API accepting every email, returning its URL
+fn spawn_email_api() -> (String, Outbox) {
+    let outbox = Outbox::default();
+    let router = Router::new()
+        .route("/email", post(receive_email))
+        .with_state(Arc::clone(&outbox));
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let base_url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    (base_url, outbox)
+}
 
 pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
+    pub tracker: Tracker,
+    pub links: SubscriptionLinks,
+    /// Emails sent by the app
+    pub emails: Outbox,
+}
+
+impl TestApp {
+    /// Creates an API key holding `scopes` and returns it
+    pub async fn api_key(&self, scopes: &[Scope]) -> String {
+        create_api_key(&self.db_pool, "tests", scopes, None)
+            .await
+            .expect("Failed to create the API key")
+            .key
+    }
+
+    /// Creates a subscriber through the API, confirms its address and
+    /// returns its id
+    pub async fn create_subscriber(&self, email: &str) -> Uuid {
+        let mut map = HashMap::new();
+        map.insert("email", email);
+        map.insert("name", "Joe B");
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/subscriber", &self.address))
+            .json(&map)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        let resp_json: serde_json::Value = response.json().await.unwrap();
+        let subscriber_id = expect_uuid(&resp_json["id"]);
+        self.confirm(subscriber_id).await;
+        subscriber_id
+    }
+
+    /// Follows the confirmation link of `subscriber_id`
+    pub async fn confirm(&self, subscriber_id: Uuid) {
+        let response = reqwest::get(self.links.url(LinkAction::Confirm, subscriber_id))
+            .await
+            .expect("Failed to execute request.");
+        assert!(response.status().is_success());
+    }
 }
 
 pub fn run(
     port: u16,
     db_pool: PgPool,
-) -> Result<axum::Server<AddrIncoming, IntoMakeService<Router>>, Error> {
+    configuration: &Settings,
+) -> Result<axum::Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router, SocketAddr>>, Error> {
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    let server: hyper::Server<hyper::server::conn::AddrIncoming, IntoMakeService<Router>> =
-        axum::Server::bind(&addr)
-            .serve(z2p_axum::startup::generate_routes(db_pool).into_make_service());
+    let server = axum::Server::bind(&addr).serve(
+        z2p_axum::startup::generate_routes(&db_pool, configuration)
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    );
     Ok(server)
 }
 
 #[cfg(test)]
 pub async fn spawn_app() -> TestApp {
-    std::sync::LazyLock::force(&TRACING);
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the app after letting the test adjust the configuration
+#[cfg(test)]
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     let listener = TcpListener::bind("127.0.0.1:0")
         .await
         .expect("Failed to bind random port");
@@ -49,45 +134,65 @@ pub async fn spawn_app() -> TestApp {
 
     let mut configuration = get_configuration().expect("Failed to read configuration.");
     configuration.database.database_name = Uuid::new_v4().to_string();
+    configuration.application.base_url.clone_from(&address);
+    // Tests opt in to the challenge explicitly, the local stub would reject every other signup
+    configuration.bot_protection.challenge.provider = ChallengeProvider::None;
+    let (email_api, emails) = spawn_email_api();
+    configuration.email_client.base_url = email_api;
+    configuration.webhooks.poll_interval_milliseconds = 10;
+    customise(&mut configuration);
+    init_tracing(&configuration.telemetry);
     let connection_pool = configure_database(&configuration.database).await;
-    let server = run(port, connection_pool.clone()).unwrap();
-    let _ = tokio::spawn(server);
+    webhooks::spawn_worker(Webhooks::from_settings(
+        &configuration.webhooks,
+        connection_pool.clone(),
+    ));
+    let address = configuration.application.tls.clone().map_or_else(
+        || {
+            let server = run(port, connection_pool.clone(), &configuration).unwrap();
+            tokio::spawn(server);
+            address
+        },
+        |tls| {
+            let router = z2p_axum::startup::generate_routes(&connection_pool, &configuration);
+            let listener = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+            tokio::spawn(async move { serve_tls(listener, router, &tls).await.unwrap() });
+            format!("https://localhost:{port}")
+        },
+    );
     TestApp {
         address,
         db_pool: connection_pool,
+        tracker: Tracker::from_settings(&configuration),
+        links: SubscriptionLinks::from_settings(&configuration),
+        emails,
     }
 }
 
 #[cfg(test)]
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     // Create database
-
-    use secrecy::{ExposeSecret, SecretBox};
-    let maintenance_settings = DatabaseSettings {
-        database_name: "postgres".to_string(),
-        username: "postgres".to_string(),
-        password: SecretBox::new(Box::new("password".to_string())),
-        port: config.port,
-        host: config.host.clone(),
-    };
-    let mut connection =
-        PgConnection::connect(maintenance_settings.connection_string().expose_secret())
-            .await
-            .expect("Failed to connect to Postgres");
+    let mut connection = PgConnection::connect_with(&config.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
     connection
         .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
         .await
         .expect("Failed to create database.");
 
     // Migrate database
-    let connection_pool = PgPool::connect(config.connection_string().expose_secret())
+    let mut connection = PgConnection::connect_with(&config.for_migrations())
         .await
-        .expect("Failed to connect to Postgres.");
+        .expect("Failed to connect to Postgres");
     sqlx::migrate!("./migrations")
-        .run(&connection_pool)
+        .run(&mut connection)
         .await
         .expect("Failed to migrate the database");
-    connection_pool
+    config
+        .pool_options()
+        .connect_with(config.with_db())
+        .await
+        .expect("Failed to connect to Postgres.")
 }
 
 #[track_caller]
@@ -0,0 +1,87 @@
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::api_keys::Scope;
+use z2p_axum::delivery::{self, DeliveryOutcome};
+
+mod common;
+
+#[sqlx::test]
+async fn delivery_report_counts_each_status(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let issue_id = Uuid::new_v4();
+    let sent = test_app.create_subscriber("sent@example.com").await;
+    let failed = test_app.create_subscriber("failed@example.com").await;
+    let bounced = test_app.create_subscriber("bounced@example.com").await;
+    let suppressed = test_app.create_subscriber("suppressed@example.com").await;
+    test_app.create_subscriber("queued@example.com").await;
+
+    let queued = delivery::start_send(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(queued, 5);
+    for (subscriber_id, outcome) in [
+        (sent, DeliveryOutcome::Sent),
+        (failed, DeliveryOutcome::Failed("timed out".to_string())),
+        (
+            failed,
+            DeliveryOutcome::Failed("connection refused".to_string()),
+        ),
+        (
+            bounced,
+            DeliveryOutcome::Bounced("mailbox full".to_string()),
+        ),
+        (suppressed, DeliveryOutcome::Suppressed),
+    ] {
+        delivery::record_outcome(&test_app.db_pool, issue_id, subscriber_id, &outcome)
+            .await
+            .unwrap();
+    }
+    delivery::finish_send(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/api/v1/admin/issues/{issue_id}/report",
+            &test_app.address
+        ))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesRead]).await)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(report["queued"], 1);
+    assert_eq!(report["sent"], 1);
+    assert_eq!(report["failed"], 1);
+    assert_eq!(report["bounced"], 1);
+    assert_eq!(report["suppressed"], 1);
+    assert!(report["finished_at"].is_string());
+    let failed_recipients = report["failed_recipients"].as_array().unwrap();
+    assert_eq!(failed_recipients.len(), 2);
+    let failed_recipient = failed_recipients
+        .iter()
+        .find(|r| r["email"] == "failed@example.com")
+        .unwrap();
+    assert_eq!(failed_recipient["last_error"], "connection refused");
+    assert_eq!(failed_recipient["attempts"], 2);
+}
+
+#[sqlx::test]
+async fn delivery_report_is_not_found_for_unsent_issue(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/api/v1/admin/issues/{}/report",
+            &test_app.address,
+            Uuid::new_v4()
+        ))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesRead]).await)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
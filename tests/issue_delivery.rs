@@ -0,0 +1,207 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use axum::{Json, Router, extract::State, http::HeaderMap, routing::post};
+use hyper::StatusCode;
+use secrecy::SecretBox;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::api_keys::Scope;
+use z2p_axum::configuration::EmailClientSettings;
+use z2p_axum::delivery::{self, DeliveryOutcome, ExecutionOutcome};
+use z2p_axum::email_client::EmailClient;
+use z2p_axum::subscription_links::LinkAction;
+
+mod common;
+
+/// Emails received by the stub API, which refuses those to `broken@`
+type Outbox = Arc<Mutex<Vec<(HeaderMap, serde_json::Value)>>>;
+
+async fn receive(
+    State(outbox): State<Outbox>,
+    headers: HeaderMap,
+    Json(email): Json<serde_json::Value>,
+) -> StatusCode {
+    let refused = email["To"] == "broken@example.com";
+    outbox.lock().unwrap().push((headers, email));
+    if refused {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    }
+}
+
+fn spawn_email_api() -> (EmailClient, Outbox) {
+    let outbox = Outbox::default();
+    let router = Router::new()
+        .route("/email", post(receive))
+        .with_state(Arc::clone(&outbox));
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let email_client = EmailClient::from_settings(&EmailClientSettings {
+        base_url: format!("http://{}", server.local_addr()),
+        sender_email: "newsletter@example.com".to_string(),
+        authorization_token: SecretBox::new(Box::new("email-token".to_string())),
+        timeout_milliseconds: 1000,
+    });
+    tokio::spawn(server);
+    (email_client, outbox)
+}
+
+#[sqlx::test]
+async fn published_issues_are_sent_and_their_outcome_recorded(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let (email_client, outbox) = spawn_email_api();
+    let subscriber_id = test_app.create_subscriber("ged@example.com").await;
+    test_app.create_subscriber("broken@example.com").await;
+
+    let published: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/issues", &test_app.address))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesPublish]).await)
+        .json(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let issue_id = common::expect_uuid(&published["issue_id"]);
+
+    for _ in 0..2 {
+        let outcome = delivery::deliver_next(
+            &test_app.db_pool,
+            &email_client,
+            &test_app.links,
+            &test_app.tracker,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, ExecutionOutcome::Delivered);
+    }
+    let outcome = delivery::deliver_next(
+        &test_app.db_pool,
+        &email_client,
+        &test_app.links,
+        &test_app.tracker,
+    )
+    .await
+    .unwrap();
+    assert_eq!(outcome, ExecutionOutcome::EmptyQueue);
+
+    let outbox = outbox.lock().unwrap().clone();
+    assert_eq!(outbox.len(), 2);
+    let (headers, email) = &outbox[0];
+    assert_eq!(headers["x-postmark-server-token"], "email-token");
+    assert_eq!(email["From"], "newsletter@example.com");
+    assert_eq!(email["To"], "ged@example.com");
+    assert_eq!(email["Subject"], "Issue #1");
+    let unsubscribe_url = test_app.links.url(LinkAction::Unsubscribe, subscriber_id);
+    assert_eq!(
+        email["TextBody"],
+        format!("Hello\n\nUnsubscribe: {unsubscribe_url}\n")
+    );
+    assert!(
+        common::expect_string(&email["HtmlBody"]).contains(&unsubscribe_url.replace('&', "&amp;"))
+    );
+    assert_eq!(
+        email["Headers"],
+        serde_json::json!([
+            {"Name": "List-Unsubscribe", "Value": format!("<{unsubscribe_url}>")},
+            {"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"},
+        ])
+    );
+
+    let statuses: Vec<(String, String, Option<String>)> = sqlx::query_as(
+        "select subscriber_email, status, last_error from issue_delivery where issue_id = $1 order by subscriber_email",
+    )
+    .bind(issue_id)
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(statuses[0].0, "broken@example.com");
+    assert_eq!(statuses[0].1, "failed");
+    assert!(statuses[0].2.as_deref().unwrap().contains("422"));
+    assert_eq!(
+        statuses[1],
+        ("ged@example.com".to_string(), "sent".to_string(), None)
+    );
+    let finished: bool =
+        sqlx::query_scalar("select finished_at is not null from issue_send where issue_id = $1")
+            .bind(issue_id)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert!(finished);
+}
+
+#[sqlx::test]
+async fn a_later_success_clears_the_last_error(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let issue_id = Uuid::new_v4();
+    let subscriber_id = test_app.create_subscriber("ged@example.com").await;
+    delivery::start_send(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+
+    for outcome in [
+        DeliveryOutcome::Failed("timed out".to_string()),
+        DeliveryOutcome::Sent,
+    ] {
+        delivery::record_outcome(&test_app.db_pool, issue_id, subscriber_id, &outcome)
+            .await
+            .unwrap();
+    }
+
+    let (status, last_error, attempts): (String, Option<String>, i32) = sqlx::query_as(
+        "select status, last_error, attempts from issue_delivery where issue_id = $1",
+    )
+    .bind(issue_id)
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "sent");
+    assert_eq!(last_error, None);
+    assert_eq!(attempts, 2);
+}
+
+#[sqlx::test]
+async fn sent_issues_track_the_links_of_each_recipient(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.tracking.enabled = true).await;
+    let (email_client, outbox) = spawn_email_api();
+    let subscriber_id = test_app.create_subscriber("ged@example.com").await;
+
+    let published: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/issues", &test_app.address))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesPublish]).await)
+        .json(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello",
+            "html_content": r#"<body><a href="https://example.com">Hello</a></body>"#,
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let issue_id = common::expect_uuid(&published["issue_id"]);
+    delivery::deliver_next(
+        &test_app.db_pool,
+        &email_client,
+        &test_app.links,
+        &test_app.tracker,
+    )
+    .await
+    .unwrap();
+
+    let outbox = outbox.lock().unwrap().clone();
+    let html = common::expect_string(&outbox[0].1["HtmlBody"]);
+    let click_url = test_app
+        .tracker
+        .click_url(issue_id, subscriber_id, "https://example.com/");
+    assert!(html.contains(&click_url.replace('&', "&amp;")), "{html}");
+    assert!(html.contains(&test_app.tracker.open_url(issue_id, subscriber_id)));
+}
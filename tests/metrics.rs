@@ -0,0 +1,63 @@
+use hyper::StatusCode;
+use sqlx::PgPool;
+use z2p_axum::api_keys::Scope;
+
+mod common;
+
+async fn scrape(test_app: &common::TestApp) -> String {
+    let response = reqwest::Client::new()
+        .get(format!("{}/metrics", &test_app.address))
+        .bearer_auth(test_app.api_key(&[Scope::MetricsRead]).await)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.text().await.unwrap()
+}
+
+#[sqlx::test]
+async fn metrics_report_requests_pool_and_subscriptions(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let client = reqwest::Client::new();
+    test_app.create_subscriber("ursula_le_guin@gmail.com").await;
+    client
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&serde_json::json!({"name": "Ursula", "email": "not-an-email"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let metrics = scrape(&test_app).await;
+
+    for expected in [
+        r#"http_requests_total{method="POST",path="/api/subscriber",status="201"}"#,
+        r#"http_request_duration_seconds_bucket{method="POST",path="/api/v1/subscriber",status="400",le="0.005"}"#,
+        "db_pool_connections ",
+        "db_pool_idle_connections ",
+        "db_pool_acquire_duration_seconds_count ",
+        "subscriptions_created_total ",
+        r#"subscription_validation_failures_total{reason="invalid_email"}"#,
+    ] {
+        assert!(
+            metrics.contains(expected),
+            "missing {expected} in:\n{metrics}"
+        );
+    }
+}
+
+#[sqlx::test]
+async fn metrics_need_a_key_with_the_scope(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let url = format!("{}/metrics", &test_app.address);
+
+    let response = reqwest::Client::new().get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(test_app.api_key(&[Scope::SubscribersRead]).await)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
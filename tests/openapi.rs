@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::configuration::get_configuration;
+use z2p_axum::startup::registered_paths;
+
+mod common;
+
+async fn fetch_spec(test_app: &common::TestApp) -> serde_json::Value {
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/openapi.json", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+/// Converts axum's `:param` segments into the `{param}` of `OpenAPI`
+fn to_openapi_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            segment
+                .strip_prefix(':')
+                .map_or_else(|| segment.to_string(), |name| format!("{{{name}}}"))
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[sqlx::test]
+async fn openapi_document_describes_the_subscriber_payloads(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let spec = fetch_spec(&test_app).await;
+
+    assert!(common::expect_string(&spec["openapi"]).starts_with("3.1"));
+    let schemas = &spec["components"]["schemas"];
+    assert!(schemas["CreateSubscriber"]["properties"]["email"].is_object());
+    assert!(schemas["Subscriber"]["properties"]["id"].is_object());
+}
+
+#[sqlx::test]
+async fn openapi_document_matches_the_registered_routes(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let spec = fetch_spec(&test_app).await;
+    let configuration = get_configuration().expect("Failed to read configuration.");
+
+    let documented: BTreeSet<String> = spec["paths"].as_object().unwrap().keys().cloned().collect();
+    let registered: BTreeSet<String> = registered_paths(&test_app.db_pool, &configuration)
+        .into_iter()
+        .map(|path| to_openapi_path(&path))
+        .collect();
+    assert_eq!(
+        documented, registered,
+        "The OpenAPI document and the router disagree on the available paths"
+    );
+
+    let client = reqwest::Client::new();
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+        let url = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    Uuid::new_v4().to_string()
+                } else {
+                    segment.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        for method in operations.as_object().unwrap().keys() {
+            let response = client
+                .request(
+                    method.to_uppercase().parse().unwrap(),
+                    format!("{}{url}", &test_app.address),
+                )
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            assert_ne!(
+                response.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {path} is documented but not routed"
+            );
+        }
+    }
+}
+
+#[sqlx::test]
+async fn docs_check_the_integrity_of_the_pinned_bundle(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.api_docs.redoc_integrity = Some("sha384-abc+/=".to_string());
+    })
+    .await;
+
+    let page = reqwest::get(format!("{}/api/docs", &test_app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(
+        page.contains(
+            r#"<script src="https://cdn.redoc.ly/redoc/v2.1.5/bundles/redoc.standalone.js" integrity="sha384-abc+/=" crossorigin="anonymous"></script>"#
+        ),
+        "{page}"
+    );
+}
@@ -0,0 +1,184 @@
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::subscription_links::LinkAction;
+
+mod common;
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+async fn submit(test_app: &common::TestApp, name: &str, email: &str) -> reqwest::Response {
+    client()
+        .post(format!("{}/subscribe", &test_app.address))
+        .form(&[("name", name), ("email", email), ("rendered_at", "0")])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn status(test_app: &common::TestApp, email: &str) -> String {
+    sqlx::query_scalar("select status from subscriber where email = $1")
+        .bind(email)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn subscribe_page_is_themed(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.pages.theme.site_name = "Earthsea Weekly".to_string();
+        c.pages.theme.primary_color = "#123456".to_string();
+    })
+    .await;
+
+    let response = client()
+        .get(format!("{}/subscribe", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().contains_key("content-security-policy"));
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<title>Subscribe · Earthsea Weekly</title>"));
+    assert!(html.contains("--primary: #123456"));
+    assert!(html.contains(r#"name="rendered_at""#));
+}
+
+#[sqlx::test]
+async fn invalid_fields_are_shown_inline(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = submit(&test_app, "Ursula \"the\" <b>", "not-an-email").await;
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"id="name-error""#));
+    assert!(html.contains(r#"id="email-error""#));
+    assert!(html.contains(r#"value="Ursula &quot;the&quot; &lt;b&gt;""#));
+    assert!(html.contains(r#"value="not-an-email""#));
+}
+
+#[sqlx::test]
+async fn duplicate_email_is_shown_inline(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    test_app.create_subscriber("ursula@example.com").await;
+
+    let response = submit(&test_app, "Ursula", "ursula@example.com").await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("This address is already subscribed"));
+    assert!(!html.contains(r#"id="name-error""#));
+}
+
+#[sqlx::test]
+async fn signup_is_pending_until_confirmed(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = submit(&test_app, "Ursula", "ursula@example.com").await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "/subscribe/check-inbox");
+    assert_eq!(status(&test_app, "ursula@example.com").await, "pending");
+
+    let subscriber_id: Uuid = sqlx::query_scalar("select id from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let forged = format!(
+        "{}/subscribe/confirm?subscriber_id={subscriber_id}&sig=00",
+        &test_app.address
+    );
+    let response = client().get(forged).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(status(&test_app, "ursula@example.com").await, "pending");
+
+    for _ in 0..2 {
+        let response = client()
+            .get(test_app.links.url(LinkAction::Confirm, subscriber_id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response
+                .text()
+                .await
+                .unwrap()
+                .contains("Subscription confirmed")
+        );
+    }
+    assert_eq!(status(&test_app, "ursula@example.com").await, "confirmed");
+}
+
+#[sqlx::test]
+async fn signups_are_emailed_their_confirmation_link(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.pages.theme.site_name = "Earthsea Weekly".to_string();
+    })
+    .await;
+
+    submit(&test_app, "Ursula", "ursula@example.com").await;
+
+    let subscriber_id: Uuid = sqlx::query_scalar("select id from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let confirmation_url = test_app.links.url(LinkAction::Confirm, subscriber_id);
+    let emails = test_app.emails.lock().unwrap().clone();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["To"], "ursula@example.com");
+    assert_eq!(
+        emails[0]["Subject"],
+        "Confirm your subscription to Earthsea Weekly"
+    );
+    assert!(common::expect_string(&emails[0]["TextBody"]).contains(&confirmation_url));
+    assert!(
+        common::expect_string(&emails[0]["HtmlBody"])
+            .contains(&confirmation_url.replace('&', "&amp;"))
+    );
+
+    let response = client().get(&confirmation_url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(status(&test_app, "ursula@example.com").await, "confirmed");
+}
+
+#[sqlx::test]
+async fn unsubscribing_asks_before_acting(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ursula@example.com").await;
+    let url = test_app.links.url(LinkAction::Unsubscribe, subscriber_id);
+
+    let response = client().get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains(r#"<form method="post">"#)
+    );
+    assert_eq!(status(&test_app, "ursula@example.com").await, "confirmed");
+
+    // As sent by mail clients implementing RFC 8058
+    let response = client()
+        .post(&url)
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        status(&test_app, "ursula@example.com").await,
+        "unsubscribed"
+    );
+
+    let confirm = test_app.links.url(LinkAction::Confirm, subscriber_id);
+    let response = client().get(confirm).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,271 @@
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::delivery;
+use z2p_axum::subscription_links::LinkAction;
+
+mod common;
+
+async fn save(
+    test_app: &common::TestApp,
+    subscriber_id: Uuid,
+    form: &[(&str, &str)],
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(
+            test_app
+                .links
+                .url(LinkAction::ManagePreferences, subscriber_id),
+        )
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[sqlx::test]
+async fn preferences_require_a_signed_link(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ursula@example.com").await;
+
+    let forged = format!(
+        "{}/preferences?subscriber_id={subscriber_id}&sig=00",
+        &test_app.address
+    );
+    let response = reqwest::get(forged).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Links for another action do not open the preference centre
+    let unsubscribe = test_app.links.url(LinkAction::Unsubscribe, subscriber_id);
+    let response = reqwest::get(unsubscribe.replace("/unsubscribe", "/preferences"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = reqwest::get(
+        test_app
+            .links
+            .url(LinkAction::ManagePreferences, subscriber_id),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"value="Joe B""#));
+    assert!(html.contains(r#"<option value="every_issue" selected>"#));
+    let unsubscribe = test_app.links.url(LinkAction::Unsubscribe, subscriber_id);
+    assert!(html.contains(&unsubscribe.replace('&', "&amp;")));
+}
+
+#[sqlx::test]
+async fn preferences_are_saved(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ursula@example.com").await;
+
+    let response = save(
+        &test_app,
+        subscriber_id,
+        &[
+            ("name", "Ursula"),
+            ("frequency", "weekly"),
+            ("pause_days", "30"),
+        ],
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Your preferences were saved."));
+    assert!(html.contains(r#"<option value="weekly" selected>"#));
+    assert!(html.contains("Paused until"));
+    let (name, frequency, paused_days): (String, String, Option<f64>) = sqlx::query_as(
+        "select name, frequency, extract(day from paused_until - now())::float8 from subscriber",
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(name, "Ursula");
+    assert_eq!(frequency, "weekly");
+    assert_eq!(paused_days, Some(29.0));
+
+    // An empty pause keeps the current one, 0 resumes delivery
+    save(
+        &test_app,
+        subscriber_id,
+        &[("name", "Ursula"), ("frequency", "weekly")],
+    )
+    .await;
+    let paused: bool = sqlx::query_scalar("select paused_until is not null from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(paused);
+    save(
+        &test_app,
+        subscriber_id,
+        &[
+            ("name", "Ursula"),
+            ("frequency", "weekly"),
+            ("pause_days", "0"),
+        ],
+    )
+    .await;
+    let paused: bool = sqlx::query_scalar("select paused_until is not null from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(!paused);
+}
+
+#[sqlx::test]
+async fn invalid_preferences_are_rejected(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ursula@example.com").await;
+
+    let response = save(
+        &test_app,
+        subscriber_id,
+        &[("name", "Ursula <b>"), ("frequency", "monthly")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"id="name-error""#));
+    assert!(html.contains(r#"value="Ursula &lt;b&gt;""#));
+    assert!(html.contains(r#"<option value="monthly" selected>"#));
+
+    for (form, error) in [
+        (
+            [
+                ("name", "Ursula"),
+                ("frequency", "daily"),
+                ("pause_days", ""),
+            ],
+            "Please choose how often you receive emails from the list",
+        ),
+        (
+            [
+                ("name", "Ursula"),
+                ("frequency", "weekly"),
+                ("pause_days", "366"),
+            ],
+            "Please choose how long to pause delivery from the list",
+        ),
+    ] {
+        let response = save(&test_app, subscriber_id, &form).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(
+            response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+        let html = response.text().await.unwrap();
+        assert!(
+            html.contains(&format!(r#"<p class="error" role="alert">{error}</p>"#)),
+            "{html}"
+        );
+        assert!(html.contains(r#"value="Ursula""#));
+    }
+
+    let (name, frequency): (String, String) =
+        sqlx::query_as("select name, frequency from subscriber")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(name, "Joe B");
+    assert_eq!(frequency, "every_issue");
+}
+
+#[sqlx::test]
+async fn unsubscribed_subscribers_cannot_change_preferences(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ursula@example.com").await;
+    reqwest::Client::new()
+        .post(test_app.links.url(LinkAction::Unsubscribe, subscriber_id))
+        .send()
+        .await
+        .unwrap();
+
+    let response = save(
+        &test_app,
+        subscriber_id,
+        &[("name", "Ursula"), ("frequency", "weekly")],
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("You are unsubscribed")
+    );
+    let name: String = sqlx::query_scalar("select name from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(name, "Joe B");
+}
+
+#[sqlx::test]
+async fn sends_skip_paused_subscribers_and_respect_frequency(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    test_app.create_subscriber("every@example.com").await;
+    let weekly = test_app.create_subscriber("weekly@example.com").await;
+    let paused = test_app.create_subscriber("paused@example.com").await;
+    save(
+        &test_app,
+        weekly,
+        &[("name", "Joe B"), ("frequency", "weekly")],
+    )
+    .await;
+    save(
+        &test_app,
+        paused,
+        &[
+            ("name", "Joe B"),
+            ("frequency", "every_issue"),
+            ("pause_days", "7"),
+        ],
+    )
+    .await;
+
+    let queued = delivery::start_send(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+    assert_eq!(queued, 2);
+
+    // Failed deliveries do not count towards the frequency
+    sqlx::query("update issue_delivery set status = 'failed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let queued = delivery::start_send(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+    assert_eq!(queued, 2);
+
+    // The weekly subscriber was already sent an issue this week
+    sqlx::query("update issue_delivery set status = 'sent'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let queued = delivery::start_send(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+    assert_eq!(queued, 1);
+
+    sqlx::query("update issue_send set started_at = now() - interval '8 days'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("update subscriber set paused_until = now() - interval '1 day'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let queued = delivery::start_send(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+    assert_eq!(queued, 3);
+}
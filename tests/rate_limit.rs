@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use hyper::StatusCode;
+use rstest::rstest;
+use sqlx::PgPool;
+use z2p_axum::configuration::RateLimitBackend;
+use z2p_axum::rate_limit::delete_expired_windows;
+
+mod common;
+
+async fn subscribe(test_app: &common::TestApp, email: &str) -> reqwest::Response {
+    let mut map = HashMap::new();
+    map.insert("email", email);
+    map.insert("name", "Joe B");
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&map)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[rstest]
+#[case(RateLimitBackend::Memory)]
+#[case(RateLimitBackend::Postgres)]
+#[sqlx::test]
+async fn subscribing_is_limited_per_client_ip(
+    #[case] backend: RateLimitBackend,
+    #[ignore] _db: PgPool,
+) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.backend = backend;
+        c.application.rate_limit.per_ip.requests = 2;
+    })
+    .await;
+
+    for i in 0..2 {
+        let response = subscribe(&test_app, &format!("user{i}@example.com")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let response = subscribe(&test_app, "user3@example.com").await;
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+}
+
+#[rstest]
+#[case(RateLimitBackend::Memory)]
+#[case(RateLimitBackend::Postgres)]
+#[sqlx::test]
+async fn subscribing_is_limited_per_email(
+    #[case] backend: RateLimitBackend,
+    #[ignore] _db: PgPool,
+) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.backend = backend;
+        c.application.rate_limit.per_email.requests = 1;
+    })
+    .await;
+
+    let response = subscribe(&test_app, "test@example.com").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = subscribe(&test_app, "TEST@example.com").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let response = subscribe(&test_app, "other@example.com").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[sqlx::test]
+async fn form_submissions_are_limited_per_email(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.per_email.requests = 1;
+        c.application.forms.error_url = "https://example.com/signup".to_string();
+    })
+    .await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let mut locations = Vec::new();
+    for email in ["test@example.com", "TEST@example.com"] {
+        let response = client
+            .post(format!("{}/api/v1/subscriber", &test_app.address))
+            .form(&[("name", "Joe B"), ("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        locations.push(response.headers()["location"].to_str().unwrap().to_string());
+    }
+
+    assert_eq!(
+        locations[1],
+        "https://example.com/signup?error=rate_limited"
+    );
+}
+
+#[sqlx::test]
+async fn hosted_signups_show_the_rate_limit(_db: PgPool) {
+    let test_app =
+        common::spawn_app_with(|c| c.application.rate_limit.per_email.requests = 1).await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    let mut responses = Vec::new();
+    for _ in 0..2 {
+        responses.push(
+            client
+                .post(format!("{}/subscribe", &test_app.address))
+                .form(&[("name", "Joe B"), ("email", "test@example.com")])
+                .send()
+                .await
+                .expect("Failed to execute request."),
+        );
+    }
+
+    assert_eq!(responses[1].status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        responses[1].headers()["location"],
+        "/subscribe?error=rate_limited"
+    );
+    let html = client
+        .get(format!(
+            "{}/subscribe?error=rate_limited",
+            &test_app.address
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Too many attempts, please try again later"));
+}
+
+#[sqlx::test]
+async fn clients_behind_a_trusted_proxy_are_limited_separately(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.per_ip.requests = 1;
+        c.application.rate_limit.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    let subscribe_from = |email: &'static str, forwarded_for: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriber", &test_app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .json(&serde_json::json!({"name": "Joe B", "email": email}))
+            .send()
+    };
+
+    let response = subscribe_from("a@example.com", "203.0.113.1")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    // Only the address added by the trusted proxy counts, not one the client made up
+    let response = subscribe_from("b@example.com", "198.51.100.7, 203.0.113.2")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = subscribe_from("c@example.com", "203.0.113.1")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[sqlx::test]
+async fn forwarded_for_is_ignored_from_untrusted_peers(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.application.rate_limit.per_ip.requests = 1).await;
+    let mut statuses = Vec::new();
+    for (email, forwarded_for) in [
+        ("a@example.com", "203.0.113.1"),
+        ("b@example.com", "203.0.113.2"),
+    ] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/v1/subscriber", &test_app.address))
+            .header("X-Forwarded-For", forwarded_for)
+            .json(&serde_json::json!({"name": "Joe B", "email": email}))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status());
+    }
+    assert_eq!(
+        statuses,
+        [StatusCode::CREATED, StatusCode::TOO_MANY_REQUESTS]
+    );
+}
+
+#[sqlx::test]
+async fn stored_windows_are_hashed_and_swept(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.backend = RateLimitBackend::Postgres;
+    })
+    .await;
+    let response = subscribe(&test_app, "test@example.com").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let keys: Vec<String> = sqlx::query_scalar("select key from rate_limit_bucket")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 2);
+    assert!(
+        keys.iter()
+            .all(|key| !key.contains("example.com") && !key.contains("127.0.0.1"))
+    );
+
+    assert_eq!(
+        delete_expired_windows(&test_app.db_pool, 3600)
+            .await
+            .unwrap(),
+        0
+    );
+    sqlx::query("update rate_limit_bucket set window_start = now() - interval '2 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        delete_expired_windows(&test_app.db_pool, 3600)
+            .await
+            .unwrap(),
+        2
+    );
+}
@@ -0,0 +1,129 @@
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+
+mod common;
+
+#[sqlx::test]
+async fn incoming_request_id_is_echoed(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/healthcheck", &test_app.address))
+        .header("X-Request-Id", "support-ticket-42")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-request-id"], "support-ticket-42");
+}
+
+#[sqlx::test]
+async fn missing_request_id_is_generated(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/healthcheck", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(Uuid::parse_str(request_id).is_ok(), "{request_id}");
+}
+
+#[sqlx::test]
+async fn error_bodies_include_the_request_id(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let client = reqwest::Client::new();
+
+    let invalid = client
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .header("X-Request-Id", "invalid-email")
+        .json(&serde_json::json!({"name": "Joe B", "email": "not-an-email"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let not_found = client
+        .get(format!("{}/nowhere", &test_app.address))
+        .header("X-Request-Id", "not-found")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    for (response, status, request_id, error) in [
+        (
+            invalid,
+            StatusCode::BAD_REQUEST,
+            "invalid-email",
+            "Validation Error",
+        ),
+        (not_found, StatusCode::NOT_FOUND, "not-found", "Not Found"),
+    ] {
+        assert_eq!(response.status(), status);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["request_id"], request_id);
+        assert_eq!(body["error"], error);
+    }
+}
+
+#[sqlx::test]
+async fn invalid_request_ids_are_replaced(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    for invalid in ["<script>", "a b", &"a".repeat(65)] {
+        let response = reqwest::Client::new()
+            .get(format!("{}/healthcheck", &test_app.address))
+            .header("X-Request-Id", invalid)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        let request_id = response.headers()["x-request-id"].to_str().unwrap();
+        assert!(Uuid::parse_str(request_id).is_ok(), "{request_id}");
+    }
+}
+
+#[sqlx::test]
+async fn browsers_get_the_request_id_in_their_own_format(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let client = reqwest::Client::new();
+    let accept = "text/html,application/xhtml+xml,*/*;q=0.8";
+
+    let response = client
+        .get(format!("{}/nowhere", &test_app.address))
+        .header("Accept", accept)
+        .header("X-Request-Id", "not-found")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Not Found\nRequest id: not-found\n"
+    );
+
+    let response = client
+        .get(format!(
+            "{}/unsubscribe?subscriber_id={}&sig=00",
+            &test_app.address,
+            Uuid::new_v4()
+        ))
+        .header("Accept", accept)
+        .header("X-Request-Id", "invalid-link")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let html = response.text().await.unwrap();
+    assert!(
+        html.contains(r#"<p class="request-id">Request id: <code>invalid-link</code></p>"#),
+        "{html}"
+    );
+}
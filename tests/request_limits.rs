@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::{Json, Router, routing::post};
+use hyper::StatusCode;
+use sqlx::PgPool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use z2p_axum::configuration::ChallengeProvider;
+
+mod common;
+
+/// Starts a challenge provider that takes `delay` to answer, so subscribing
+/// is slow
+fn spawn_slow_provider(delay: Duration) -> String {
+    let router = Router::new().route(
+        "/siteverify",
+        post(move || async move {
+            tokio::time::sleep(delay).await;
+            Json(serde_json::json!({ "success": true }))
+        }),
+    );
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let url = format!("http://{}/siteverify", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+async fn subscribe(test_app: &common::TestApp) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&serde_json::json!({
+            "name": "Joe B",
+            "email": "test@example.com",
+            "challenge_token": "token",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[sqlx::test]
+async fn oversized_body_is_rejected(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.application.limits.body_limit_bytes = 64).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&serde_json::json!({
+            "name": "Joe B".repeat(20),
+            "email": "test@example.com",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["request_id"].is_string(), "{body}");
+}
+
+#[sqlx::test]
+async fn oversized_forms_are_redirected(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.limits.body_limit_bytes = 64;
+        c.application.forms.error_url = "https://example.com/signup".to_string();
+    })
+    .await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let name = "Joe B".repeat(20);
+    let form = [("name", name.as_str()), ("email", "test@example.com")];
+
+    for (path, location) in [
+        (
+            "/api/v1/subscriber",
+            "https://example.com/signup?error=too_large",
+        ),
+        ("/subscribe", "/subscribe?error=too_large"),
+    ] {
+        let response = client
+            .post(format!("{}{path}", &test_app.address))
+            .form(&form)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status(), StatusCode::SEE_OTHER, "{path}");
+        assert_eq!(response.headers()["location"], location);
+    }
+}
+
+#[sqlx::test]
+async fn slow_forms_are_redirected(_db: PgPool) {
+    let test_app =
+        common::spawn_app_with(|c| c.application.limits.body_timeout_milliseconds = 200).await;
+    let address = test_app.address.trim_start_matches("http://");
+
+    let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "POST /subscribe HTTP/1.1\r\nHost: {address}\r\n\
+                 Content-Type: application/x-www-form-urlencoded\r\nContent-Length: 64\r\n\r\nname="
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response))
+        .await
+        .expect("The server did not time out")
+        .unwrap();
+
+    let response = String::from_utf8_lossy(&response[..read]);
+    assert!(
+        response.starts_with("HTTP/1.1 303"),
+        "unexpected response {response}"
+    );
+    assert!(response.contains("location: /subscribe?error=timed_out"));
+}
+
+#[sqlx::test]
+async fn slow_body_times_out_with_408(_db: PgPool) {
+    let test_app =
+        common::spawn_app_with(|c| c.application.limits.body_timeout_milliseconds = 200).await;
+    let address = test_app.address.trim_start_matches("http://");
+
+    // Announce a body and never send it
+    let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "POST /api/v1/subscriber HTTP/1.1\r\nHost: {address}\r\n\
+                 Content-Type: application/json\r\nContent-Length: 64\r\n\r\n{{"
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response))
+        .await
+        .expect("The server did not time out")
+        .unwrap();
+
+    let response = String::from_utf8_lossy(&response[..read]);
+    assert!(
+        response.starts_with("HTTP/1.1 408"),
+        "unexpected response {response}"
+    );
+}
+
+#[sqlx::test]
+async fn slow_handler_times_out_with_503(_db: PgPool) {
+    let verify_url = spawn_slow_provider(Duration::from_secs(2));
+    let test_app = common::spawn_app_with(|c| {
+        c.application.limits.request_timeout_milliseconds = 200;
+        c.bot_protection.challenge.provider = ChallengeProvider::Turnstile;
+        c.bot_protection.challenge.verify_url = Some(verify_url);
+    })
+    .await;
+
+    let response = subscribe(&test_app).await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "Request timed out");
+}
+
+#[sqlx::test]
+async fn requests_over_the_concurrency_limit_are_shed(_db: PgPool) {
+    let verify_url = spawn_slow_provider(Duration::from_millis(500));
+    let test_app = common::spawn_app_with(|c| {
+        c.application.limits.max_concurrent_requests = 1;
+        c.bot_protection.challenge.provider = ChallengeProvider::Turnstile;
+        c.bot_protection.challenge.verify_url = Some(verify_url);
+    })
+    .await;
+
+    let in_flight = subscribe(&test_app);
+    let shed = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        reqwest::get(format!("{}/healthcheck", &test_app.address))
+            .await
+            .expect("Failed to execute request.")
+    };
+    let (in_flight, shed) = tokio::join!(in_flight, shed);
+
+    assert_eq!(in_flight.status(), StatusCode::CREATED);
+    assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(shed.headers()["retry-after"], "1");
+}
+
+#[sqlx::test]
+async fn responses_are_compressed_when_accepted(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.application.compression.br = false).await;
+    let client = reqwest::Client::new();
+
+    for (accepted, expected) in [
+        ("gzip", Some("gzip")),
+        ("zstd", Some("zstd")),
+        ("br", None),
+        ("identity", None),
+    ] {
+        let response = client
+            .get(format!("{}/api/openapi.json", &test_app.address))
+            .header("Accept-Encoding", accepted)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get("content-encoding")
+                .map(|value| value.to_str().unwrap()),
+            expected,
+            "accepting {accepted}"
+        );
+    }
+}
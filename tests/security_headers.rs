@@ -0,0 +1,118 @@
+use hyper::StatusCode;
+use sqlx::PgPool;
+
+mod common;
+
+const WIDGET_ORIGIN: &str = "https://widget.example.com";
+
+#[sqlx::test]
+async fn preflight_from_an_allowed_origin_is_accepted(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.cors.allowed_origins = vec![WIDGET_ORIGIN.to_string()];
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/api/v1/subscriber", &test_app.address),
+        )
+        .header("Origin", WIDGET_ORIGIN)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "content-type")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], WIDGET_ORIGIN);
+    assert!(
+        headers["access-control-allow-methods"]
+            .to_str()
+            .unwrap()
+            .contains("POST")
+    );
+    assert!(
+        headers["access-control-allow-headers"]
+            .to_str()
+            .unwrap()
+            .contains("content-type")
+    );
+}
+
+#[sqlx::test]
+async fn other_origins_get_no_cors_headers(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.cors.allowed_origins = vec![WIDGET_ORIGIN.to_string()];
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/healthcheck", &test_app.address))
+        .header("Origin", "https://evil.example.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response
+            .headers()
+            .get("access-control-allow-origin")
+            .is_none()
+    );
+}
+
+#[sqlx::test]
+async fn responses_carry_the_security_headers(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.security_headers.hsts_max_age_seconds = 600;
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let json = client
+        .get(format!("{}/api/openapi.json", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let headers = json.headers();
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(
+        headers["referrer-policy"],
+        "strict-origin-when-cross-origin"
+    );
+    assert_eq!(
+        headers["strict-transport-security"],
+        "max-age=600; includeSubDomains"
+    );
+    assert!(headers.get("content-security-policy").is_none());
+
+    let html = client
+        .get(format!("{}/api/docs", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let csp = html.headers()["content-security-policy"].to_str().unwrap();
+    assert!(csp.contains("frame-ancestors 'none'"), "{csp}");
+}
+
+#[sqlx::test]
+async fn hsts_is_not_sent_by_default(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/healthcheck", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(
+        response
+            .headers()
+            .get("strict-transport-security")
+            .is_none()
+    );
+    assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use hyper::StatusCode;
 use rstest::rstest;
 use sqlx::PgPool;
+use z2p_axum::api_keys::Scope;
 
 mod common;
 
@@ -34,6 +35,41 @@ async fn create_subscriber_works(_db: PgPool) -> sqlx::Result<()> {
     Ok(())
 }
 
+#[sqlx::test]
+async fn json_signups_receive_no_issue_until_confirmed(_db: PgPool) -> sqlx::Result<()> {
+    let test_app = common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&serde_json::json!({"name": "Joe B", "email": "test@example.com"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let status: String = sqlx::query_scalar("select status from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await?;
+    assert_eq!(status, "pending");
+
+    let published: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/issues", &test_app.address))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesPublish]).await)
+        .json(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(published["queued"], 0);
+
+    Ok(())
+}
+
 #[sqlx::test]
 async fn create_subscriber_fails_when_data_is_missing(_db: PgPool) -> sqlx::Result<()> {
     let test_app = common::spawn_app().await;
@@ -74,7 +110,10 @@ async fn create_subsciber_fails_duplicate_email(_db: PgPool) -> sqlx::Result<()>
     assert_eq!(resp_json["name"], "Joe B");
     assert_eq!(resp_json["email"], "test@example.com");
 
-    common::expect_uuid(&resp_json["id"]);
+    // Signing up again only resends the link until the address is confirmed
+    test_app
+        .confirm(common::expect_uuid(&resp_json["id"]))
+        .await;
     let response = client
         .post(format!("{}/api/subscriber", &test_app.address))
         .json(&map)
@@ -85,6 +124,26 @@ async fn create_subsciber_fails_duplicate_email(_db: PgPool) -> sqlx::Result<()>
     Ok(())
 }
 
+#[sqlx::test]
+async fn database_errors_are_not_exposed(_db: PgPool) -> sqlx::Result<()> {
+    let test_app = common::spawn_app().await;
+    sqlx::query("alter table subscriber drop column name")
+        .execute(&test_app.db_pool)
+        .await?;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .json(&serde_json::json!({"name": "Joe B", "email": "test@example.com"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("column"), "database error exposed: {body}");
+    Ok(())
+}
+
 #[rstest]
 #[case("", "ursula_le_guin%40gmail.com", "empty name")]
 #[case("Ursula", "", "empty email")]
@@ -116,3 +175,68 @@ async fn subscribe_returns_a_200_when_fields_are_present_but_empty(
         "The API did not return a 400 when the payload was {error_description}."
     );
 }
+
+fn without_redirects() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn form_submission_redirects_to_the_success_page(_db: PgPool) -> sqlx::Result<()> {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.forms.success_url = "https://example.com/thanks".to_string();
+    })
+    .await;
+
+    let response = without_redirects()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .form(&[("name", "Joe B"), ("email", "test@example.com")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "https://example.com/thanks");
+    let (email, status): (String, String) = sqlx::query_as("select email, status from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await?;
+    assert_eq!(email, "test@example.com");
+    // Like the hosted page, the address is confirmed by link
+    assert_eq!(status, "pending");
+
+    Ok(())
+}
+
+#[rstest]
+#[case::invalid_email(&[("name", "Joe B"), ("email", "not-an-email")], "invalid_email")]
+#[case::missing_field(&[("name", "Joe B")], "malformed")]
+#[case::honeypot(
+    &[("name", "Joe B"), ("email", "test@example.com"), ("website", "https://spam.example.com")],
+    "rejected"
+)]
+#[sqlx::test]
+async fn failed_form_submission_redirects_with_the_reason(
+    #[case] form: &[(&str, &str)],
+    #[case] reason: &str,
+    #[ignore] _db: PgPool,
+) {
+    let test_app = common::spawn_app_with(|c| {
+        c.application.forms.error_url = "https://example.com/signup?lang=en".to_string();
+    })
+    .await;
+
+    let response = without_redirects()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        response.headers()["location"],
+        format!("https://example.com/signup?lang=en&error={reason}").as_str()
+    );
+}
@@ -0,0 +1,96 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{Router, extract::State, http::HeaderMap, http::Uri, routing::post};
+use hyper::StatusCode;
+use secrecy::SecretBox;
+use sqlx::PgPool;
+use z2p_axum::configuration::{OtlpProtocol, WebhookEndpointSettings};
+
+mod common;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+type Requests = Arc<Mutex<Vec<(String, HeaderMap, String)>>>;
+
+async fn receive(
+    State(requests): State<Requests>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    requests
+        .lock()
+        .unwrap()
+        .push((uri.path().to_string(), headers, body));
+    StatusCode::OK
+}
+
+/// Starts a stub serving both as the OTLP collector and as a webhook endpoint
+fn spawn_collector() -> (String, Requests) {
+    let requests = Requests::default();
+    let router = Router::new()
+        .route("/v1/traces", post(receive))
+        .route("/hook", post(receive))
+        .with_state(requests.clone());
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let address = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    (address, requests)
+}
+
+async fn wait_for(requests: &Requests, path: &str) -> Vec<(HeaderMap, String)> {
+    for _ in 0..250 {
+        let received: Vec<_> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(p, _, _)| p == path)
+            .map(|(_, headers, body)| (headers.clone(), body.clone()))
+            .collect();
+        if !received.is_empty() {
+            return received;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Nothing was sent to {path}");
+}
+
+#[sqlx::test]
+async fn incoming_trace_context_is_exported_and_propagated(_db: PgPool) {
+    let (address, requests) = spawn_collector();
+    let test_app = common::spawn_app_with(|c| {
+        c.telemetry.otlp_endpoint = Some(format!("{address}/v1/traces"));
+        c.telemetry.otlp_protocol = OtlpProtocol::HttpJson;
+        c.telemetry.export_delay_milliseconds = 50;
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url: format!("{address}/hook"),
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![],
+        });
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriber", &test_app.address))
+        .header("traceparent", format!("00-{TRACE_ID}-00f067aa0ba902b7-01"))
+        .json(&serde_json::json!({"name": "Joe B", "email": "test@example.com"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let (headers, _) = wait_for(&requests, "/hook").await.remove(0);
+    let traceparent = headers["traceparent"].to_str().unwrap();
+    assert!(
+        traceparent.starts_with(&format!("00-{TRACE_ID}-")),
+        "webhook sent with {traceparent}"
+    );
+    let exported = wait_for(&requests, "/v1/traces").await;
+    assert!(
+        exported.iter().any(|(_, body)| body.contains(TRACE_ID)),
+        "no exported span belongs to the incoming trace"
+    );
+}
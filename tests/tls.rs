@@ -0,0 +1,109 @@
+use std::path::Path;
+use std::time::Duration;
+
+use hyper::StatusCode;
+use sqlx::PgPool;
+use tempfile::TempDir;
+use z2p_axum::configuration::TlsSettings;
+
+mod common;
+
+/// Writes a new self-signed certificate for `localhost` and returns it
+fn write_certificate(directory: &Path) -> reqwest::Certificate {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(directory.join("cert.pem"), certified.cert.pem()).unwrap();
+    std::fs::write(
+        directory.join("key.pem"),
+        certified.key_pair.serialize_pem(),
+    )
+    .unwrap();
+    reqwest::Certificate::from_pem(certified.cert.pem().as_bytes()).unwrap()
+}
+
+fn client(root: reqwest::Certificate) -> reqwest::Client {
+    reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(root)
+        .build()
+        .unwrap()
+}
+
+async fn spawn_tls_app(directory: &Path) -> common::TestApp {
+    let tls = TlsSettings {
+        cert_path: directory.join("cert.pem"),
+        key_path: directory.join("key.pem"),
+        reload_interval_milliseconds: 50,
+    };
+    common::spawn_app_with(|c| c.application.tls = Some(tls)).await
+}
+
+#[sqlx::test]
+async fn https_negotiates_http2(_db: PgPool) {
+    let directory = TempDir::new().unwrap();
+    let root = write_certificate(directory.path());
+    let test_app = spawn_tls_app(directory.path()).await;
+
+    let response = client(root)
+        .get(format!("{}/healthcheck", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(test_app.address.starts_with("https://"));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), reqwest::Version::HTTP_2);
+}
+
+#[sqlx::test]
+async fn http1_clients_are_still_served(_db: PgPool) {
+    let directory = TempDir::new().unwrap();
+    let root = write_certificate(directory.path());
+    let test_app = spawn_tls_app(directory.path()).await;
+
+    let response = reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(root)
+        .http1_only()
+        .build()
+        .unwrap()
+        .get(format!("{}/healthcheck", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), reqwest::Version::HTTP_11);
+}
+
+#[sqlx::test]
+async fn certificate_is_reloaded_when_the_files_change(_db: PgPool) {
+    let directory = TempDir::new().unwrap();
+    write_certificate(directory.path());
+    let test_app = spawn_tls_app(directory.path()).await;
+
+    // Some filesystems only keep modification times to the second
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let renewed = client(write_certificate(directory.path()));
+
+    let mut attempts = 0;
+    loop {
+        match renewed
+            .get(format!("{}/healthcheck", &test_app.address))
+            .send()
+            .await
+        {
+            Ok(response) => {
+                assert_eq!(response.status(), StatusCode::OK);
+                break;
+            }
+            Err(e) if attempts < 50 => {
+                attempts += 1;
+                assert!(e.is_connect(), "{e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => panic!("the renewed certificate was not served: {e}"),
+        }
+    }
+}
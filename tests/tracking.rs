@@ -0,0 +1,142 @@
+use hyper::StatusCode;
+use sqlx::{PgPool, types::Uuid};
+use z2p_axum::api_keys::Scope;
+use z2p_axum::delivery::{self, DeliveryOutcome};
+
+mod common;
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn opens_and_clicks_are_reported_per_issue(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.tracking.enabled = true).await;
+    let issue_id = Uuid::new_v4();
+    let first = test_app.create_subscriber("first@example.com").await;
+    let second = test_app.create_subscriber("second@example.com").await;
+    delivery::start_send(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    for subscriber_id in [first, second] {
+        delivery::record_outcome(
+            &test_app.db_pool,
+            issue_id,
+            subscriber_id,
+            &DeliveryOutcome::Sent,
+        )
+        .await
+        .unwrap();
+    }
+    let client = client();
+
+    for subscriber_id in [first, first, second] {
+        let response = client
+            .get(test_app.tracker.open_url(issue_id, subscriber_id))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/gif");
+    }
+    let response = client
+        .get(
+            test_app
+                .tracker
+                .click_url(issue_id, first, "https://example.com/a?b=c"),
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()["location"], "https://example.com/a?b=c");
+
+    let report: serde_json::Value = client
+        .get(format!(
+            "{}/api/v1/admin/issues/{issue_id}/engagement",
+            &test_app.address
+        ))
+        .bearer_auth(test_app.api_key(&[Scope::IssuesRead]).await)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["recipients"], 2);
+    assert_eq!(report["unique_opens"], 2);
+    assert_eq!(report["total_opens"], 3);
+    assert_eq!(report["unique_clicks"], 1);
+    assert_eq!(report["open_rate"], 1.0);
+    assert_eq!(report["click_rate"], 0.5);
+    assert_eq!(report["links"][0]["url"], "https://example.com/a?b=c");
+    assert_eq!(report["links"][0]["clicks"], 1);
+}
+
+#[sqlx::test]
+async fn clicks_with_a_tampered_target_are_rejected(_db: PgPool) {
+    let test_app = common::spawn_app_with(|c| c.tracking.enabled = true).await;
+    let subscriber_id = test_app.create_subscriber("first@example.com").await;
+    let url = test_app
+        .tracker
+        .click_url(Uuid::new_v4(), subscriber_id, "https://example.com")
+        .replace("example.com", "evil.com");
+
+    let response = client()
+        .get(url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn nothing_is_recorded_when_tracking_is_disabled(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("first@example.com").await;
+    let issue_id = Uuid::new_v4();
+
+    let response = client()
+        .get(test_app.tracker.open_url(issue_id, subscriber_id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let events = sqlx::query_scalar::<_, i64>("select count(*) from engagement_event")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events, 0);
+}
+
+#[sqlx::test]
+async fn events_move_to_the_partition_of_their_month(_db: PgPool) {
+    let test_app = common::spawn_app().await;
+    let subscriber_id = test_app.create_subscriber("ged@example.com").await;
+    sqlx::query(
+        "insert into engagement_event (issue_id, subscriber_id, kind, occurred_at) values ($1, $2, 'open', '2100-01-15')",
+    )
+    .bind(Uuid::new_v4())
+    .bind(subscriber_id)
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    sqlx::query("select create_engagement_event_partition('2100-01-01')")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let partitions: Vec<String> = sqlx::query_scalar(
+        "select tableoid::regclass::text from engagement_event where occurred_at = '2100-01-15'",
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(partitions, ["engagement_event_2100_01"]);
+}
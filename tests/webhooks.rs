@@ -0,0 +1,250 @@
+use std::net::SocketAddr;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::Duration;
+
+use axum::{Router, extract::State, http::HeaderMap, routing::post};
+use hmac::{Hmac, Mac};
+use hyper::StatusCode;
+use secrecy::SecretBox;
+use sha2::Sha256;
+use sqlx::PgPool;
+use z2p_axum::configuration::WebhookEndpointSettings;
+use z2p_axum::subscription_links::LinkAction;
+use z2p_axum::webhooks::{SIGNATURE_HEADER, SubscriberEvent};
+
+mod common;
+
+#[derive(Clone, Default)]
+struct Receiver {
+    requests: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    failures_left: Arc<AtomicUsize>,
+}
+
+async fn receive(State(receiver): State<Receiver>, headers: HeaderMap, body: String) -> StatusCode {
+    receiver.requests.lock().unwrap().push((headers, body));
+    let failing = receiver
+        .failures_left
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok();
+    if failing {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::NO_CONTENT
+    }
+}
+
+/// Starts a stub endpoint that fails the first `failures` requests
+fn spawn_receiver(failures: usize) -> (String, Receiver) {
+    let receiver = Receiver {
+        failures_left: Arc::new(AtomicUsize::new(failures)),
+        ..Receiver::default()
+    };
+    let router = Router::new()
+        .route("/hook", post(receive))
+        .with_state(receiver.clone());
+    let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+        .serve(router.into_make_service());
+    let url = format!("http://{}/hook", server.local_addr());
+    tokio::spawn(server);
+    (url, receiver)
+}
+
+async fn wait_for_requests(receiver: &Receiver, count: usize) -> Vec<(HeaderMap, String)> {
+    for _ in 0..100 {
+        if receiver.requests.lock().unwrap().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    receiver.requests.lock().unwrap().clone()
+}
+
+#[sqlx::test]
+async fn created_subscriber_is_delivered_with_a_valid_signature(_db: PgPool) {
+    let (url, receiver) = spawn_receiver(1);
+    let test_app = common::spawn_app_with(|c| {
+        c.webhooks.retry_base_delay_milliseconds = 10;
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url,
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![SubscriberEvent::Created],
+        });
+    })
+    .await;
+
+    let subscriber_id = test_app.create_subscriber("test@example.com").await;
+
+    let requests = wait_for_requests(&receiver, 2).await;
+    assert_eq!(requests.len(), 2, "The failed delivery was not retried");
+    let (headers, body) = &requests[1];
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"webhook-secret").unwrap();
+    mac.update(body.as_bytes());
+    let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(headers[SIGNATURE_HEADER], expected.as_str());
+
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["type"], "subscriber.created");
+    assert_eq!(common::expect_uuid(&payload["data"]["id"]), subscriber_id);
+    assert_eq!(payload["data"]["email"], "test@example.com");
+
+    let mut delivery = None;
+    for _ in 0..50 {
+        delivery = sqlx::query_as::<_, (String, i32, Option<i32>)>(
+            "select status, attempts, last_status_code from webhook_delivery",
+        )
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .unwrap()
+        .filter(|(status, _, _)| status == "delivered");
+        if delivery.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(delivery, Some(("delivered".to_string(), 2, Some(204))));
+}
+
+#[sqlx::test]
+async fn endpoints_only_receive_the_events_they_subscribe_to(_db: PgPool) {
+    let (url, receiver) = spawn_receiver(0);
+    let test_app = common::spawn_app_with(|c| {
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url,
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![SubscriberEvent::Unsubscribed],
+        });
+    })
+    .await;
+
+    test_app.create_subscriber("test@example.com").await;
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(receiver.requests.lock().unwrap().is_empty());
+}
+
+#[sqlx::test]
+async fn hosted_signups_carry_the_confirmation_link(_db: PgPool) {
+    let (url, receiver) = spawn_receiver(0);
+    let test_app = common::spawn_app_with(|c| {
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url,
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![SubscriberEvent::Created, SubscriberEvent::Confirmed],
+        });
+    })
+    .await;
+
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(format!("{}/subscribe", &test_app.address))
+        .form(&[("name", "Joe B"), ("email", "test@example.com")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let requests = wait_for_requests(&receiver, 1).await;
+    let created: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+    assert_eq!(created["type"], "subscriber.created");
+    let confirmation_url = common::expect_string(&created["data"]["confirmation_url"]);
+
+    let response = reqwest::get(confirmation_url).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let requests = wait_for_requests(&receiver, 2).await;
+    let confirmed: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+    assert_eq!(confirmed["type"], "subscriber.confirmed");
+    assert_eq!(confirmed["data"]["email"], "test@example.com");
+}
+
+#[sqlx::test]
+async fn signing_up_again_resends_the_confirmation_link(_db: PgPool) {
+    let (url, receiver) = spawn_receiver(0);
+    let test_app = common::spawn_app_with(|c| {
+        c.application.rate_limit.per_email.requests = 10;
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url,
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![SubscriberEvent::Created],
+        });
+    })
+    .await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let sign_up = || {
+        client
+            .post(format!("{}/subscribe", &test_app.address))
+            .form(&[("name", "Joe B"), ("email", "test@example.com")])
+            .send()
+    };
+
+    // Still pending
+    for _ in 0..2 {
+        let response = sign_up().await.unwrap();
+        assert_eq!(response.headers()["location"], "/subscribe/check-inbox");
+    }
+    let requests = wait_for_requests(&receiver, 2).await;
+    assert_eq!(requests.len(), 2);
+    let created: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+    let confirmation_url = common::expect_string(&created["data"]["confirmation_url"]);
+    assert_eq!(
+        reqwest::get(confirmation_url).await.unwrap().status(),
+        StatusCode::OK
+    );
+
+    // Confirmed addresses are refused, unsubscribed ones sign up again
+    let response = sign_up().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let subscriber_id = common::expect_uuid(&created["data"]["id"]);
+    client
+        .post(test_app.links.url(LinkAction::Unsubscribe, subscriber_id))
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+    let response = sign_up().await.unwrap();
+    assert_eq!(response.headers()["location"], "/subscribe/check-inbox");
+    let requests = wait_for_requests(&receiver, 3).await;
+    let created: serde_json::Value = serde_json::from_str(&requests[2].1).unwrap();
+    assert_eq!(created["data"]["id"], subscriber_id.to_string());
+    assert!(created["data"]["confirmation_url"].is_string());
+    let status: String = sqlx::query_scalar("select status from subscriber")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "pending");
+}
+
+#[sqlx::test]
+async fn deliveries_left_pending_by_a_previous_process_are_sent(_db: PgPool) {
+    let (url, receiver) = spawn_receiver(0);
+    let endpoint_url = url.clone();
+    let test_app = common::spawn_app_with(|c| {
+        c.webhooks.endpoints.push(WebhookEndpointSettings {
+            url,
+            secret: SecretBox::new(Box::new("webhook-secret".to_string())),
+            events: vec![],
+        });
+    })
+    .await;
+
+    // Retried once already before the previous process stopped
+    sqlx::query(
+        r#"
+        insert into webhook_delivery (event_id, event_type, endpoint_url, payload, attempts)
+        values (gen_random_uuid(), 'subscriber.created', $1, '{"type": "subscriber.created"}', 1)
+        "#,
+    )
+    .bind(&endpoint_url)
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    let requests = wait_for_requests(&receiver, 1).await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1, r#"{"type":"subscriber.created"}"#);
+}